@@ -0,0 +1,32 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // Compile the Wren VM for `testing::WrenHost`.
+    #[cfg(feature = "wren-host")]
+    {
+        println!("cargo:rerun-if-changed=wren");
+        cc::Build::new()
+            .files(
+                [
+                    "wren_compiler.c",
+                    "wren_core.c",
+                    "wren_debug.c",
+                    "wren_primitive.c",
+                    "wren_utils.c",
+                    "wren_value.c",
+                    "wren_vm.c",
+                ]
+                .iter()
+                .map(|file| format!("wren/vm/{}", file)),
+            )
+            .include("wren/include")
+            .include("wren/vm")
+            .define("WREN_OPT_META", "0")
+            .define("WREN_OPT_RANDOM", "0")
+            // Wren checks the uses of its API with assertions.
+            .define("DEBUG", None)
+            .std("c99")
+            // The sources are vendored as-is.
+            .warnings(false)
+            .compile("wren");
+    }
+}
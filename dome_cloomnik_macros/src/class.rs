@@ -0,0 +1,560 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote, quote_spanned};
+use syn::parse::Parser;
+use syn::spanned::Spanned;
+use syn::{Error, FnArg, Ident, ImplItem, ImplItemFn, ItemImpl, LitStr, Pat, Result, Type};
+
+struct ClassArgs {
+    module: LitStr,
+    name: Option<LitStr>,
+    superclass: Option<LitStr>,
+    source: Option<LitStr>,
+}
+
+impl ClassArgs {
+    fn parse(args: TokenStream) -> Result<Self> {
+        let mut module = None;
+        let mut name = None;
+        let mut superclass = None;
+        let mut source = None;
+        let parser = syn::meta::parser(|meta| {
+            let target = if meta.path.is_ident("module") {
+                &mut module
+            } else if meta.path.is_ident("name") {
+                &mut name
+            } else if meta.path.is_ident("is") {
+                &mut superclass
+            } else if meta.path.is_ident("source") {
+                &mut source
+            } else {
+                return Err(meta.error("expected `module`, `name`, `is` or `source`"));
+            };
+            *target = Some(meta.value()?.parse()?);
+            Ok(())
+        });
+        parser.parse2(args.clone())?;
+        let module = module
+            .ok_or_else(|| Error::new_spanned(&args, "missing `module = \"...\"` argument"))?;
+        Ok(Self {
+            module,
+            name,
+            superclass,
+            source,
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Constructor,
+    Method,
+    Getter,
+    Setter,
+}
+
+enum Param {
+    Vm {
+        mutable: bool,
+    },
+    Arg {
+        wren_name: String,
+        ty: Box<Type>,
+    },
+    /// A reference to a Rust foreign object, borrowed from its slot.
+    Foreign {
+        wren_name: String,
+        ty: Box<Type>,
+        mutable: bool,
+    },
+}
+
+struct ForeignMethod {
+    rust_name: Ident,
+    wren_name: String,
+    kind: Kind,
+    /// Whether this is an instance method, and if so, whether it takes `&mut self`.
+    receiver: Option<bool>,
+    params: Vec<Param>,
+}
+
+impl ForeignMethod {
+    /// Parses `method` if it has a `#[foreign]` attribute, and strips the attribute.
+    fn parse(method: &mut ImplItemFn) -> Result<Option<Self>> {
+        let position = match method
+            .attrs
+            .iter()
+            .position(|attr| attr.path().is_ident("foreign"))
+        {
+            Some(position) => position,
+            None => return Ok(None),
+        };
+        let attr = method.attrs.remove(position);
+
+        let mut kind = Kind::Method;
+        let mut wren_name = None;
+        if !matches!(attr.meta, syn::Meta::Path(_)) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("construct") {
+                    kind = Kind::Constructor;
+                } else if meta.path.is_ident("getter") {
+                    kind = Kind::Getter;
+                } else if meta.path.is_ident("setter") {
+                    kind = Kind::Setter;
+                } else if meta.path.is_ident("name") {
+                    wren_name = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else {
+                    return Err(
+                        meta.error("expected `construct`, `getter`, `setter` or `name = \"...\"`")
+                    );
+                }
+                Ok(())
+            })?;
+        }
+
+        let sig = &method.sig;
+        if !sig.generics.params.is_empty() {
+            return Err(Error::new_spanned(
+                &sig.generics,
+                "foreign methods cannot be generic",
+            ));
+        }
+        let mut receiver = None;
+        let mut params = Vec::new();
+        for (index, input) in sig.inputs.iter().enumerate() {
+            match input {
+                FnArg::Receiver(input) => {
+                    if input.reference.is_none() {
+                        return Err(Error::new_spanned(
+                            input,
+                            "foreign methods must take `self` by reference",
+                        ));
+                    }
+                    receiver = Some(input.mutability.is_some());
+                }
+                FnArg::Typed(input) => {
+                    params.push(match &*input.ty {
+                        Type::Reference(reference) if is_vm(&reference.elem) => Param::Vm {
+                            mutable: reference.mutability.is_some(),
+                        },
+                        Type::Reference(reference) if is_str_or_bytes(&reference.elem) => {
+                            if reference.mutability.is_some() {
+                                return Err(Error::new_spanned(
+                                    &input.ty,
+                                    "strings and bytes can only be borrowed immutably",
+                                ));
+                            }
+                            Param::Arg {
+                                wren_name: param_name(&input.pat, index),
+                                ty: input.ty.clone(),
+                            }
+                        }
+                        Type::Reference(reference) if matches!(*reference.elem, Type::Path(_)) => {
+                            Param::Foreign {
+                                wren_name: param_name(&input.pat, index),
+                                ty: reference.elem.clone(),
+                                mutable: reference.mutability.is_some(),
+                            }
+                        }
+                        Type::Reference(_) => return Err(Error::new_spanned(
+                            &input.ty,
+                            "expected `&str`, `&[u8]`, `&WrenVM` or a reference to a foreign class",
+                        )),
+                        _ => Param::Arg {
+                            wren_name: param_name(&input.pat, index),
+                            ty: input.ty.clone(),
+                        },
+                    })
+                }
+            }
+        }
+
+        let vms = params
+            .iter()
+            .filter_map(|param| match param {
+                Param::Vm { mutable } => Some(*mutable),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if vms.len() > 1 && vms.contains(&true) {
+            return Err(Error::new_spanned(
+                &sig.inputs,
+                "foreign methods taking `&mut WrenVM` cannot take the VM again",
+            ));
+        }
+
+        let method = Self {
+            rust_name: sig.ident.clone(),
+            wren_name: wren_name.unwrap_or_else(|| camel_case(&sig.ident.to_string())),
+            kind,
+            receiver,
+            params,
+        };
+        let arity = method.args().count();
+        match kind {
+            Kind::Constructor if receiver.is_some() => Err(Error::new_spanned(
+                &sig.inputs,
+                "constructors cannot take `self`",
+            )),
+            Kind::Getter if arity != 0 => Err(Error::new_spanned(
+                &sig.inputs,
+                "getters cannot take arguments",
+            )),
+            Kind::Setter if arity != 1 => Err(Error::new_spanned(
+                &sig.inputs,
+                "setters must take exactly one argument",
+            )),
+            _ => Ok(Some(method)),
+        }
+    }
+
+    /// The names of the Wren parameters.
+    fn args(&self) -> impl Iterator<Item = &str> {
+        self.params.iter().filter_map(|param| match param {
+            Param::Arg { wren_name, .. } | Param::Foreign { wren_name, .. } => {
+                Some(wren_name.as_str())
+            }
+            Param::Vm { .. } => None,
+        })
+    }
+
+    /// The declaration of this method inside the Wren class.
+    fn wren_declaration(&self) -> String {
+        let params = self.args().collect::<Vec<_>>();
+        match self.kind {
+            Kind::Constructor => {
+                format!("construct {}({}) {{}}", self.wren_name, params.join(", "))
+            }
+            _ => format!(
+                "foreign {}{}",
+                if self.receiver.is_some() {
+                    ""
+                } else {
+                    "static "
+                },
+                self.wren_signature(&params.join(", "))
+            ),
+        }
+    }
+
+    /// The signature DOME expects in `registerFn()`.
+    fn dome_signature(&self, class_name: &str) -> String {
+        let params = self.args().map(|_| "_").collect::<Vec<_>>();
+        format!(
+            "{}{}.{}",
+            if self.receiver.is_some() {
+                ""
+            } else {
+                "static "
+            },
+            class_name,
+            self.wren_signature(&params.join(","))
+        )
+    }
+
+    fn wren_signature(&self, params: &str) -> String {
+        match self.kind {
+            Kind::Getter => self.wren_name.clone(),
+            Kind::Setter => format!("{}=({})", self.wren_name, params),
+            Kind::Constructor | Kind::Method => format!("{}({})", self.wren_name, params),
+        }
+    }
+
+    /// A closure calling the Rust method, in the shape foreign methods are called in: the
+    /// receiver, then the VM, then the arguments, with foreign objects borrowed by guards.
+    fn adapter(&self, self_ty: &Type) -> TokenStream {
+        let rust_name = &self.rust_name;
+        let mut params = Vec::new();
+        let mut call_args = Vec::new();
+        if let Some(mutable) = self.receiver {
+            params.push(if mutable {
+                quote! { __dome_cloomnik_receiver: &mut #self_ty }
+            } else {
+                quote! { __dome_cloomnik_receiver: &#self_ty }
+            });
+            call_args.push(quote! { __dome_cloomnik_receiver });
+        }
+        // References to `FromWren` types would be taken for foreign objects.
+        let checks = self.params.iter().filter_map(|param| match param {
+            Param::Foreign { ty, .. } => Some(quote_spanned! {ty.span()=>
+                let () = <#ty as ::dome_cloomnik::__ForeignBorrow<_>>::CHECK;
+            }),
+            _ => None,
+        });
+        let mut vm = self.params.iter().filter_map(|param| match param {
+            Param::Vm { mutable } => Some(*mutable),
+            _ => None,
+        });
+        match vm.next() {
+            Some(true) => params.push(quote! { __dome_cloomnik_vm: &mut ::dome_cloomnik::WrenVM }),
+            Some(false) => params.push(quote! { __dome_cloomnik_vm: &::dome_cloomnik::WrenVM }),
+            None => {}
+        }
+        for (index, param) in self.params.iter().enumerate() {
+            let arg = format_ident!("__dome_cloomnik_arg_{}", index);
+            match param {
+                Param::Vm { .. } => call_args.push(quote! { __dome_cloomnik_vm }),
+                Param::Arg { ty, .. } => {
+                    params.push(quote! { #arg: #ty });
+                    call_args.push(quote! { #arg });
+                }
+                Param::Foreign {
+                    ty, mutable: true, ..
+                } => {
+                    params.push(quote! { mut #arg: ::dome_cloomnik::ForeignRefMut<'_, #ty> });
+                    call_args.push(quote! { &mut *#arg });
+                }
+                Param::Foreign {
+                    ty, mutable: false, ..
+                } => {
+                    params.push(quote! { #arg: ::dome_cloomnik::ForeignRef<'_, #ty> });
+                    call_args.push(quote! { &*#arg });
+                }
+            }
+        }
+        quote! {{
+            #(#checks)*
+            |#(#params),*| <#self_ty>::#rust_name(#(#call_args),*)
+        }}
+    }
+}
+
+/// Whether `ty` is `WrenVM`.
+fn is_vm(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "WrenVM"),
+        _ => false,
+    }
+}
+
+/// Whether `ty` is `str` or `[u8]`, which are borrowed from Wren strings.
+fn is_str_or_bytes(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path.qself.is_none() && path.path.is_ident("str"),
+        Type::Slice(slice) => {
+            matches!(&*slice.elem, Type::Path(path) if path.qself.is_none() && path.path.is_ident("u8"))
+        }
+        _ => false,
+    }
+}
+
+fn param_name(pat: &Pat, index: usize) -> String {
+    match pat {
+        Pat::Ident(ident) => {
+            let name = camel_case(&ident.ident.to_string());
+            if name.is_empty() {
+                format!("arg{}", index)
+            } else {
+                name
+            }
+        }
+        _ => format!("arg{}", index),
+    }
+}
+
+/// Converts a Rust `snake_case` identifier to a Wren `camelCase` one.
+fn camel_case(name: &str) -> String {
+    let name = name.trim_start_matches("r#").trim_start_matches('_');
+    let mut result = String::with_capacity(name.len());
+    for (index, part) in name.split('_').filter(|part| !part.is_empty()).enumerate() {
+        let mut chars = part.chars();
+        if index == 0 {
+            result.push_str(part);
+        } else if let Some(first) = chars.next() {
+            result.extend(first.to_uppercase());
+            result.push_str(chars.as_str());
+        }
+    }
+    result
+}
+
+pub(crate) fn expand(args: TokenStream, input: TokenStream) -> Result<TokenStream> {
+    let args = ClassArgs::parse(args)?;
+    let mut item: ItemImpl = syn::parse2(input)?;
+    if let Some((_, trait_, _)) = &item.trait_ {
+        return Err(Error::new_spanned(
+            trait_,
+            "`#[dome_class]` must be used on an inherent `impl` block",
+        ));
+    }
+    if !item.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &item.generics,
+            "generic classes are not supported",
+        ));
+    }
+    let self_ty = item.self_ty.clone();
+
+    let class_name = match &args.name {
+        Some(name) => name.value(),
+        None => match &*self_ty {
+            Type::Path(path) => path.path.segments.last().unwrap().ident.to_string(),
+            _ => {
+                return Err(Error::new_spanned(
+                    &self_ty,
+                    "cannot infer the class name, specify it with `name = \"...\"`",
+                ))
+            }
+        },
+    };
+
+    let mut methods = Vec::new();
+    for impl_item in &mut item.items {
+        if let ImplItem::Fn(method) = impl_item {
+            if let Some(method) = ForeignMethod::parse(method)? {
+                methods.push(method);
+            }
+        }
+    }
+
+    let mut constructors = methods
+        .iter()
+        .filter(|method| method.kind == Kind::Constructor);
+    let constructor = constructors.next();
+    if let Some(second) = constructors.next() {
+        return Err(Error::new(
+            second.rust_name.span(),
+            "a class can only have one constructor",
+        ));
+    }
+    if constructor.is_none() {
+        if let Some(method) = methods.iter().find(|method| method.receiver.is_some()) {
+            return Err(Error::new(
+                method.rust_name.span(),
+                "instance methods require a `#[foreign(construct)]` constructor",
+            ));
+        }
+    }
+
+    // The Wren source
+    let mut source = format!(
+        "{}class {}",
+        if constructor.is_some() {
+            "foreign "
+        } else {
+            ""
+        },
+        class_name
+    );
+    if let Some(superclass) = &args.superclass {
+        source += &format!(" is ({})", superclass.value());
+    }
+    source += " {\n";
+    for method in &methods {
+        source += &format!("  {}\n", method.wren_declaration());
+    }
+    if let Some(extra) = &args.source {
+        source += &format!("{}\n", extra.value());
+    }
+    source += "}\n";
+
+    // The allocator and finalizer
+    let register_class = constructor.map(|constructor| {
+        let adapter = constructor.adapter(&self_ty);
+        let names = constructor.args();
+        quote! {
+            extern "C" fn __dome_cloomnik_class_allocate(mut vm: ::dome_cloomnik::WrenVM) {
+                let instance = ::dome_cloomnik::__catch_panic_from_foreign(&vm, || {
+                    ::dome_cloomnik::__construct(&vm, #adapter, &[#(#names),*])
+                });
+                // SAFETY: Wren calls the allocator with the foreign class on slot 0.
+                unsafe { ::dome_cloomnik::__finish_allocation::<#self_ty>(&mut vm, instance) };
+            }
+            extern "C" fn __dome_cloomnik_class_finalize(data: *mut ::dome_cloomnik::__c_void) {
+                // We cannot report the failure, but we still have to not panic
+                let _ = ::std::panic::catch_unwind(|| {
+                    // SAFETY: The allocator always creates a `Self` object, possibly
+                    // unconstructed, and Wren calls the finalizer only once.
+                    unsafe { <::dome_cloomnik::__ForeignWrapper<#self_ty>>::finalize(data) };
+                });
+            }
+            // SAFETY: The allocator always creates the foreign object, even if the constructor
+            // fails, and both the allocator and the finalizer catch panics in user code. User
+            // code cannot store the VM because we pass it by reference.
+            unsafe {
+                ctx.register_class(
+                    <Self as ::dome_cloomnik::WrenClass>::MODULE,
+                    <Self as ::dome_cloomnik::WrenClass>::NAME,
+                    __dome_cloomnik_class_allocate,
+                    if <::dome_cloomnik::__ForeignWrapper<#self_ty>>::needs_drop() {
+                        Some(__dome_cloomnik_class_finalize)
+                    } else {
+                        None
+                    },
+                )
+            }?;
+            ::dome_cloomnik::__register_foreign_class::<#self_ty>(
+                <Self as ::dome_cloomnik::WrenClass>::MODULE,
+                <Self as ::dome_cloomnik::WrenClass>::NAME,
+            )?;
+        }
+    });
+
+    // Returning instances from foreign methods
+    let foreign_return = constructor.map(|_| {
+        quote! {
+            impl ::dome_cloomnik::__ForeignReturn for #self_ty {
+                #[inline]
+                fn store(self, vm: &mut ::dome_cloomnik::WrenVM) {
+                    // The class may be unregistered, or its module not imported yet.
+                    if let Err(error) = vm.try_new_foreign(0, self) {
+                        vm.abort_fiber_with(error);
+                    }
+                }
+            }
+        }
+    });
+
+    // The foreign methods
+    let module = &args.module;
+    let register_methods = methods
+        .iter()
+        .filter(|method| method.kind != Kind::Constructor)
+        .map(|method| {
+            let shim = format_ident!("__dome_cloomnik_method_{}", method.rust_name);
+            let signature = method.dome_signature(&class_name);
+            let adapter = method.adapter(&self_ty);
+            let names = method.args();
+            let receiver = match method.receiver {
+                // SAFETY: Wren passes the receiver, which is an instance of this class, at slot 0.
+                Some(_) => {
+                    quote! { unsafe { ::dome_cloomnik::__ForeignReceiver::<#self_ty>::new() } }
+                }
+                None => quote! { () },
+            };
+            quote! {
+                extern "C" fn #shim(vm: ::dome_cloomnik::WrenVM) {
+                    ::dome_cloomnik::__catch_panic_from_foreign(&vm, || {
+                        ::dome_cloomnik::__invoke(&vm, #module, #adapter, #receiver, &[#(#names),*])
+                    });
+                }
+                // SAFETY: The wrapper catches panics in user code. User code cannot store
+                // the VM because we pass it by reference.
+                unsafe {
+                    ctx.register_fn(<Self as ::dome_cloomnik::WrenClass>::MODULE, #signature, #shim)
+                }?;
+            }
+        })
+        .collect::<Vec<_>>();
+
+    Ok(quote! {
+        #item
+
+        #foreign_return
+
+        impl ::dome_cloomnik::WrenClass for #self_ty {
+            const MODULE: &'static str = #module;
+            const NAME: &'static str = #class_name;
+            const SOURCE: &'static str = #source;
+
+            fn __register(ctx: &mut ::dome_cloomnik::Context<'_>) -> ::dome_cloomnik::Result {
+                #register_class
+                #(#register_methods)*
+                Ok(())
+            }
+        }
+    })
+}
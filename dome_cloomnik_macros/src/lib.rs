@@ -0,0 +1,134 @@
+//! Procedural macros for [dome_cloomnik](https://docs.rs/dome_cloomnik).
+//!
+//! Do not depend on this crate directly: use the re-exports in `dome_cloomnik` instead.
+
+extern crate proc_macro;
+
+mod class;
+mod plugin;
+
+use proc_macro::TokenStream;
+
+/// Declares a Wren class whose foreign methods are implemented by the annotated `impl` block.
+///
+/// The attribute takes the following arguments:
+///
+///  - `module = "..."` (required): the module the class is declared in.
+///  - `name = "..."`: the name of the class in Wren. Defaults to the name of the Rust type.
+///  - `is = "..."`: the superclass.
+///  - `source = "..."`: additional Wren code to put inside the class body.
+///
+/// Methods marked with `#[foreign]` are bound to Wren. Other methods are left alone.
+/// `#[foreign]` accepts the following arguments:
+///
+///  - `construct`: this is the constructor of the class. It must return `Self`, or
+///    `Result<Self, E>` where `E: Display`. When a class has a constructor it is declared
+///    as a `foreign class`, and the Rust value becomes the foreign object. If the constructor
+///    fails (returns an error, panics or gets invalid arguments), the fiber is aborted.
+///  - `getter`: bind as a Wren getter (`foreign name`). Must not take arguments.
+///  - `setter`: bind as a Wren setter (`foreign name=(value)`). Must take one argument.
+///  - `name = "..."`: the name of the method in Wren. Defaults to the Rust name
+///    in camelCase.
+///
+/// Methods with a `self` receiver become instance methods, and other methods become
+/// static methods. A parameter of type `&WrenVM` or `&mut WrenVM` receives the VM.
+/// Every other parameter is a Wren argument, and so the arity of the Wren method
+/// is derived from them. Arguments are converted using `FromWren`, except references.
+/// `&str` and `&[u8]` borrow a Wren `String` without copying it, and other references
+/// (`&T` or `&mut T`) are borrows of Rust foreign objects of type `T`. The receiver
+/// and such arguments are borrowed like a `RefCell`, so passing the same object twice to a
+/// method that borrows it mutably aborts the fiber instead of creating aliasing references.
+/// References to types that implement `FromWren`, such as `&String`, do not compile: take
+/// them by value instead.
+///
+/// Methods may return `()`, any `ToWren` value or an instance of a foreign class, which
+/// is stored in slot 0. They may also return `Result<T, E>` where `E: Display`: `Ok` values
+/// are stored in slot 0, and errors abort the fiber with their message.
+///
+/// The macro implements `WrenClass` for the type. Register the class with
+/// `register_classes!`.
+///
+/// # Example
+///
+/// ```no_run
+/// use dome_cloomnik::dome_class;
+///
+/// struct Counter(f64);
+///
+/// #[dome_class(module = "counter")]
+/// impl Counter {
+///     #[foreign(construct)]
+///     fn new(start: f64) -> Self {
+///         Counter(start)
+///     }
+///
+///     #[foreign]
+///     fn increase_by(&mut self, amount: f64) -> f64 {
+///         self.0 += amount;
+///         self.0
+///     }
+/// }
+/// ```
+///
+/// This declares the following Wren class in the module `counter`:
+///
+/// ```wren
+/// foreign class Counter {
+///   construct new(start) {}
+///   foreign increaseBy(amount)
+/// }
+/// ```
+///
+/// Only foreign objects, strings and bytes can be borrowed:
+///
+/// ```compile_fail
+/// use dome_cloomnik::dome_class;
+///
+/// struct Greeter;
+///
+/// #[dome_class(module = "greeter")]
+/// impl Greeter {
+///     #[foreign]
+///     fn greet(name: &String) -> String {
+///         format!("Hello, {}!", name)
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn dome_class(args: TokenStream, input: TokenStream) -> TokenStream {
+    class::expand(args.into(), input.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Generates the `PLUGIN_onInit()` entry point of the plugin.
+///
+/// Put it on an inline module or on an `impl` block. The functions named `on_init`,
+/// `pre_update`, `post_update`, `pre_draw`, `post_draw` and `on_shutdown` inside it
+/// become the plugin hooks, and the others are left alone. Each hook must be of type
+/// `fn(Context) -> HookResult`.
+///
+/// # Example
+///
+/// ```no_run
+/// #[dome_cloomnik::plugin]
+/// mod plugin {
+///     use dome_cloomnik::{Context, HookResult};
+///
+///     fn on_init(mut ctx: Context) -> HookResult {
+///         ctx.log("Hello from Rust!\n");
+///         Ok(())
+///     }
+///
+///     fn on_shutdown(mut ctx: Context) -> HookResult {
+///         ctx.log("Goodbye!\n");
+///         Ok(())
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn plugin(args: TokenStream, input: TokenStream) -> TokenStream {
+    plugin::expand(args.into(), input.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
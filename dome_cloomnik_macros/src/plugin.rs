@@ -0,0 +1,97 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Error, ImplItem, Item, Result};
+
+const HOOKS: [&str; 6] = [
+    "on_init",
+    "pre_update",
+    "post_update",
+    "pre_draw",
+    "post_draw",
+    "on_shutdown",
+];
+
+/// Generates `PLUGIN_onInit()`, where `path` is the path to a hook relative to where
+/// the function is generated, given its name.
+fn entry_point(defined: &[String], path: impl Fn(&syn::Ident) -> TokenStream) -> TokenStream {
+    let hooks = HOOKS.iter().map(|hook| {
+        let hook = format_ident!("{}", hook);
+        if defined.iter().any(|defined| hook == defined) {
+            let path = path(&hook);
+            quote! { #hook: Some(#path) }
+        } else {
+            quote! { #hook: None }
+        }
+    });
+    quote! {
+        #[no_mangle]
+        #[allow(non_snake_case, clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PLUGIN_onInit(
+            get_api: *mut ::dome_cloomnik::__c_void,
+            ctx: *mut ::dome_cloomnik::__c_void,
+        ) -> ::dome_cloomnik::__c_int {
+            // SAFETY: We pass the arguments DOME gave us as-is.
+            unsafe {
+                ::dome_cloomnik::init_plugin(
+                    get_api,
+                    ctx,
+                    ::dome_cloomnik::Hooks {
+                        #(#hooks,)*
+                    },
+                )
+            }
+        }
+    }
+}
+
+pub(crate) fn expand(args: TokenStream, input: TokenStream) -> Result<TokenStream> {
+    if !args.is_empty() {
+        return Err(Error::new_spanned(
+            args,
+            "`#[plugin]` does not take arguments",
+        ));
+    }
+    match syn::parse2(input)? {
+        Item::Mod(mut module) => {
+            let content = match &mut module.content {
+                Some((_, content)) => content,
+                None => {
+                    return Err(Error::new_spanned(
+                        module,
+                        "`#[plugin]` can only be used on inline modules",
+                    ))
+                }
+            };
+            let defined = content
+                .iter()
+                .filter_map(|item| match item {
+                    Item::Fn(function) => Some(function.sig.ident.to_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            let entry_point = entry_point(&defined, |hook| quote! { #hook });
+            content.push(Item::Verbatim(entry_point));
+            Ok(quote! { #module })
+        }
+        Item::Impl(item) if item.trait_.is_none() && item.generics.params.is_empty() => {
+            let defined = item
+                .items
+                .iter()
+                .filter_map(|item| match item {
+                    ImplItem::Fn(function) => Some(function.sig.ident.to_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            let self_ty = &item.self_ty;
+            let entry_point = entry_point(&defined, |hook| quote! { <#self_ty>::#hook });
+            Ok(quote! {
+                #item
+                #entry_point
+            })
+        }
+        item => Err(Error::new_spanned(
+            item,
+            "`#[plugin]` can only be used on an inline module or a non-generic inherent `impl` block",
+        )),
+    }
+}
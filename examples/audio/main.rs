@@ -6,41 +6,17 @@ use dome_cloomnik::{
     register_modules, CallbackChannel, Channel, ChannelState, Context, HookResult, WrenVM,
 };
 
-#[no_mangle]
-#[allow(non_snake_case)]
-extern "C" fn PLUGIN_onInit(get_api: *mut libc::c_void, ctx: *mut libc::c_void) -> libc::c_int {
-    unsafe {
-        dome_cloomnik::init_plugin(
-            get_api,
-            ctx,
-            dome_cloomnik::Hooks {
-                on_init: Some(on_init),
-                pre_update: None,
-                post_update: None,
-                pre_draw: None,
-                post_draw: None,
-                on_shutdown: None,
-            },
-        )
-    }
-}
-
 static mut GLOBAL_TIME: f64 = 0.0;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy)]
 enum OscType {
+    #[default]
     Sine,
     Square,
     Saw,
     Triangle,
 }
 
-impl Default for OscType {
-    fn default() -> Self {
-        OscType::Sine
-    }
-}
-
 #[derive(Debug, Default, Clone, Copy)]
 struct Note {
     duration: f64,
@@ -61,19 +37,14 @@ struct Envelop {
     playing: bool,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy)]
 enum SynthMode {
+    #[default]
     Tone,
     Pattern,
     Note,
 }
 
-impl Default for SynthMode {
-    fn default() -> Self {
-        SynthMode::Tone
-    }
-}
-
 #[derive(Debug, Default)]
 struct Synth {
     r#type: OscType,
@@ -272,25 +243,25 @@ impl SynthClass {
         SynthClass(channel)
     }
 
-    fn synth(&self) -> std::sync::RwLockReadGuard<Synth> {
+    fn synth(&self) -> std::sync::RwLockReadGuard<'_, Synth> {
         self.0.data().unwrap()
     }
-    fn synth_mut(&mut self) -> std::sync::RwLockWriteGuard<Synth> {
+    fn synth_mut(&mut self) -> std::sync::RwLockWriteGuard<'_, Synth> {
         self.0.data_mut().unwrap()
     }
 
-    fn set_volume(&mut self, vm: &WrenVM) {
-        self.synth_mut().volume = 0.0f32.max(vm.get_slot_double(1) as f32);
+    fn set_volume(&mut self, volume: f64) {
+        self.synth_mut().volume = 0.0f32.max(volume as f32);
     }
 
-    fn get_volume(&mut self, vm: &mut WrenVM) {
-        vm.set_slot_double(0, self.synth().volume as f64);
+    fn get_volume(&self) -> f64 {
+        self.synth().volume as f64
     }
 
-    fn play_tone(&mut self, vm: &WrenVM) {
+    fn play_tone(&mut self, vm: &WrenVM, frequency: f64, time: f64) {
         let mut synth = self.synth_mut();
-        synth.frequency = vm.get_slot_double(1) as f32;
-        synth.length = vm.get_slot_double(2) as f32 / 1_000.0;
+        synth.frequency = frequency as f32;
+        synth.length = time as f32 / 1_000.0;
         synth.start_time = unsafe { GLOBAL_TIME };
 
         synth.activate();
@@ -300,12 +271,11 @@ impl SynthClass {
         ctx.log(&format!("Frequency: {}\n", synth.frequency));
     }
 
-    fn play_note(&mut self, vm: &WrenVM) {
-        let octave = vm.get_slot_double(1) as f32;
-        let pitch = vm.get_slot_double(2) as f32;
+    fn play_note(&mut self, vm: &WrenVM, octave: f64, pitch: f64, time: f64) {
+        let (octave, pitch) = (octave as f32, pitch as f32);
         let mut synth = self.synth_mut();
         synth.frequency = get_note_frequency(octave, pitch);
-        synth.length = vm.get_slot_double(3) as f32;
+        synth.length = time as f32;
         synth.start_time = unsafe { GLOBAL_TIME };
 
         synth.activate();
@@ -318,9 +288,8 @@ impl SynthClass {
         ));
     }
 
-    fn note_on(&mut self, vm: &WrenVM) {
-        let octave = vm.get_slot_double(1) as f32;
-        let pitch = vm.get_slot_double(2) as f32;
+    fn note_on(&mut self, vm: &WrenVM, octave: f64, pitch: f64) {
+        let (octave, pitch) = (octave as f32, pitch as f32);
         let mut synth = self.synth_mut();
         synth.frequency = get_note_frequency(octave, pitch);
 
@@ -331,19 +300,18 @@ impl SynthClass {
         ));
     }
 
-    fn note_off(&mut self, _vm: &WrenVM) {
+    fn note_off(&mut self) {
         let mut synth = self.synth_mut();
         synth.env.trigger_off_time = unsafe { GLOBAL_TIME };
         synth.env.playing = false;
     }
 
-    fn store_pattern(&mut self, vm: &WrenVM) {
+    fn store_pattern(&mut self, vm: &WrenVM, pattern_str: Vec<u8>) {
         const BPM: f64 = 144.0;
         const DEFAULT_DURATION: i8 = 4;
         const DEFAULT_OCTAVE: i8 = 4;
 
         let mut ctx = vm.get_context();
-        let pattern_str = vm.get_slot_bytes(1);
 
         let pattern = pattern_str
             .split(|&c| c == b' ')
@@ -360,17 +328,17 @@ impl SynthClass {
                     ..Default::default()
                 };
 
-                if let Some(b'.') = token.get(0) {
+                if let Some(b'.') = token.first() {
                     note.duration *= 1.5;
                     token = &token[1..];
                 }
 
-                let sharp = matches!(token.get(0), Some(b'#'));
+                let sharp = matches!(token.first(), Some(b'#'));
                 if sharp {
                     token = &token[1..];
                 }
 
-                if let Some(&letter) = token.get(0) {
+                if let Some(&letter) = token.first() {
                     if is_note_letter(letter) {
                         let k = letter.to_ascii_lowercase() & 7;
                         note.pitch = ((((k as f64 * 1.6) as i32) + 8 + (sharp as i32)) % 12) as i8;
@@ -402,7 +370,7 @@ impl SynthClass {
         self.synth_mut().pending_pattern = Some(pattern);
     }
 
-    fn play_pattern(&mut self, _vm: &WrenVM) {
+    fn play_pattern(&mut self) {
         let mut synth = self.synth_mut();
         synth.swap_pattern = true;
         synth.active = true;
@@ -412,26 +380,31 @@ impl SynthClass {
     }
 }
 
-fn on_init(mut ctx: Context) -> HookResult {
-    ctx.log("init hook triggered\n");
-
-    (register_modules! {
-        ctx,
-        module "synth" {
-            foreign class SynthClass_ = new of SynthClass {
-                "construct new_() {}"
-                foreign volume=(v) = set_volume
-                foreign volume = get_volume
-                foreign playTone(frequency, time) = play_tone
-                foreign playNote(octave, note, time) = play_note
-                foreign noteOn(octave, note) = note_on
-                foreign noteOff() = note_off
-                foreign storePattern(pattern) = store_pattern
-                foreign playPattern() = play_pattern
+struct Plugin;
+
+#[dome_cloomnik::plugin]
+impl Plugin {
+    fn on_init(mut ctx: Context) -> HookResult {
+        ctx.log("init hook triggered\n");
+
+        (register_modules! {
+            ctx,
+            module "synth" {
+                foreign class SynthClass_ = new of SynthClass {
+                    "construct new_() {}"
+                    foreign volume=(v) = set_volume
+                    foreign volume = get_volume
+                    foreign playTone(frequency, time) = play_tone
+                    foreign playNote(octave, note, time) = play_note
+                    foreign noteOn(octave, note) = note_on
+                    foreign noteOff() = note_off
+                    foreign storePattern(pattern) = store_pattern
+                    foreign playPattern() = play_pattern
+                }
+                "var Synth = SynthClass_.new_()"
             }
-            "var Synth = SynthClass_.new_()"
-        }
-    })?;
+        })?;
 
-    Ok(())
+        Ok(())
+    }
 }
@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::WrenType;
+
 /// The error type of this crate.
 #[derive(Debug, Error)]
 pub enum Error {
@@ -24,7 +26,182 @@ pub enum Error {
         module_name: String,
         method_signature: String,
     },
+    /// `slot` is not a valid slot, since there are only `count` slots.
+    ///
+    /// Can be returned by the `try_*` methods of [`WrenVM`][crate::WrenVM].
+    #[error("Slot {slot} is out of bounds: there are only {count} slots.")]
+    SlotOutOfBounds { slot: usize, count: usize },
+    /// `slot` was expected to contain a value of type `expected`, but contained `actual`.
+    ///
+    /// Can be returned by the `try_*` methods of [`WrenVM`][crate::WrenVM].
+    #[error("{} must be {expected}, got {actual}.", capitalized_slot_name(*.slot))]
+    SlotTypeMismatch {
+        slot: usize,
+        expected: WrenType,
+        actual: WrenType,
+    },
+    /// `slot` contains a value of the right type, but it cannot be converted to the requested
+    /// Rust type, e.g. a number that is not an integer or a string that is not valid UTF-8.
+    ///
+    /// Can be returned by [`WrenVM::try_get()`][crate::WrenVM::try_get()] and
+    /// [`WrenVM::try_get_slot_string()`][crate::WrenVM::try_get_slot_string()].
+    #[error("{} is invalid: {reason}.", capitalized_slot_name(*.slot))]
+    SlotConversionFailed { slot: usize, reason: String },
+    /// A Rust value cannot be stored in `slot`, e.g. because it contains a map whose keys
+    /// Wren cannot hash.
+    ///
+    /// Can be returned by `serde::to_slot()`, with the `serde` feature.
+    #[error("Cannot store value in {}: {reason}.", slot_name(*.slot))]
+    SlotSerializationFailed { slot: usize, reason: String },
+    /// `slot` contains a Rust foreign object of type `actual`, but `expected` was requested.
+    /// `class` is the Wren class of `expected`, if it is registered.
+    ///
+    /// Can be returned by
+    /// [`WrenVM::try_get_slot_foreign()`][crate::WrenVM::try_get_slot_foreign()],
+    /// [`WrenVM::borrow_foreign()`][crate::WrenVM::borrow_foreign()] and
+    /// [`WrenVM::borrow_foreign_mut()`][crate::WrenVM::borrow_foreign_mut()].
+    #[error(
+        "{} must be foreign `{}`, got foreign `{}`{}.",
+        capitalized_slot_name(*.slot),
+        short_type_name(.expected),
+        short_type_name(.actual),
+        .class.map(|class| format!(" (Wren class {})", class)).unwrap_or_default(),
+    )]
+    ForeignTypeMismatch {
+        slot: usize,
+        expected: &'static str,
+        actual: &'static str,
+        class: Option<&'static str>,
+    },
+    /// The foreign object in `slot` is already borrowed, so it cannot be borrowed again
+    /// (e.g. because it was passed twice to the same method).
+    ///
+    /// Can be returned by [`WrenVM::borrow_foreign()`][crate::WrenVM::borrow_foreign()] and
+    /// [`WrenVM::borrow_foreign_mut()`][crate::WrenVM::borrow_foreign_mut()].
+    #[error("{} is already borrowed.", capitalized_slot_name(*.slot))]
+    ForeignAlreadyBorrowed { slot: usize },
+    /// The Rust foreign object of type `type_name` behind a [`TypedHandle`][crate::TypedHandle]
+    /// is already borrowed, so it cannot be borrowed again.
+    ///
+    /// Can be returned by [`TypedHandle::borrow()`][crate::TypedHandle::borrow()] and
+    /// [`TypedHandle::borrow_mut()`][crate::TypedHandle::borrow_mut()].
+    #[error("The foreign `{}` behind the handle is already borrowed.", short_type_name(.type_name))]
+    HandleAlreadyBorrowed { type_name: &'static str },
+    /// The foreign object behind a [`TypedHandle`][crate::TypedHandle] is of type `actual`,
+    /// but `expected` was requested.
+    ///
+    /// Can be returned by [`TypedHandle::borrow()`][crate::TypedHandle::borrow()] and
+    /// [`TypedHandle::borrow_mut()`][crate::TypedHandle::borrow_mut()].
+    #[error(
+        "The handle must be to a foreign `{}`, got foreign `{}`.",
+        short_type_name(.expected),
+        short_type_name(.actual),
+    )]
+    HandleTypeMismatch {
+        expected: &'static str,
+        actual: &'static str,
+    },
+    /// The objects of the foreign class `class` are of type `actual`, but an instance
+    /// of type `expected` was given.
+    ///
+    /// Can be returned by
+    /// [`ClassHandle::try_new_instance()`][crate::ClassHandle::try_new_instance()].
+    #[error(
+        "The objects of class {class} are of type `{}`, not `{}`.",
+        short_type_name(.actual),
+        short_type_name(.expected),
+    )]
+    ClassTypeMismatch {
+        class: &'static str,
+        expected: &'static str,
+        actual: &'static str,
+    },
+    /// `index` is out of bounds of the list in `slot`, which has `count` elements.
+    ///
+    /// Can be returned by [`WrenList::try_get()`][crate::WrenList::try_get()].
+    #[error("Index {index} is out of bounds of the list in {}, which has {count} elements.", slot_name(*.slot))]
+    ListIndexOutOfBounds {
+        slot: usize,
+        index: usize,
+        count: usize,
+    },
+    /// The Rust type `type_name` does not back any registered foreign class.
+    ///
+    /// Can be returned by [`WrenVM::try_new_foreign()`][crate::WrenVM::try_new_foreign()] and
+    /// [`ClassHandle::try_of()`][crate::ClassHandle::try_of()].
+    #[error("Rust type `{type_name}` is not registered as a foreign class.")]
+    ForeignClassNotRegistered { type_name: &'static str },
+    /// The Rust type `type_name` already backs the foreign class `class` in `module`, so it
+    /// cannot back another class.
+    ///
+    /// Can be returned by [`register_modules!`] and
+    /// [`register_classes!`][crate::register_classes!].
+    #[error(
+        "Rust type `{}` already backs foreign class '{class}' in module '{module}'.",
+        short_type_name(.type_name)
+    )]
+    ForeignClassAlreadyRegistered {
+        type_name: &'static str,
+        module: &'static str,
+        class: &'static str,
+    },
+    /// The foreign class of the Rust type `type_name` is registered, but Wren is not known
+    /// to have imported its `module` yet, so the class may not exist yet. A module counts as
+    /// imported once Wren called a foreign method or allocated an object of one of its
+    /// classes.
+    ///
+    /// Can be returned by [`WrenVM::try_new_foreign()`][crate::WrenVM::try_new_foreign()] and
+    /// [`ClassHandle::try_of()`][crate::ClassHandle::try_of()].
+    #[error(
+        "The foreign class of Rust type `{}` is in module '{module}', which is not imported yet.",
+        short_type_name(.type_name)
+    )]
+    ForeignClassNotImported {
+        type_name: &'static str,
+        module: &'static str,
+    },
+    /// The argument `name` of a foreign method is invalid.
+    ///
+    /// Foreign methods with typed parameters abort the fiber with this error.
+    #[error("Invalid argument `{name}`. {error}")]
+    InvalidArgument { name: String, error: Box<Error> },
+}
+
+/// In foreign methods, slot 0 is the receiver and the arguments start at slot 1.
+fn slot_name(slot: usize) -> String {
+    match slot {
+        0 => "slot 0".to_owned(),
+        _ => format!("argument {}", slot),
+    }
+}
+
+/// Like [`slot_name()`], but for the start of a sentence.
+fn capitalized_slot_name(slot: usize) -> String {
+    match slot {
+        0 => "Slot 0".to_owned(),
+        _ => format!("Argument {}", slot),
+    }
+}
+
+/// Strips the module paths from a type name, e.g. `alloc::vec::Vec<my_plugin::Note>`
+/// becomes `Vec<Note>`.
+fn short_type_name(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut segment_start = 0;
+    for (index, c) in name.char_indices() {
+        if !(c.is_alphanumeric() || c == '_' || c == ':') {
+            result.push_str(path_last_segment(&name[segment_start..index]));
+            result.push(c);
+            segment_start = index + c.len_utf8();
+        }
+    }
+    result.push_str(path_last_segment(&name[segment_start..]));
+    result
+}
+
+fn path_last_segment(path: &str) -> &str {
+    path.rsplit("::").next().unwrap_or(path)
 }
 
-/// The result of operations in this crate that may fail. Alias of `std::result::Result<(), Error>`.
-pub type Result = std::result::Result<(), Error>;
+/// The result of operations in this crate that may fail. Alias of `std::result::Result<T, Error>`.
+pub type Result<T = ()> = std::result::Result<T, Error>;
@@ -20,64 +20,72 @@
 //! ```
 //!
 //! lib.rs:
-//! ```
-//! use dome_cloomnik::{Context, WrenVM, register_modules, HookResult};
+//! ```ignore
+//! #[dome_cloomnik::plugin]
+//! mod plugin {
+//!     use dome_cloomnik::{Context, WrenVM, register_modules, HookResult};
 //!
-//! #[no_mangle]
-//! #[allow(non_snake_case)]
-//! extern "C" fn PLUGIN_onInit(get_api: *mut libc::c_void, ctx: *mut libc::c_void) -> libc::c_int {
-//!     unsafe {
-//!         dome_cloomnik::init_plugin(
-//!             get_api,
+//!     fn on_init(mut ctx: Context) -> HookResult {
+//!         (register_modules! {
 //!             ctx,
-//!             dome_cloomnik::Hooks {
-//!                 on_init: Some(on_init),
-//!                 pre_update: Some(pre_update),
-//!                 post_update: Some(post_update),
-//!                 pre_draw: Some(pre_draw),
-//!                 post_draw: Some(post_draw),
-//!                 on_shutdown: Some(on_shutdown),
-//!             }
-//!         )
-//!     }
-//! }
+//!             ...
+//!         })?;
 //!
-//! fn on_init(mut ctx: Context) -> HookResult {
-//!     (register_modules! {
-//!         ctx,
-//!         ...
-//!     })?;
-//!
-//!     // ...
-//! }
+//!         // ...
+//!     }
 //!
-//! fn pre_update(mut ctx: Context) -> HookResult {
-//!     // ...
-//! }
+//!     fn pre_update(mut ctx: Context) -> HookResult {
+//!         // ...
+//!     }
 //!
-//! fn post_update(mut ctx: Context) -> HookResult {
-//!     // ...
-//! }
+//!     fn post_update(mut ctx: Context) -> HookResult {
+//!         // ...
+//!     }
 //!
-//! fn pre_draw(mut ctx: Context) -> HookResult {
-//!     // ...
-//! }
+//!     fn pre_draw(mut ctx: Context) -> HookResult {
+//!         // ...
+//!     }
 //!
-//! fn post_draw(mut ctx: Context) -> HookResult {
-//!     // ...
-//! }
+//!     fn post_draw(mut ctx: Context) -> HookResult {
+//!         // ...
+//!     }
 //!
-//! fn on_shutdown(mut ctx: Context) -> HookResult {
-//!     // ...
+//!     fn on_shutdown(mut ctx: Context) -> HookResult {
+//!         // ...
+//!     }
 //! }
 //! ```
 //!
+//! The [`plugin`] attribute generates the `PLUGIN_onInit()` function DOME calls,
+//! with the hooks you defined. All hooks are optional.
+//!
 //! Go ahead, and start with [learning DOME plugins from the docs](https://domeengine.com/plugins/).
 //! Don't worry, much of the things there will apply to doom_cloomnik too!
+//!
+//! Instead of [`register_modules!`], you can also declare classes with the [`dome_class`]
+//! attribute on an `impl` block, and register them with [`register_classes!`].
+//!
+//! With the `testing` feature, the `testing` module lets you test your plugin without DOME.
+//! You usually only need it in tests, so enable it in your dev-dependencies:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! dome_cloomnik = { version = "0.1", features = ["testing"] }
+//! ```
+//!
+//! The `wren-host` feature adds `testing::WrenHost`, which runs Wren code on a real Wren
+//! VM, so you can also test the Wren side of your modules. It needs a C compiler.
+//!
+//! With the `serde` feature, the `serde` module converts any Rust type
+//! implementing `Serialize` or `Deserialize` from and to Wren values.
 
 mod errors;
 mod panic;
 mod safe_wrappers;
+#[cfg(feature = "serde")]
+pub mod serde;
+#[cfg(feature = "testing")]
+pub mod testing;
 mod unsafe_wrappers;
 
 use libc::{c_int, c_void};
@@ -90,16 +98,42 @@ use unsafe_wrappers::audio as unsafe_audio;
 use unsafe_wrappers::dome::{self as unsafe_dome, Result as DomeResult};
 use unsafe_wrappers::wren as unsafe_wren;
 
+pub use dome_cloomnik_macros::{dome_class, plugin};
 pub use errors::{Error, Result};
 pub use safe_wrappers::audio::{CallbackChannel, Channel, ChannelMix, ChannelState, ChannelUpdate};
+pub use safe_wrappers::class::WrenClass;
+pub use safe_wrappers::collections::{ListIter, WrenList, WrenMap};
+pub use safe_wrappers::convert::{FromWren, ToWren};
 pub use safe_wrappers::dome::Context;
-pub use safe_wrappers::wren::{Handle as WrenHandle, Type as WrenType, VM as WrenVM};
+pub use safe_wrappers::frame::SlotFrame;
+pub use safe_wrappers::handles::{ClassHandle, SendHandle, TypedHandle};
+pub use safe_wrappers::registry::Upcast;
+pub use safe_wrappers::value::WrenValue;
+pub use safe_wrappers::wren::{
+    ForeignRef, ForeignRefMut, Handle as WrenHandle, Type as WrenType, VM as WrenVM,
+};
 
 #[doc(hidden)]
 #[allow(non_camel_case_types)]
 pub type __c_void = c_void;
 #[doc(hidden)]
 #[allow(non_camel_case_types)]
+pub type __c_int = c_int;
+#[doc(hidden)]
+pub use safe_wrappers::class::{
+    register_classes as __register_classes, ClassEntry as __ClassEntry,
+};
+#[doc(hidden)]
+pub use safe_wrappers::convert::ForeignReturn as __ForeignReturn;
+#[doc(hidden)]
+pub use safe_wrappers::method::{
+    construct as __construct, finish_allocation as __finish_allocation, invoke as __invoke,
+    ForeignBorrow as __ForeignBorrow, ForeignReceiver as __ForeignReceiver,
+};
+#[doc(hidden)]
+pub use safe_wrappers::registry::register_foreign_class as __register_foreign_class;
+#[doc(hidden)]
+#[allow(non_camel_case_types)]
 pub type __ForeignWrapper<T> = safe_wrappers::wren::ForeignWrapper<T>;
 #[doc(hidden)]
 #[allow(non_camel_case_types)]
@@ -108,15 +142,15 @@ pub fn __catch_panic_from_foreign<R>(
     vm: &WrenVM,
     callback: impl FnOnce() -> R + std::panic::UnwindSafe,
 ) -> Option<R> {
-    panic::catch_panic(callback)
+    panic::catch_panic(|| safe_wrappers::frame::foreign_call(callback))
         .map_err(|panic_message| panic::handle_wren_callback_panic(vm.0, &panic_message))
         .ok()
 }
 #[doc(hidden)]
 #[allow(non_camel_case_types)]
 #[inline]
-pub unsafe fn __clone_vm(vm: &WrenVM) -> WrenVM {
-    WrenVM(vm.0)
+pub unsafe fn __clone_vm<'vm>(vm: &WrenVM<'vm>) -> WrenVM<'vm> {
+    WrenVM::from_raw(vm.0)
 }
 
 #[repr(C)]
@@ -175,7 +209,7 @@ impl Api {
 pub type HookResult = anyhow::Result<()>;
 /// DOME plugin hook.
 pub type Hook = fn(Context) -> HookResult;
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 /// A struct containing all plugin hooks. All hooks are optional.
 pub struct Hooks {
     pub on_init: Option<Hook>,
@@ -248,6 +282,7 @@ extern "C" fn PLUGIN_preUpdate(ctx: unsafe_dome::Context) -> DomeResult {
     // call `init_plugin()`, but protecting against that would make user code
     // awkward, and you'll get immediate crash because of dereferencing null pointer
     // once you'll do something with this crate.
+    safe_wrappers::handles::release_queued();
     invoke_hook(ctx, unsafe { HOOKS.pre_update })
 }
 
@@ -296,5 +331,16 @@ extern "C" fn PLUGIN_onShutdown(ctx: unsafe_dome::Context) -> DomeResult {
     // call `init_plugin()`, but protecting against that would make user code
     // awkward, and you'll get immediate crash because of dereferencing null pointer
     // once you'll do something with this crate.
-    invoke_hook(ctx, unsafe { HOOKS.on_shutdown })
+    let result = invoke_hook(ctx, unsafe { HOOKS.on_shutdown });
+    // The VM is freed after the plugins are shut down.
+    safe_wrappers::registry::clear();
+    let leaked = safe_wrappers::handles::release_all();
+    if cfg!(debug_assertions) && leaked > 0 {
+        Context(ctx, PhantomData).log(&format!(
+            "{} Wren handle(s) were still alive at shutdown. They were released, and dropping \
+             them later does nothing.",
+            leaked
+        ));
+    }
+    result
 }
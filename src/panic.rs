@@ -14,7 +14,7 @@ pub(crate) struct PanicInfo {
 }
 
 thread_local! {
-    static PANIC_INFO: Cell<Option<PanicInfo>> = Cell::new(None);
+    static PANIC_INFO: Cell<Option<PanicInfo>> = const { Cell::new(None) };
 }
 
 #[inline]
@@ -64,7 +64,8 @@ pub(crate) fn log_panic(ctx: Context, panic_info: &PanicInfo) {
 
 #[inline]
 pub(crate) fn handle_wren_callback_panic(vm: crate::unsafe_wren::VM, panic_info: &PanicInfo) {
-    let mut vm = crate::safe_wrappers::wren::VM(vm);
+    // SAFETY: Wren is calling us with this VM.
+    let mut vm = unsafe { crate::safe_wrappers::wren::VM::from_raw(vm) };
 
     log_panic(vm.get_context().0, panic_info);
 
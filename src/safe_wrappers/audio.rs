@@ -1,13 +1,13 @@
 use libc::{c_float, size_t};
 use std::alloc::{self, Layout};
 use std::cell::UnsafeCell;
-use std::convert::TryInto;
 use std::marker::PhantomData;
 use std::mem;
 use std::ptr;
 use std::slice;
 use std::sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
+use super::handles;
 use super::wren;
 use crate::panic::{catch_panic, handle_wren_callback_panic, PanicInfo};
 use crate::unsafe_wrappers::audio as unsafe_audio;
@@ -16,7 +16,7 @@ use crate::Api;
 pub use unsafe_audio::ChannelState;
 
 pub(crate) struct InternalChannelData {
-    mix: fn(&unsafe_audio::ChannelRef, &mut [[f32; 2]], usize),
+    mix: fn(&unsafe_audio::ChannelRef, &mut [[f32; 2]]),
     update: Option<fn(&unsafe_audio::ChannelRef, &unsafe_wren::VM)>,
     mix_error: Mutex<Option<PanicInfo>>,
 
@@ -38,15 +38,28 @@ impl<T: Send + Sync> ChannelData<T> {
             internal_data: InternalChannelData {
                 // SAFETY: `Channel<T>` is `repr(transparent)` over `ChannelRef`,
                 // and so the ABI matches.
-                mix: unsafe { mem::transmute(mix) },
-                update: unsafe { mem::transmute(update) },
+                mix: unsafe {
+                    mem::transmute::<ChannelMix<T>, fn(&unsafe_audio::ChannelRef, &mut [[f32; 2]])>(
+                        mix,
+                    )
+                },
+                update: Some(unsafe {
+                    mem::transmute::<
+                        ChannelUpdate<T>,
+                        fn(&unsafe_audio::ChannelRef, &unsafe_wren::VM),
+                    >(update)
+                }),
                 mix_error: Mutex::new(None),
 
                 // SAFETY: `ChannelData<T>` is `repr(C)` and its first member is
                 // `InternalChannelData` (which guarantees it to be at offset 0),
                 // And so passing a pointer to `InternalChannelData` to a function
                 // that takes `ChannelData<T>` is valid.
-                drop_fn: unsafe { mem::transmute::<unsafe fn(_), _>(ptr::drop_in_place::<Self>) },
+                drop_fn: unsafe {
+                    mem::transmute::<unsafe fn(*mut Self), unsafe fn(*mut InternalChannelData)>(
+                        ptr::drop_in_place::<Self>,
+                    )
+                },
                 layout: Layout::new::<Self>(),
             },
             user_data: RwLock::new(user_data),
@@ -68,13 +81,12 @@ pub(crate) extern "C" fn mix(
     let internal_data = unsafe { &mut *get_internal_data(channel_ref) };
     let callback = internal_data.mix;
     let error = catch_panic(|| {
-        let requested_samples = requested_samples.try_into().unwrap();
         let buffer = buffer as *mut [c_float; 2];
         // SAFETY: DOME guarantees a zeroes buffer of size `2 * requested_samples`.
         // Array layout is sequence of elements, so `&mut [f32]` of `2 * size`
         // can be transmuted into `&mut [[f32; 2]]` of `size`.
         let buffer = unsafe { slice::from_raw_parts_mut(buffer, requested_samples) };
-        callback(&channel_ref, buffer, requested_samples)
+        callback(&channel_ref, buffer)
     });
     if let Err(error) = error {
         // OK to `.unwrap()` the mutex lock (even though panicking across FFI is undefined
@@ -99,13 +111,14 @@ pub(crate) extern "C" fn update(channel_ref: unsafe_audio::ChannelRef, vm: unsaf
     let internal_data = unsafe { &mut *get_internal_data(channel_ref) };
 
     handle_mix_error(vm, &internal_data.mix_error);
+    handles::release_queued();
 
-    internal_data.update.map(|callback| {
+    if let Some(callback) = internal_data.update {
         let error = catch_panic(|| callback(&channel_ref, &vm));
         if let Err(error) = error {
             handle_wren_callback_panic(vm, &error);
         }
-    });
+    }
 }
 
 pub(crate) extern "C" fn finish(channel_ref: unsafe_audio::ChannelRef, vm: unsafe_wren::VM) {
@@ -206,7 +219,7 @@ impl<T: Send + Sync> Channel<T> {
     /// so you can have multiple read-only references but only one read-write
     /// reference at a time.
     #[inline]
-    pub fn data(&self) -> Option<RwLockReadGuard<T>> {
+    pub fn data(&self) -> Option<RwLockReadGuard<'_, T>> {
         Some(self.user_data()?.read().unwrap())
     }
     /// Gets the user data associated with this channel, for read and write.
@@ -214,7 +227,7 @@ impl<T: Send + Sync> Channel<T> {
     /// so you can have multiple read-only references but only one read-write
     /// reference at a time.
     #[inline]
-    pub fn data_mut(&self) -> Option<RwLockWriteGuard<T>> {
+    pub fn data_mut(&self) -> Option<RwLockWriteGuard<'_, T>> {
         Some(self.user_data()?.write().unwrap())
     }
 }
@@ -262,7 +275,7 @@ impl<T: Send + Sync> CallbackChannel<T> {
     /// so you can have multiple read-only references but only one read-write
     /// reference at a time.
     #[inline]
-    pub fn data(&self) -> RwLockReadGuard<T> {
+    pub fn data(&self) -> RwLockReadGuard<'_, T> {
         self.user_data().read().unwrap()
     }
     /// Gets the user data associated with this channel, for read and write.
@@ -270,7 +283,7 @@ impl<T: Send + Sync> CallbackChannel<T> {
     /// so you can have multiple read-only references but only one read-write
     /// reference at a time.
     #[inline]
-    pub fn data_mut(&self) -> RwLockWriteGuard<T> {
+    pub fn data_mut(&self) -> RwLockWriteGuard<'_, T> {
         self.user_data().write().unwrap()
     }
 }
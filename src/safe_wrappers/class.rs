@@ -0,0 +1,196 @@
+use super::dome::Context;
+use crate::Result;
+
+/// A Rust type that implements a Wren class.
+///
+/// Do not implement this trait yourself: use the [`dome_class`][crate::dome_class] attribute,
+/// then register the class with [`register_classes!`][crate::register_classes!].
+pub trait WrenClass: 'static {
+    /// The module the class is declared in.
+    const MODULE: &'static str;
+    /// The name of the class in Wren.
+    const NAME: &'static str;
+    /// The Wren source declaring the class.
+    const SOURCE: &'static str;
+
+    #[doc(hidden)]
+    fn __register(ctx: &mut Context<'_>) -> Result;
+}
+
+#[doc(hidden)]
+#[derive(Clone, Copy)]
+pub struct ClassEntry {
+    module: &'static str,
+    source: &'static str,
+    register: fn(&mut Context<'_>) -> Result,
+}
+
+impl ClassEntry {
+    #[inline]
+    pub fn of<T: WrenClass>() -> Self {
+        Self {
+            module: T::MODULE,
+            source: T::SOURCE,
+            register: T::__register,
+        }
+    }
+}
+
+#[doc(hidden)]
+pub fn register_classes(ctx: &mut Context<'_>, classes: &[ClassEntry]) -> Result {
+    let mut modules = Vec::<&str>::new();
+    for class in classes {
+        if !modules.contains(&class.module) {
+            modules.push(class.module);
+        }
+    }
+    for module in modules {
+        let mut classes = classes.iter().filter(|class| class.module == module);
+        let source = classes
+            .clone()
+            .map(|class| class.source)
+            .collect::<String>();
+        ctx.register_module(module, &source)?;
+        let result = classes.try_for_each(|class| (class.register)(ctx));
+        ctx.lock_module(module);
+        result?;
+    }
+    Ok(())
+}
+
+/// Registers classes declared with [`dome_class`][crate::dome_class].
+///
+/// Each module the classes are declared in is registered with all of its classes,
+/// then locked. Therefore, all classes of a module must be registered in the same call.
+///
+/// # Example
+///
+/// ```
+/// use dome_cloomnik::testing::{MockHost, Value};
+/// use dome_cloomnik::{dome_class, register_classes, Context, HookResult, Hooks};
+///
+/// struct Counter {
+///     value: f64,
+///     step: f64,
+/// }
+///
+/// #[dome_class(module = "counter")]
+/// impl Counter {
+///     #[foreign(construct)]
+///     fn new(start: f64) -> Self {
+///         Counter { value: start, step: 1.0 }
+///     }
+///
+///     #[foreign]
+///     fn increment(&mut self) -> f64 {
+///         self.value += self.step;
+///         self.value
+///     }
+///
+///     #[foreign]
+///     fn fork(&self) -> Counter {
+///         Counter { value: self.value, step: self.step }
+///     }
+///
+///     #[foreign]
+///     fn add(&mut self, other: &Counter) -> f64 {
+///         self.value += other.value;
+///         self.value
+///     }
+///
+///     #[foreign(setter)]
+///     fn step(&mut self, step: f64) {
+///         self.step = step;
+///     }
+/// }
+///
+/// struct Math;
+///
+/// #[dome_class(module = "counter", source = "static answer { 42 }")]
+/// impl Math {
+///     #[foreign(name = "max")]
+///     fn maximum(a: f64, b: f64) -> f64 {
+///         a.max(b)
+///     }
+///
+///     // Strings and bytes can be borrowed without copying them.
+///     #[foreign]
+///     fn repeat(text: &str, count: usize) -> String {
+///         text.repeat(count)
+///     }
+/// }
+///
+/// fn on_init(mut ctx: Context) -> HookResult {
+///     register_classes!(ctx, Counter, Math)?;
+///     Ok(())
+/// }
+///
+/// let host = MockHost::new();
+/// assert!(host.init(Hooks {
+///     on_init: Some(on_init),
+///     ..Hooks::default()
+/// }));
+/// assert_eq!(
+///     host.module_source("counter").unwrap(),
+///     "foreign class Counter {
+///   construct new(start) {}
+///   foreign increment()
+///   foreign fork()
+///   foreign add(other)
+///   foreign step=(step)
+/// }
+/// class Math {
+///   foreign static max(a, b)
+///   foreign static repeat(text, count)
+/// static answer { 42 }
+/// }
+/// ",
+/// );
+///
+/// // Invalid constructor arguments abort the fiber too.
+/// let result = host.construct("counter", "Counter", &["one".into()]);
+/// assert_eq!(result, Err(Value::from(
+///     "Invalid argument `start`. Argument 1 must be Num, got String."
+/// )));
+///
+/// let counter = host.construct("counter", "Counter", &[Value::Num(1.0)]).unwrap();
+/// host.call("counter", "Counter.step=(_)", &[counter.clone(), Value::Num(10.0)]).unwrap();
+/// let result = host.call("counter", "Counter.increment()", &[counter.clone()]);
+/// assert_eq!(result, Ok(Value::Num(11.0)));
+///
+/// // Returned Rust values become new foreign objects.
+/// let fork = host.call("counter", "Counter.fork()", &[counter.clone()]).unwrap();
+/// host.call("counter", "Counter.increment()", &[fork.clone()]).unwrap();
+/// assert_eq!(host.call("counter", "Counter.increment()", &[fork.clone()]), Ok(Value::Num(31.0)));
+/// assert_eq!(host.call("counter", "Counter.increment()", &[counter.clone()]), Ok(Value::Num(21.0)));
+/// // Storing the result in slot 0 frees the receiver, if nothing else references it.
+/// let temporary = host.construct("counter", "Counter", &[Value::Num(5.0)]).unwrap();
+/// let fork_of_temporary = host.call("counter", "Counter.fork()", [temporary]).unwrap();
+/// assert_eq!(host.call("counter", "Counter.increment()", [fork_of_temporary]), Ok(Value::Num(6.0)));
+///
+/// let result = host.call("counter", "Counter.add(_)", &[counter.clone(), fork]);
+/// assert_eq!(result, Ok(Value::Num(52.0)));
+/// // `counter.add(counter)` would alias `&mut self` and `other`.
+/// let result = host.call("counter", "Counter.add(_)", &[counter.clone(), counter.clone()]);
+/// assert_eq!(
+///     result,
+///     Err(Value::from("Invalid argument `other`. Argument 1 is already borrowed.")),
+/// );
+///
+/// // Invalid arguments abort the fiber.
+/// let result = host.call("counter", "Counter.step=(_)", &[counter, "ten".into()]);
+/// assert_eq!(result, Err(Value::from(
+///     "Invalid argument `step`. Argument 1 must be Num, got String."
+/// )));
+///
+/// let result = host.call("counter", "static Math.max(_,_)", &[Value::Null, 1.0.into(), 2.0.into()]);
+/// assert_eq!(result, Ok(Value::Num(2.0)));
+/// let result = host.call("counter", "static Math.repeat(_,_)", [Value::Null, "ab".into(), 2.0.into()]);
+/// assert_eq!(result, Ok(Value::from("abab")));
+/// ```
+#[macro_export]
+macro_rules! register_classes {
+    ( $ctx:expr, $( $class:ty ),+ $(,)? ) => {
+        $crate::__register_classes(&mut $ctx, &[ $( $crate::__ClassEntry::of::<$class>() ),+ ])
+    };
+}
@@ -0,0 +1,306 @@
+use std::marker::PhantomData;
+
+use super::convert::{element_error, nested_error, scratch_vm, FromWren, ToWren};
+use super::frame::SlotFrame;
+use super::wren::VM;
+use crate::errors::{Error, Result};
+
+/// A view of the `List` in a slot, converting elements from and to Rust values.
+///
+/// Get one with [`WrenVM::list()`][VM::list()]. Elements are moved through scratch slots
+/// reserved with a [`SlotFrame`], so the other slots are left alone.
+///
+/// # Example
+///
+/// ```
+/// use dome_cloomnik::testing::{MockHost, Value};
+///
+/// let host = MockHost::new();
+/// let scores = Value::list(vec![Value::Num(3.0), Value::Num(5.0)]);
+/// let result = host.with_vm(&[scores.clone()], |vm| {
+///     let mut scores = vm.list(0);
+///     scores.push(8);
+///     scores.set(0, 4);
+///     scores.insert(0, 1);
+///     assert_eq!(scores.len(), 4);
+///     assert_eq!(scores.get::<u32>(3), 8);
+///     assert!(scores.try_get::<u32>(4).is_err());
+///     scores.iter::<u32>().sum::<Result<u32, _>>()
+/// });
+/// assert_eq!(result.unwrap().unwrap(), 18);
+///
+/// let result = host.with_vm(&[scores], |vm| {
+///     vm.list(0).set(1, "five");
+///     vm.list(0).iter::<u32>().collect::<Result<Vec<_>, _>>().unwrap_err().to_string()
+/// });
+/// assert_eq!(result, Ok("Slot 0 is invalid: element 1 must be Num, got String.".to_owned()));
+/// ```
+#[derive(Debug)]
+pub struct WrenList<'a> {
+    vm: VM<'a>,
+    slot: usize,
+    _vm: PhantomData<&'a mut VM<'a>>,
+}
+
+impl<'a> WrenList<'a> {
+    #[inline]
+    pub(crate) fn new(vm: &'a mut VM<'_>, slot: usize) -> Self {
+        Self {
+            // SAFETY: We borrow the VM mutably, so nobody else uses it while we are alive.
+            vm: unsafe { scratch_vm(vm) },
+            slot,
+            _vm: PhantomData,
+        }
+    }
+
+    /// The slot containing the list.
+    #[inline]
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
+
+    /// The number of elements in the list.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.vm.get_list_count(self.slot)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reads the element at `index` as a Rust value of type `T`.
+    ///
+    /// Panics if `index` is out of bounds or the element is not convertible to `T`.
+    #[inline]
+    pub fn get<T: FromWren>(&self, index: usize) -> T {
+        self.try_get(index).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Reads the element at `index` as a Rust value of type `T`, or returns an error if
+    /// `index` is out of bounds or the element is not convertible to `T`.
+    pub fn try_get<T: FromWren>(&self, index: usize) -> Result<T> {
+        let count = self.len();
+        if index >= count {
+            return Err(Error::ListIndexOutOfBounds {
+                slot: self.slot,
+                index,
+                count,
+            });
+        }
+        // SAFETY: Only this function uses the frame, and it returns owned values.
+        let mut frame = unsafe { SlotFrame::scratch(&self.vm) };
+        let element_slot = frame.slot();
+        frame.get_list_element(self.slot, index, element_slot);
+        T::from_wren(&frame, element_slot).map_err(|err| element_error(err, self.slot, index))
+    }
+
+    /// Replaces the element at `index` with the Rust `value`.
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn set<T: ToWren>(&mut self, index: usize, value: T) {
+        let mut frame = self.vm.slot_frame();
+        let element_slot = frame.slot();
+        value.to_wren(&mut frame, element_slot);
+        frame.set_list_element(self.slot, index, element_slot);
+    }
+
+    /// Inserts the Rust `value` at `index`, shifting the elements after it.
+    ///
+    /// Panics if `index` is greater than the length of the list.
+    pub fn insert<T: ToWren>(&mut self, index: usize, value: T) {
+        let mut frame = self.vm.slot_frame();
+        let element_slot = frame.slot();
+        value.to_wren(&mut frame, element_slot);
+        frame.insert_in_list(self.slot, index, element_slot);
+    }
+
+    /// Appends the Rust `value` to the list.
+    #[inline]
+    pub fn push<T: ToWren>(&mut self, value: T) {
+        self.insert(self.len(), value)
+    }
+
+    /// Iterates over the elements, reading each as a Rust value of type `T`.
+    #[inline]
+    pub fn iter<T: FromWren>(&self) -> ListIter<'_, T> {
+        // SAFETY: Only this function uses the frame, and it returns owned values.
+        let mut frame = unsafe { SlotFrame::scratch(&self.vm) };
+        let element_slot = frame.slot();
+        ListIter {
+            count: self.len(),
+            frame,
+            list_slot: self.slot,
+            element_slot,
+            index: 0,
+            _element: PhantomData,
+        }
+    }
+}
+
+/// An iterator over the elements of a [`WrenList`], created by [`WrenList::iter()`].
+#[derive(Debug)]
+pub struct ListIter<'a, T> {
+    frame: SlotFrame<'a>,
+    list_slot: usize,
+    element_slot: usize,
+    index: usize,
+    count: usize,
+    _element: PhantomData<fn() -> T>,
+}
+
+impl<T: FromWren> Iterator for ListIter<'_, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index == self.count {
+            return None;
+        }
+        let index = self.index;
+        self.index += 1;
+        self.frame
+            .get_list_element(self.list_slot, index, self.element_slot);
+        Some(
+            T::from_wren(&self.frame, self.element_slot)
+                .map_err(|err| element_error(err, self.list_slot, index)),
+        )
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.count - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T: FromWren> ExactSizeIterator for ListIter<'_, T> {}
+
+/// A view of the `Map` in a slot, converting keys and values from and to Rust values.
+///
+/// Get one with [`WrenVM::map()`][VM::map()]. Keys and values are moved through scratch
+/// slots reserved with a [`SlotFrame`], so the other slots are left alone.
+///
+/// Keys must be `Null`, `Bool`, `Num` or `String`; the methods panic otherwise. Wren's
+/// embedding API cannot enumerate the keys of a map, so there is no iterator.
+///
+/// # Example
+///
+/// ```
+/// use dome_cloomnik::testing::{MockHost, Value};
+///
+/// let host = MockHost::new();
+/// let stats = Value::map(vec![("hp".into(), Value::Num(10.0))]);
+/// let result = host.with_vm(&[stats], |vm| {
+///     let mut stats = vm.map(0);
+///     stats.insert("mp", 4);
+///     assert!(stats.contains_key("mp"));
+///     assert_eq!(stats.get::<_, f64>("hp"), Some(10.0));
+///     assert_eq!(stats.get::<_, f64>("xp"), None);
+///     assert_eq!(stats.remove::<_, f64>("hp"), Some(10.0));
+///     assert_eq!(
+///         stats.try_get::<_, String>("mp").unwrap_err().to_string(),
+///         "Slot 0 is invalid: map value must be String, got Num.",
+///     );
+///     stats.len()
+/// });
+/// assert_eq!(result, Ok(1));
+/// ```
+#[derive(Debug)]
+pub struct WrenMap<'a> {
+    vm: VM<'a>,
+    slot: usize,
+    _vm: PhantomData<&'a mut VM<'a>>,
+}
+
+impl<'a> WrenMap<'a> {
+    #[inline]
+    pub(crate) fn new(vm: &'a mut VM<'_>, slot: usize) -> Self {
+        Self {
+            // SAFETY: We borrow the VM mutably, so nobody else uses it while we are alive.
+            vm: unsafe { scratch_vm(vm) },
+            slot,
+            _vm: PhantomData,
+        }
+    }
+
+    /// The slot containing the map.
+    #[inline]
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
+
+    /// The number of entries in the map.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.vm.get_map_count(self.slot)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the map contains the Rust `key`.
+    pub fn contains_key<K: ToWren>(&self, key: K) -> bool {
+        // SAFETY: Only this function uses the frame, and it returns owned values.
+        let mut frame = unsafe { SlotFrame::scratch(&self.vm) };
+        let key_slot = frame.slot();
+        key.to_wren(&mut frame, key_slot);
+        frame.map_contains_key(self.slot, key_slot)
+    }
+
+    /// Reads the value of `key` as a Rust value of type `V`, or `None` if the map does not
+    /// contain `key`.
+    ///
+    /// Panics if the value is not convertible to `V`.
+    #[inline]
+    pub fn get<K: ToWren, V: FromWren>(&self, key: K) -> Option<V> {
+        self.try_get(key).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Reads the value of `key` as a Rust value of type `V`, or `None` if the map does not
+    /// contain `key`. Returns an error if the value is not convertible to `V`.
+    pub fn try_get<K: ToWren, V: FromWren>(&self, key: K) -> Result<Option<V>> {
+        // SAFETY: Only this function uses the frame, and it returns owned values.
+        let mut frame = unsafe { SlotFrame::scratch(&self.vm) };
+        let key_slot = frame.slot();
+        let value_slot = frame.slot();
+        key.to_wren(&mut frame, key_slot);
+        if !frame.map_contains_key(self.slot, key_slot) {
+            return Ok(None);
+        }
+        frame.get_map_value(self.slot, key_slot, value_slot);
+        V::from_wren(&frame, value_slot)
+            .map(Some)
+            .map_err(|err| nested_error(err, self.slot, format_args!("map value")))
+    }
+
+    /// Sets the value of the Rust `key` to the Rust `value`.
+    pub fn insert<K: ToWren, V: ToWren>(&mut self, key: K, value: V) {
+        let mut frame = self.vm.slot_frame();
+        let key_slot = frame.slot();
+        let value_slot = frame.slot();
+        key.to_wren(&mut frame, key_slot);
+        value.to_wren(&mut frame, value_slot);
+        frame.set_map_value(self.slot, key_slot, value_slot);
+    }
+
+    /// Removes `key` from the map, and returns its value as a Rust value of type `V`,
+    /// or `None` if the map did not contain `key`. Use [`WrenValue`][crate::WrenValue]
+    /// for `V` to accept any value.
+    ///
+    /// Panics if the value is not convertible to `V`. It is removed anyway.
+    pub fn remove<K: ToWren, V: FromWren>(&mut self, key: K) -> Option<V> {
+        let map_slot = self.slot;
+        let mut frame = self.vm.slot_frame();
+        let key_slot = frame.slot();
+        let value_slot = frame.slot();
+        key.to_wren(&mut frame, key_slot);
+        if !frame.map_contains_key(map_slot, key_slot) {
+            return None;
+        }
+        frame.remove_map_value(map_slot, key_slot, value_slot);
+        let value = V::from_wren(&frame, value_slot)
+            .map_err(|err| nested_error(err, map_slot, format_args!("map value")));
+        Some(value.unwrap_or_else(|err| panic!("{}", err)))
+    }
+}
@@ -0,0 +1,400 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::BuildHasher;
+
+use super::frame::SlotFrame;
+use super::wren::{Handle, Type, VM};
+use crate::errors::{Error, Result};
+
+/// A Rust type that can be read from a Wren slot.
+///
+/// Use it via [`VM::get()`][crate::WrenVM::get()].
+///
+/// Conversions fail if the slot does not contain a value of the expected type,
+/// or if the value does not fit the Rust type (e.g. `1.5` is not a valid `i32`).
+///
+/// `u8` does not implement this trait, so that `Vec<u8>` can be read from a Wren `String`.
+/// Wren's embedding API cannot enumerate the keys of a `Map`, so `HashMap` and `BTreeMap`
+/// only implement [`ToWren`]. To read a map, look up its keys with a
+/// [`WrenMap`][crate::WrenMap] (see [`VM::map()`][crate::WrenVM::map()]), or, with the
+/// `serde` feature, read it into a struct with `Serde<T>`.
+///
+/// # Example
+///
+/// ```
+/// use dome_cloomnik::testing::{MockHost, Value};
+///
+/// let host = MockHost::new();
+/// let two_pow_63 = 9_223_372_036_854_775_808.0;
+/// let two_pow_64 = 18_446_744_073_709_551_616.0;
+/// let read = |value: f64| {
+///     host.with_vm(&[Value::Num(value)], |vm| {
+///         (vm.try_get::<i64>(0).ok(), vm.try_get::<u64>(0).ok())
+///     })
+/// };
+/// assert_eq!(read(-two_pow_63), Ok((Some(i64::MIN), None)));
+/// assert_eq!(read(two_pow_63), Ok((None, Some(1 << 63))));
+/// // Doubles cannot represent `u64::MAX`, and `2^64` is out of range.
+/// assert_eq!(read(two_pow_64), Ok((None, None)));
+/// assert_eq!(read(1.5), Ok((None, None)));
+/// ```
+pub trait FromWren: Sized {
+    /// Reads the value in `slot`.
+    ///
+    /// Implementations that need scratch slots reserve them with a [`SlotFrame`].
+    fn from_wren(vm: &VM, slot: usize) -> Result<Self>;
+}
+
+/// A Rust type that can be stored in a Wren slot.
+///
+/// Use it via [`VM::set()`][crate::WrenVM::set()].
+///
+/// `u8` does not implement this trait, so that `[u8]` and `Vec<u8>` can be stored as a Wren
+/// `String`. `()` does not implement it either, so that foreign methods returning `()`
+/// leave slot 0 alone.
+pub trait ToWren {
+    /// Stores `self` in `slot`.
+    ///
+    /// Implementations that need scratch slots reserve them with a [`SlotFrame`].
+    fn to_wren(&self, vm: &mut VM, slot: usize);
+}
+
+/// Reports errors in list elements in terms of the list slot, since the element slot
+/// is only a scratch slot.
+pub(super) fn element_error(error: Error, slot: usize, index: usize) -> Error {
+    nested_error(error, slot, format_args!("element {}", index))
+}
+
+/// Reports errors in a value nested in `slot`, described by `what`, in terms of `slot`.
+pub(super) fn nested_error(error: Error, slot: usize, what: fmt::Arguments<'_>) -> Error {
+    let reason = match error {
+        Error::SlotTypeMismatch {
+            expected, actual, ..
+        } => format!("{} must be {}, got {}", what, expected, actual),
+        Error::SlotConversionFailed { reason, .. } => format!("{}: {}", what, reason),
+        error => return error,
+    };
+    Error::SlotConversionFailed { slot, reason }
+}
+
+/// Reading lists and handles requires a mutable VM, but it only writes to scratch slots,
+/// which the caller does not use and which are never reserved below a borrowed string. So
+/// we allow it even with a shared reference to the VM.
+///
+/// # Safety
+///
+/// The copy must only write to slots nobody else uses while it is alive, e.g. the slots of a
+/// [`SlotFrame`], and must not change which values the slots in use hold.
+#[inline]
+pub(crate) unsafe fn scratch_vm<'a>(vm: &'a VM<'_>) -> VM<'a> {
+    VM::from_raw(vm.0)
+}
+
+impl FromWren for () {
+    #[inline]
+    fn from_wren(vm: &VM, slot: usize) -> Result<Self> {
+        match vm.try_get_slot_type(slot)? {
+            Type::Null => Ok(()),
+            actual => Err(Error::SlotTypeMismatch {
+                slot,
+                expected: Type::Null,
+                actual,
+            }),
+        }
+    }
+}
+
+impl FromWren for bool {
+    #[inline]
+    fn from_wren(vm: &VM, slot: usize) -> Result<Self> {
+        vm.try_get_slot_bool(slot)
+    }
+}
+impl ToWren for bool {
+    #[inline]
+    fn to_wren(&self, vm: &mut VM, slot: usize) {
+        vm.set_slot_bool(slot, *self)
+    }
+}
+
+impl FromWren for f64 {
+    #[inline]
+    fn from_wren(vm: &VM, slot: usize) -> Result<Self> {
+        vm.try_get_slot_double(slot)
+    }
+}
+impl ToWren for f64 {
+    #[inline]
+    fn to_wren(&self, vm: &mut VM, slot: usize) {
+        vm.set_slot_double(slot, *self)
+    }
+}
+
+impl FromWren for f32 {
+    #[inline]
+    fn from_wren(vm: &VM, slot: usize) -> Result<Self> {
+        Ok(vm.try_get_slot_double(slot)? as f32)
+    }
+}
+impl ToWren for f32 {
+    #[inline]
+    fn to_wren(&self, vm: &mut VM, slot: usize) {
+        vm.set_slot_double(slot, f64::from(*self))
+    }
+}
+
+// Wren only has doubles. Reading an integer requires the number to be integral and in range;
+// writing an integer that does not fit in a double loses precision.
+macro_rules! impl_integer {
+    ( $( $type:ty ),* ) => {
+        $(
+            impl FromWren for $type {
+                #[inline]
+                fn from_wren(vm: &VM, slot: usize) -> Result<Self> {
+                    let value = vm.try_get_slot_double(slot)?;
+                    // `MAX + 1` is a power of two, so it is exact even when `MAX` is not. Casts
+                    // from doubles saturate, so we cannot just compare after casting back.
+                    let in_range =
+                        value >= <$type>::MIN as f64 && value < <$type>::MAX as f64 + 1.0;
+                    if in_range && value.fract() == 0.0 {
+                        Ok(value as $type)
+                    } else {
+                        Err(Error::SlotConversionFailed {
+                            slot,
+                            reason: format!("{} is not a valid {}", value, stringify!($type)),
+                        })
+                    }
+                }
+            }
+            impl ToWren for $type {
+                #[inline]
+                fn to_wren(&self, vm: &mut VM, slot: usize) {
+                    vm.set_slot_double(slot, *self as f64)
+                }
+            }
+        )*
+    };
+}
+impl_integer!(i8, i16, i32, i64, isize, u16, u32, u64, usize);
+
+impl FromWren for String {
+    #[inline]
+    fn from_wren(vm: &VM, slot: usize) -> Result<Self> {
+        vm.try_get_slot_string(slot)
+    }
+}
+impl ToWren for str {
+    #[inline]
+    fn to_wren(&self, vm: &mut VM, slot: usize) {
+        vm.set_slot_string(slot, self)
+    }
+}
+impl ToWren for String {
+    #[inline]
+    fn to_wren(&self, vm: &mut VM, slot: usize) {
+        vm.set_slot_string(slot, self)
+    }
+}
+
+impl FromWren for Vec<u8> {
+    #[inline]
+    fn from_wren(vm: &VM, slot: usize) -> Result<Self> {
+        vm.try_get_slot_bytes(slot)
+    }
+}
+impl ToWren for [u8] {
+    #[inline]
+    fn to_wren(&self, vm: &mut VM, slot: usize) {
+        vm.set_slot_bytes(slot, self)
+    }
+}
+impl ToWren for Vec<u8> {
+    #[inline]
+    fn to_wren(&self, vm: &mut VM, slot: usize) {
+        vm.set_slot_bytes(slot, self)
+    }
+}
+
+impl<T: FromWren> FromWren for Option<T> {
+    #[inline]
+    fn from_wren(vm: &VM, slot: usize) -> Result<Self> {
+        match vm.try_get_slot_type(slot)? {
+            Type::Null => Ok(None),
+            _ => T::from_wren(vm, slot).map(Some),
+        }
+    }
+}
+impl<T: ToWren> ToWren for Option<T> {
+    #[inline]
+    fn to_wren(&self, vm: &mut VM, slot: usize) {
+        match self {
+            Some(value) => value.to_wren(vm, slot),
+            None => vm.set_slot_null(slot),
+        }
+    }
+}
+
+impl<T: FromWren> FromWren for Vec<T> {
+    fn from_wren(vm: &VM, slot: usize) -> Result<Self> {
+        let count = vm.try_get_list_count(slot)?;
+        // SAFETY: Only this function uses the frame, and it returns owned values.
+        let mut frame = unsafe { SlotFrame::scratch(vm) };
+        let element_slot = frame.slot();
+        (0..count)
+            .map(|index| {
+                frame.get_list_element(slot, index, element_slot);
+                T::from_wren(&frame, element_slot).map_err(|err| element_error(err, slot, index))
+            })
+            .collect()
+    }
+}
+impl<T: ToWren> ToWren for [T] {
+    fn to_wren(&self, vm: &mut VM, slot: usize) {
+        let mut frame = vm.slot_frame();
+        let element_slot = frame.slot();
+        frame.set_slot_new_list(slot);
+        for (index, element) in self.iter().enumerate() {
+            element.to_wren(&mut frame, element_slot);
+            frame.insert_in_list(slot, index, element_slot);
+        }
+    }
+}
+impl<T: ToWren> ToWren for Vec<T> {
+    #[inline]
+    fn to_wren(&self, vm: &mut VM, slot: usize) {
+        self.as_slice().to_wren(vm, slot)
+    }
+}
+
+pub(super) fn map_to_wren<'a, K: ToWren + 'a, V: ToWren + 'a>(
+    vm: &mut VM,
+    slot: usize,
+    entries: impl Iterator<Item = (&'a K, &'a V)>,
+) {
+    let mut frame = vm.slot_frame();
+    let key_slot = frame.slot();
+    let value_slot = frame.slot();
+    frame.set_slot_new_map(slot);
+    for (key, value) in entries {
+        key.to_wren(&mut frame, key_slot);
+        value.to_wren(&mut frame, value_slot);
+        frame.set_map_value(slot, key_slot, value_slot);
+    }
+}
+
+impl<K: ToWren, V: ToWren, S: BuildHasher> ToWren for HashMap<K, V, S> {
+    #[inline]
+    fn to_wren(&self, vm: &mut VM, slot: usize) {
+        map_to_wren(vm, slot, self.iter())
+    }
+}
+impl<K: ToWren, V: ToWren> ToWren for BTreeMap<K, V> {
+    #[inline]
+    fn to_wren(&self, vm: &mut VM, slot: usize) {
+        map_to_wren(vm, slot, self.iter())
+    }
+}
+
+// Tuples are represented as lists of fixed length.
+macro_rules! impl_tuple {
+    ( $count:literal; $( $name:ident : $index:tt ),+ ) => {
+        impl<$( $name: FromWren ),+> FromWren for ( $( $name, )+ ) {
+            fn from_wren(vm: &VM, slot: usize) -> Result<Self> {
+                let count = vm.try_get_list_count(slot)?;
+                if count != $count {
+                    return Err(Error::SlotConversionFailed {
+                        slot,
+                        reason: format!("expected a list of {} elements, got {}", $count, count),
+                    });
+                }
+                // SAFETY: Only this function uses the frame, and it returns owned values.
+                let mut frame = unsafe { SlotFrame::scratch(vm) };
+                let element_slot = frame.slot();
+                Ok(( $( {
+                    frame.get_list_element(slot, $index, element_slot);
+                    $name::from_wren(&frame, element_slot)
+                        .map_err(|err| element_error(err, slot, $index))?
+                }, )+ ))
+            }
+        }
+        impl<$( $name: ToWren ),+> ToWren for ( $( $name, )+ ) {
+            fn to_wren(&self, vm: &mut VM, slot: usize) {
+                let mut frame = vm.slot_frame();
+                let element_slot = frame.slot();
+                frame.set_slot_new_list(slot);
+                $(
+                    self.$index.to_wren(&mut frame, element_slot);
+                    frame.insert_in_list(slot, $index, element_slot);
+                )+
+            }
+        }
+    };
+}
+impl_tuple!(1; A: 0);
+impl_tuple!(2; A: 0, B: 1);
+impl_tuple!(3; A: 0, B: 1, C: 2);
+impl_tuple!(4; A: 0, B: 1, C: 2, D: 3);
+impl_tuple!(5; A: 0, B: 1, C: 2, D: 3, E: 4);
+impl_tuple!(6; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+impl_tuple!(7; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+impl_tuple!(8; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+
+impl FromWren for Handle {
+    #[inline]
+    fn from_wren(vm: &VM, slot: usize) -> Result<Self> {
+        vm.try_get_slot_type(slot)?;
+        // SAFETY: Creating a handle does not write to any slot.
+        Ok(unsafe { scratch_vm(vm) }.get_slot_handle(slot))
+    }
+}
+impl ToWren for Handle {
+    #[inline]
+    fn to_wren(&self, vm: &mut VM, slot: usize) {
+        vm.set_slot_handle(slot, self)
+    }
+}
+
+impl<T: ToWren + ?Sized> ToWren for &T {
+    #[inline]
+    fn to_wren(&self, vm: &mut VM, slot: usize) {
+        (**self).to_wren(vm, slot)
+    }
+}
+
+/// The return type of a foreign method.
+///
+/// `()` leaves slot 0 as is. Values that implement [`ToWren`] are stored in slot 0, and so are
+/// instances of classes declared with [`dome_class`][crate::dome_class] (as new foreign
+/// objects). `Ok` values of a `Result` are handled the same way, and errors abort the fiber
+/// with their message. The alternate representation (`{:#}`) of the error is used, so
+/// `anyhow::Error`s include their causes.
+///
+/// Types registered with [`register_modules!`][crate::register_modules!] cannot be returned:
+/// their methods create instances with [`WrenVM::new_foreign()`][crate::WrenVM::new_foreign()]
+/// instead.
+#[doc(hidden)]
+pub trait ForeignReturn {
+    fn store(self, vm: &mut VM);
+}
+
+impl ForeignReturn for () {
+    #[inline]
+    fn store(self, _vm: &mut VM) {}
+}
+
+impl<T: ToWren> ForeignReturn for T {
+    #[inline]
+    fn store(self, vm: &mut VM) {
+        self.to_wren(vm, 0)
+    }
+}
+
+impl<T: ForeignReturn, E: fmt::Display> ForeignReturn for std::result::Result<T, E> {
+    #[inline]
+    fn store(self, vm: &mut VM) {
+        match self {
+            Ok(value) => value.store(vm),
+            Err(error) => vm.abort_fiber_with(format_args!("{:#}", error)),
+        }
+    }
+}
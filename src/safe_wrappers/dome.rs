@@ -3,6 +3,7 @@ use std::marker::PhantomData;
 use std::mem;
 
 use super::audio;
+use super::registry::{self, Upcast};
 use super::wren;
 use crate::errors::{Error, Result};
 use crate::unsafe_wrappers::dome as unsafe_dome;
@@ -37,10 +38,12 @@ impl Context<'_> {
     /// # Example
     ///
     /// ```
-    /// # let ctx: Context;
+    /// # let host = dome_cloomnik::testing::MockHost::new();
+    /// # let mut ctx = host.context();
     /// ctx.register_module("my-module", r"
     ///     class MyClass {}
     /// ")?;
+    /// # Ok::<(), dome_cloomnik::Error>(())
     /// ```
     #[inline]
     pub fn register_module(&mut self, name: &str, source: &str) -> Result {
@@ -65,8 +68,8 @@ impl Context<'_> {
     ///
     /// # Safety
     ///
-    /// This function is unsafe because it is forbidden to register a function that may panic,
-    /// and/or store the VM instance it gets for later use.
+    /// This function is unsafe because it is forbidden to register a function that may panic.
+    /// The VM it gets cannot be stored for later use, since it only lives as long as the call.
     ///
     /// Doing so may lead to Undefined Behavior.
     ///
@@ -76,7 +79,9 @@ impl Context<'_> {
     /// # Example
     ///
     /// ```
-    /// # let ctx: Context;
+    /// # use dome_cloomnik::WrenVM;
+    /// # let host = dome_cloomnik::testing::MockHost::new();
+    /// # let mut ctx = host.context();
     /// ctx.register_module("my-module", r"
     ///     class MyClass {
     ///         foreign myGetter
@@ -86,6 +91,7 @@ impl Context<'_> {
     /// # unsafe {
     /// ctx.register_fn("my-module", "MyClass.myGetter", my_fn)?;
     /// # }
+    /// # Ok::<(), dome_cloomnik::Error>(())
     /// ```
     #[inline]
     pub unsafe fn register_fn(
@@ -100,7 +106,7 @@ impl Context<'_> {
             self.0,
             c_module.as_ptr(),
             c_signature.as_ptr(),
-            mem::transmute(method),
+            mem::transmute::<ForeignFn, unsafe_dome::ForeignFn>(method),
         )
         .to_result(|| Error::MethodRegistrationFailed {
             module_name: module.to_owned(),
@@ -115,8 +121,8 @@ impl Context<'_> {
     /// # Safety
     ///
     /// This function is unsafe because it is forbidden to register a function that may panic,
-    /// and/or store the VM instance it gets for later use, and/or an allocator that does not
-    /// call [`WrenVM::set_slot_new_raw_foreign_unchecked()`] or [`WrenVM::set_slot_new_foreign_unchecked()`].
+    /// and/or an allocator that does not call [`WrenVM::set_slot_new_raw_foreign_unchecked()`]
+    /// or [`WrenVM::set_slot_new_foreign_unchecked()`].
     ///
     /// Doing so may lead to Undefined Behavior.
     ///
@@ -126,7 +132,9 @@ impl Context<'_> {
     /// # Example
     ///
     /// ```
-    /// # let ctx: Context;
+    /// # use dome_cloomnik::WrenVM;
+    /// # let host = dome_cloomnik::testing::MockHost::new();
+    /// # let mut ctx = host.context();
     /// ctx.register_module("my-module", r"
     ///     foreign class MyClass {
     ///         construct new() {}
@@ -137,6 +145,7 @@ impl Context<'_> {
     /// # unsafe {
     /// ctx.register_class("my-module", "MyClass", allocate, Some(finalize))?;
     /// # }
+    /// # Ok::<(), dome_cloomnik::Error>(())
     /// ```
     #[inline]
     pub unsafe fn register_class(
@@ -152,7 +161,7 @@ impl Context<'_> {
             self.0,
             c_module_name.as_ptr(),
             c_class_name.as_ptr(),
-            mem::transmute(allocate),
+            mem::transmute::<ForeignFn, unsafe_dome::ForeignFn>(allocate),
             finalize,
         )
         .to_result(|| Error::ClassRegistrationFailed {
@@ -161,6 +170,80 @@ impl Context<'_> {
         })
     }
 
+    /// Declares that Rust foreign objects of type `T` can be used where objects of type `Base`
+    /// are expected. See [`Upcast`] for more.
+    ///
+    /// Wren does not allow classes to inherit from foreign classes, so this relationship
+    /// exists only on the Rust side: a foreign class `Circle` whose objects are `Circle`s
+    /// can be passed to foreign methods expecting a `Shape`, and its objects can be borrowed
+    /// as `Shape`s.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use dome_cloomnik::testing::{MockHost, Value};
+    /// use dome_cloomnik::{register_modules, ForeignRef, Upcast, WrenVM};
+    ///
+    /// struct Shape {
+    ///     name: String,
+    /// }
+    /// impl Shape {
+    ///     fn new(vm: &WrenVM) -> Self {
+    ///         Shape { name: vm.get_slot_string(1).unwrap() }
+    ///     }
+    ///     fn describe(shape: ForeignRef<Shape>) -> String {
+    ///         format!("a shape named {}", shape.name)
+    ///     }
+    /// }
+    ///
+    /// struct Circle {
+    ///     shape: Shape,
+    ///     radius: f64,
+    /// }
+    /// impl Circle {
+    ///     fn new(vm: &WrenVM) -> Self {
+    ///         let shape = Shape { name: "circle".to_owned() };
+    ///         Circle { shape, radius: vm.get_slot_double(1) }
+    ///     }
+    /// }
+    /// impl Upcast<Shape> for Circle {
+    ///     fn upcast(&self) -> &Shape {
+    ///         &self.shape
+    ///     }
+    ///     fn upcast_mut(&mut self) -> &mut Shape {
+    ///         &mut self.shape
+    ///     }
+    /// }
+    ///
+    /// let host = MockHost::new();
+    /// let mut ctx = host.context();
+    /// (register_modules! {
+    ///     ctx,
+    ///     module "shapes" {
+    ///         foreign class Shape = new of Shape {
+    ///             "construct new(name) {}"
+    ///             foreign static describe(shape) = describe
+    ///         }
+    ///         foreign class Circle = new of Circle {
+    ///             "construct new(radius) {}"
+    ///         }
+    ///     }
+    /// })?;
+    /// ctx.register_upcast::<Circle, Shape>();
+    ///
+    /// let circle = host.construct("shapes", "Circle", &[Value::Num(1.0)]).unwrap();
+    /// let result = host.call("shapes", "static Shape.describe(_)", &[Value::Null, circle.clone()]);
+    /// assert_eq!(result, Ok(Value::from("a shape named circle")));
+    ///
+    /// let name = host.with_vm(&[circle], |vm| unsafe { vm.get_slot_foreign::<Shape>(0) }.name.clone());
+    /// assert_eq!(name, Ok("circle".to_owned()));
+    /// # Ok::<(), dome_cloomnik::Error>(())
+    /// ```
+    #[inline]
+    pub fn register_upcast<T: Upcast<Base>, Base: 'static>(&mut self) {
+        registry::register_upcast::<T, Base>()
+    }
+
     /// Locks a module, preventing extending it later.
     ///
     /// It is recommended to lock all modules after you finished to register all
@@ -245,8 +328,44 @@ impl Context<'_> {
 ///    This way, you run the destructor automatically, and the object
 ///    won't be closed again.
 ///
+/// Foreign methods take the receiver first (for instance methods of foreign classes), by
+/// shared or mutable reference. Then they may take the VM (`&WrenVM` or `&mut WrenVM`).
+/// Then they take the arguments of the Wren method, each of a type implementing
+/// [`FromWren`][crate::FromWren], a `&str` or `&[u8]` borrowing a Wren `String`, or a
+/// [`ForeignRef`][crate::ForeignRef] or [`ForeignRefMut`][crate::ForeignRefMut] borrowing
+/// a Rust foreign object. The receiver is
+/// borrowed the same way, so passing an object twice to a method that borrows it mutably
+/// fails instead of creating aliasing references. The arguments are validated before the
+/// method is called, and invalid arguments abort the fiber with an error naming the parameter.
+/// A method that takes the VM may instead read all of the arguments itself, and then it takes
+/// none of them as parameters. Any other mismatch with the arity of the Wren method is a
+/// compile-time error.
+///
+/// Foreign methods may return `()`, and then their return value is whatever they stored in
+/// slot 0 (by default, the receiver). They can also return `Result<T, E>` where
+/// `E: Display`: `Ok(value)` is stored in slot 0 using [`ToWren`][crate::ToWren]
+/// (`Ok(())` leaves slot 0 alone), and `Err(error)` aborts the fiber with the error message.
+/// The error is formatted with `{:#}`, so [`anyhow::Error`]s include their causes. Methods
+/// can also return any [`ToWren`][crate::ToWren] value directly, which is then stored in
+/// slot 0. To return new instances of foreign classes, either store them with
+/// [`WrenVM::new_foreign()`][crate::WrenVM::new_foreign()], or declare the classes with
+/// [`dome_class`][crate::dome_class].
+///
+/// Constructors of foreign classes take the VM and return the Rust value, or `Result<T, E>`
+/// where `E: Display` to reject bad arguments. When a constructor returns an error or panics,
+/// the fiber is aborted. Wren still requires the object to be created, so it is left
+/// unconstructed: no Rust value is stored in it, and its finalizer does nothing. Since the
+/// fiber is aborted, Wren code never gets hold of it.
+///
+/// Each Rust type can back only one foreign class, which
+/// [`WrenVM::new_foreign()`][crate::WrenVM::new_foreign()] creates its instances with.
+/// Registering another class with the same type fails.
+///
 /// # Example
 /// ```rust
+/// # use dome_cloomnik::{register_modules, WrenVM};
+/// # let host = dome_cloomnik::testing::MockHost::new();
+/// # let mut ctx = host.context();
 /// struct MyType;
 /// impl MyType {
 ///     fn new(_vm: &WrenVM) -> Self {
@@ -268,6 +387,7 @@ impl Context<'_> {
 ///     }
 /// }
 /// mod non_foreign {
+/// #   use dome_cloomnik::WrenVM;
 ///     pub(super) struct SomeOtherClass;
 ///     impl SomeOtherClass {
 ///         pub(super) fn foreign_getter(vm: &mut WrenVM) {}
@@ -300,6 +420,119 @@ impl Context<'_> {
 ///     }
 ///     module "my-second-module" {}
 /// })?;
+/// # Ok::<(), dome_cloomnik::Error>(())
+/// ```
+///
+/// Typed parameters and returning results:
+/// ```rust
+/// use anyhow::Context as _;
+/// use dome_cloomnik::testing::{MockHost, Value};
+/// use dome_cloomnik::register_modules;
+///
+/// struct Parser;
+/// impl Parser {
+///     fn parse(text: String) -> anyhow::Result<f64> {
+///         let number = text
+///             .parse::<f64>()
+///             .with_context(|| format!("cannot parse `{}`", text))?;
+///         Ok(number)
+///     }
+/// }
+///
+/// let host = MockHost::new();
+/// let mut ctx = host.context();
+/// (register_modules! {
+///     ctx,
+///     module "parser" {
+///         class Parser = Parser {
+///             foreign static parse(text) = parse
+///         }
+///     }
+/// })?;
+///
+/// let result = host.call("parser", "static Parser.parse(_)", &[Value::Null, "1.5".into()]);
+/// assert_eq!(result, Ok(Value::Num(1.5)));
+/// let result = host.call("parser", "static Parser.parse(_)", &[Value::Null, "one".into()]);
+/// assert_eq!(result, Err(Value::from("cannot parse `one`: invalid float literal")));
+/// let result = host.call("parser", "static Parser.parse(_)", &[Value::Null, 1.0.into()]);
+/// assert_eq!(
+///     result,
+///     Err(Value::from("Invalid argument `text`. Argument 1 must be String, got Num.")),
+/// );
+/// # Ok::<(), dome_cloomnik::Error>(())
+/// ```
+///
+/// Fallible constructors:
+/// ```rust
+/// use dome_cloomnik::testing::{MockHost, Value};
+/// use dome_cloomnik::{register_modules, WrenVM};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// static DROPPED: AtomicUsize = AtomicUsize::new(0);
+///
+/// struct Temperature(f64);
+/// impl Temperature {
+///     fn new(vm: &WrenVM) -> Result<Self, String> {
+///         let kelvin = vm.try_get_slot_double(1).map_err(|err| err.to_string())?;
+///         if kelvin < 0.0 {
+///             return Err(format!("{} K is below absolute zero", kelvin));
+///         }
+///         Ok(Temperature(kelvin))
+///     }
+/// }
+/// impl Drop for Temperature {
+///     fn drop(&mut self) {
+///         DROPPED.fetch_add(1, Ordering::SeqCst);
+///     }
+/// }
+///
+/// let host = MockHost::new();
+/// let mut ctx = host.context();
+/// (register_modules! {
+///     ctx,
+///     module "temperature" {
+///         foreign class Temperature = new of Temperature {
+///             "construct new(kelvin) {}"
+///         }
+///     }
+/// })?;
+///
+/// let result = host.construct("temperature", "Temperature", &[Value::Num(-1.0)]);
+/// assert_eq!(result, Err(Value::from("-1 K is below absolute zero")));
+/// // The unconstructed object is released, but there is nothing to drop.
+/// assert_eq!(DROPPED.load(Ordering::SeqCst), 0);
+///
+/// let temperature = host.construct("temperature", "Temperature", &[Value::Num(300.0)]).unwrap();
+/// drop(temperature);
+/// assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+/// # Ok::<(), dome_cloomnik::Error>(())
+/// ```
+///
+/// A Rust type backs a single class:
+/// ```rust
+/// use dome_cloomnik::testing::MockHost;
+/// use dome_cloomnik::{register_modules, WrenVM};
+///
+/// struct Point;
+/// impl Point {
+///     fn new(_vm: &WrenVM) -> Self {
+///         Point
+///     }
+/// }
+///
+/// let host = MockHost::new();
+/// let mut ctx = host.context();
+/// let result = register_modules! {
+///     ctx,
+///     module "geometry" {
+///         foreign class Point = new of Point {}
+///         foreign class Vertex = new of Point {}
+///     }
+/// };
+/// assert_eq!(
+///     result.unwrap_err().to_string(),
+///     "Rust type `Point` already backs foreign class 'Point' in module 'geometry'.",
+/// );
 /// ```
 #[macro_export]
 macro_rules! register_modules {
@@ -584,39 +817,36 @@ macro_rules! __register_modules_impl {
         }]
     } => {{
         extern "C" fn __dome_cloomnik_class_allocate(mut vm: $crate::WrenVM) {
-            if let Some(instance) = $crate::__catch_panic_from_foreign(&vm, || {
-                <$foreign_type>::$constructor(&vm)
-            }) {
-                // SAFETY: Wren calls the allocator with the foreign class on slot 0.
-                unsafe {
-                    vm.set_slot_new_foreign_unchecked(0, 0, instance);
-                }
-            }
+            let instance = $crate::__catch_panic_from_foreign(&vm, || {
+                $crate::__construct(&vm, <$foreign_type>::$constructor, &[])
+            });
+            // SAFETY: Wren calls the allocator with the foreign class on slot 0.
+            unsafe { $crate::__finish_allocation::<$foreign_type>(&mut vm, instance) };
         }
         extern "C" fn __dome_cloomnik_class_finalize(data: *mut $crate::__c_void) {
             // We cannot report the failure, but we still have to not panic
             let _ = ::std::panic::catch_unwind(|| {
-                // SAFETY: The memory is valid for read/write and is properly aligned
-                // because `ForeignWrapper<T>` is align(1).
-                let data = data as *mut $crate::__ForeignWrapper<$foreign_type>;
-                unsafe { ::std::ptr::drop_in_place(data) };
+                // SAFETY: The allocator always creates a `$foreign_type` object, possibly
+                // unconstructed, and Wren calls the finalizer only once.
+                unsafe { <$crate::__ForeignWrapper<$foreign_type>>::finalize(data) };
             });
         }
-        // SAFETY: The allocator always calls `vm.set_slot_new_foreign()` and both the allocator
-        // and the finalizer catch panics in user code. User code cannot store the VM because
-        // we pass it by reference.
+        // SAFETY: The allocator always creates the foreign object, even if the constructor fails,
+        // and both the allocator and the finalizer catch panics in user code. User code cannot
+        // store the VM because we pass it by reference.
         unsafe {
             $ctx.register_class(
                 $module,
                 stringify!($name),
                 __dome_cloomnik_class_allocate,
-                if ::std::mem::needs_drop::<$crate::__ForeignWrapper<$foreign_type>>() {
+                if <$crate::__ForeignWrapper<$foreign_type>>::needs_drop() {
                     Some(__dome_cloomnik_class_finalize)
                 } else {
                     None
                 },
             )
         }
+        .and_then(|()| $crate::__register_foreign_class::<$foreign_type>($module, stringify!($name)))
         .and_then(|()| {
             $crate::__register_modules_impl! { @register_class_members
                 ctx = [{ $ctx }]
@@ -673,6 +903,26 @@ macro_rules! __register_modules_impl {
         }
     };
 
+    // The receiver of an instance method: the foreign object for foreign classes, nothing otherwise
+    { @receiver } => { () };
+    { @receiver foreign_type = [{ $($foreign_type:tt)+ }] } => {
+        unsafe { $crate::__ForeignReceiver::<$($foreign_type)+>::new() }
+    };
+
+    // The shim Wren calls for a foreign method
+    { @method_shim
+        module = [{ $module:literal }]
+        method = [{ $method:expr }]
+        receiver = [{ $receiver:expr }]
+        names = [{ $($names:tt)* }]
+    } => {
+        extern "C" fn __dome_cloomnik_method(vm: $crate::WrenVM) {
+            $crate::__catch_panic_from_foreign(&vm, || {
+                $crate::__invoke(&vm, $module, $method, $receiver, &[$($names)*])
+            });
+        }
+    };
+
     // A little utility macro that allows us to replace parameter names by underscores
     // while still associating them to the repetition, so that `macro_rules!` won't complain
     { @underscore $($t:tt)* } => { "_" };
@@ -700,8 +950,11 @@ macro_rules! __register_modules_impl {
         type = [{ $($type:tt)+ }]
         $(foreign_type = [{ $($foreign_type:tt)+ }])?
     } => {{
-        extern "C" fn __dome_cloomnik_method(vm: $crate::WrenVM) {
-            $crate::__catch_panic_from_foreign(&vm, || <$($type)+>::$method(&mut vm));
+        $crate::__register_modules_impl! { @method_shim
+            module = [{ $module }]
+            method = [{ <$($type)+>::$method }]
+            receiver = [{ () }]
+            names = [{  }]
         }
         unsafe {
             $ctx.register_fn(
@@ -733,13 +986,11 @@ macro_rules! __register_modules_impl {
         type = [{ $($type:tt)+ }]
         $(foreign_type = [{ $($foreign_type:tt)+ }])?
     } => {{
-        extern "C" fn __dome_cloomnik_method(vm: $crate::WrenVM) {
-            $crate::__catch_panic_from_foreign(&vm, || {
-                <$($type)+>::$method(
-                    $(unsafe { vm.get_slot_foreign_unchecked::<$($foreign_type)+>(0) },)?
-                    &mut unsafe { $crate::__clone_vm(&vm) },
-                )
-            });
+        $crate::__register_modules_impl! { @method_shim
+            module = [{ $module }]
+            method = [{ <$($type)+>::$method }]
+            receiver = [{ $crate::__register_modules_impl! { @receiver $(foreign_type = [{ $($foreign_type)+ }])? } }]
+            names = [{  }]
         }
         unsafe {
             $ctx.register_fn(
@@ -771,8 +1022,11 @@ macro_rules! __register_modules_impl {
         type = [{ $($type:tt)+ }]
         $(foreign_type = [{ $($foreign_type:tt)+ }])?
     } => {{
-        extern "C" fn __dome_cloomnik_method(vm: $crate::WrenVM) {
-            $crate::__catch_panic_from_foreign(&vm, || <$($type)+>::$method(&mut vm));
+        $crate::__register_modules_impl! { @method_shim
+            module = [{ $module }]
+            method = [{ <$($type)+>::$method }]
+            receiver = [{ () }]
+            names = [{ stringify!($value) }]
         }
         unsafe {
             $ctx.register_fn(
@@ -804,13 +1058,11 @@ macro_rules! __register_modules_impl {
         type = [{ $($type:tt)+ }]
         $(foreign_type = [{ $($foreign_type:tt)+ }])?
     } => {{
-        extern "C" fn __dome_cloomnik_method(vm: $crate::WrenVM) {
-            $crate::__catch_panic_from_foreign(&vm, || {
-                <$($type)+>::$method(
-                    $(unsafe { vm.get_slot_foreign_unchecked::<$($foreign_type)+>(0) },)?
-                    &mut unsafe { $crate::__clone_vm(&vm) },
-                )
-            });
+        $crate::__register_modules_impl! { @method_shim
+            module = [{ $module }]
+            method = [{ <$($type)+>::$method }]
+            receiver = [{ $crate::__register_modules_impl! { @receiver $(foreign_type = [{ $($foreign_type)+ }])? } }]
+            names = [{ stringify!($value) }]
         }
         unsafe {
             $ctx.register_fn(
@@ -842,8 +1094,11 @@ macro_rules! __register_modules_impl {
         type = [{ $($type:tt)+ }]
         $(foreign_type = [{ $($foreign_type:tt)+ }])?
     } => {{
-        extern "C" fn __dome_cloomnik_method(vm: $crate::WrenVM) {
-            $crate::__catch_panic_from_foreign(&vm, || <$($type)+>::$method(&mut vm));
+        $crate::__register_modules_impl! { @method_shim
+            module = [{ $module }]
+            method = [{ <$($type)+>::$method }]
+            receiver = [{ () }]
+            names = [{ $(stringify!($param0) $(, stringify!($params))*)? }]
         }
         unsafe {
             $ctx.register_fn(
@@ -880,13 +1135,11 @@ macro_rules! __register_modules_impl {
         type = [{ $($type:tt)+ }]
         $(foreign_type = [{ $($foreign_type:tt)+ }])?
     } => {{
-        extern "C" fn __dome_cloomnik_method(vm: $crate::WrenVM) {
-            $crate::__catch_panic_from_foreign(&vm, || {
-                <$($type)+>::$method(
-                    $(unsafe { vm.get_slot_foreign_unchecked::<$($foreign_type)+>(0) },)?
-                    &mut unsafe { $crate::__clone_vm(&vm) },
-                )
-            });
+        $crate::__register_modules_impl! { @method_shim
+            module = [{ $module }]
+            method = [{ <$($type)+>::$method }]
+            receiver = [{ $crate::__register_modules_impl! { @receiver $(foreign_type = [{ $($foreign_type)+ }])? } }]
+            names = [{ $(stringify!($param0) $(, stringify!($params))*)? }]
         }
         unsafe {
             $ctx.register_fn(
@@ -923,8 +1176,11 @@ macro_rules! __register_modules_impl {
         type = [{ $($type:tt)+ }]
         $(foreign_type = [{ $($foreign_type:tt)+ }])?
     } => {{
-        extern "C" fn __dome_cloomnik_method(vm: $crate::WrenVM) {
-            $crate::__catch_panic_from_foreign(&vm, || <$($type)+>::$method(&mut vm));
+        $crate::__register_modules_impl! { @method_shim
+            module = [{ $module }]
+            method = [{ <$($type)+>::$method }]
+            receiver = [{ () }]
+            names = [{ stringify!($param0) $(, stringify!($params))* }]
         }
         unsafe {
             $ctx.register_fn(
@@ -959,13 +1215,11 @@ macro_rules! __register_modules_impl {
         type = [{ $($type:tt)+ }]
         $(foreign_type = [{ $($foreign_type:tt)+ }])?
     } => {{
-        extern "C" fn __dome_cloomnik_method(vm: $crate::WrenVM) {
-            $crate::__catch_panic_from_foreign(&vm, || {
-                <$($type)+>::$method(
-                    $(unsafe { vm.get_slot_foreign_unchecked::<$($foreign_type)+>(0) },)?
-                    &mut unsafe { $crate::__clone_vm(&vm) },
-                )
-            });
+        $crate::__register_modules_impl! { @method_shim
+            module = [{ $module }]
+            method = [{ <$($type)+>::$method }]
+            receiver = [{ $crate::__register_modules_impl! { @receiver $(foreign_type = [{ $($foreign_type)+ }])? } }]
+            names = [{ stringify!($param0) $(, stringify!($params))* }]
         }
         unsafe {
             $ctx.register_fn(
@@ -1000,8 +1254,11 @@ macro_rules! __register_modules_impl {
         type = [{ $($type:tt)+ }]
         $(foreign_type = [{ $($foreign_type:tt)+ }])?
     } => {{
-        extern "C" fn __dome_cloomnik_method(vm: $crate::WrenVM) {
-            $crate::__catch_panic_from_foreign(&vm, || <$($type)+>::$method(&mut vm));
+        $crate::__register_modules_impl! { @method_shim
+            module = [{ $module }]
+            method = [{ <$($type)+>::$method }]
+            receiver = [{ () }]
+            names = [{ stringify!($param0) $(, stringify!($params))*, stringify!($value) }]
         }
         unsafe {
             $ctx.register_fn(
@@ -1036,13 +1293,11 @@ macro_rules! __register_modules_impl {
         type = [{ $($type:tt)+ }]
         $(foreign_type = [{ $($foreign_type:tt)+ }])?
     } => {{
-        extern "C" fn __dome_cloomnik_method(vm: $crate::WrenVM) {
-            $crate::__catch_panic_from_foreign(&vm, || {
-                <$($type)+>::$method(
-                    $(unsafe { vm.get_slot_foreign_unchecked::<$($foreign_type)+>(0) },)?
-                    &mut unsafe { $crate::__clone_vm(&vm) },
-                )
-            });
+        $crate::__register_modules_impl! { @method_shim
+            module = [{ $module }]
+            method = [{ <$($type)+>::$method }]
+            receiver = [{ $crate::__register_modules_impl! { @receiver $(foreign_type = [{ $($foreign_type)+ }])? } }]
+            names = [{ stringify!($param0) $(, stringify!($params))*, stringify!($value) }]
         }
         unsafe {
             $ctx.register_fn(
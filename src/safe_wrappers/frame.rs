@@ -0,0 +1,225 @@
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use super::convert::scratch_vm;
+use super::wren::VM;
+
+/// The scratch slots of the current foreign call.
+#[derive(Clone, Copy)]
+struct Scratch {
+    /// The first scratch slot. The slots below it were in use before the first frame.
+    base: usize,
+    /// The slot count after the scratch slots were allocated.
+    top: usize,
+    /// The first slot not reserved by a live frame.
+    used: usize,
+    /// The slots below it may hold strings that are borrowed from the VM without copying.
+    /// Frames opened from a shared reference to the VM may be alive at the same time, so
+    /// no frame reserves slots below it.
+    borrowed: usize,
+}
+
+thread_local! {
+    static SCRATCH: Cell<Option<Scratch>> = const { Cell::new(None) };
+    /// Whether a foreign method generated by this crate is running. Only then can scratch
+    /// slots be reused after all frames were dropped: otherwise, we cannot tell them from
+    /// the arguments of the next call.
+    static IN_FOREIGN_CALL: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Records that the string in `slot` is borrowed from the VM, so that scratch slots never
+/// overwrite it.
+///
+/// Only released scratch slots can be reserved again, so the record lives as long as the
+/// scratch slots: without them, new frames start above all slots, borrowed or not.
+#[inline]
+pub(crate) fn borrow_slot(slot: usize) {
+    SCRATCH.with(|scratch| {
+        if let Some(state) = scratch.get() {
+            scratch.set(Some(Scratch {
+                borrowed: state.borrowed.max(slot + 1),
+                ..state
+            }));
+        }
+    });
+}
+
+/// Runs `callback` as a foreign call, whose scratch slots can be reused by later frames.
+pub(crate) fn foreign_call<R>(callback: impl FnOnce() -> R) -> R {
+    struct Restore(Option<Scratch>, bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SCRATCH.with(|scratch| scratch.set(self.0));
+            IN_FOREIGN_CALL.with(|in_call| in_call.set(self.1));
+        }
+    }
+
+    let _restore = Restore(
+        SCRATCH.with(|scratch| scratch.take()),
+        IN_FOREIGN_CALL.with(|in_call| in_call.replace(true)),
+    );
+    callback()
+}
+
+/// Temporary slots, reserved above the slots in use and released when the frame is dropped.
+///
+/// Get one with [`WrenVM::slot_frame()`][VM::slot_frame()], then reserve slots with
+/// [`slot()`][Self::slot()]. The frame derefs to the VM, so the slots can be used with any
+/// method, and nested frames can be opened from it. Slots never overlap the arguments of
+/// the foreign method, the slots reserved by enclosing frames, or slots added with
+/// [`WrenVM::ensure_slots()`][VM::ensure_slots()] before the frame was opened.
+///
+/// Wren cannot shrink the slot array, so released slots remain valid: do not use them after
+/// the frame is dropped, as later frames reuse them.
+///
+/// # Example
+///
+/// ```
+/// use dome_cloomnik::testing::{MockHost, Value};
+///
+/// let host = MockHost::new();
+/// let names = Value::list(vec!["Ada".into(), "Grace".into()]);
+/// let result = host.with_vm(&[Value::Null, names], |vm| {
+///     let mut initials = String::new();
+///     for index in 0..vm.get_list_count(1) {
+///         let mut frame = vm.slot_frame();
+///         let name = frame.slot();
+///         // The arguments are safe from the scratch slots.
+///         assert_eq!(name, 2);
+///         frame.get_list_element(1, index, name);
+///         initials.push_str(&frame.get_slot_string(name).unwrap()[..1]);
+///     }
+///     initials
+/// });
+/// assert_eq!(result, Ok("AG".to_owned()));
+/// ```
+#[derive(Debug)]
+pub struct SlotFrame<'a> {
+    vm: VM<'a>,
+    /// The first slot of this frame.
+    start: usize,
+    /// The next slot this frame reserves.
+    end: usize,
+    _vm: PhantomData<&'a mut VM<'a>>,
+}
+
+impl<'a> SlotFrame<'a> {
+    #[inline]
+    pub(crate) fn new(vm: &'a mut VM<'_>) -> Self {
+        // SAFETY: We borrow the VM mutably, so nobody else uses it while the frame is alive.
+        unsafe { Self::scratch(vm) }
+    }
+
+    /// Opens a frame from a shared reference to the VM, for reading values that need
+    /// scratch slots (e.g. [`FromWren`][crate::FromWren] implementations). Frames must
+    /// still be dropped in the reverse order they were opened.
+    ///
+    /// Strings borrowed from the same VM may be alive, so the frame only reserves slots above
+    /// the slots they were borrowed from.
+    ///
+    /// # Safety
+    ///
+    /// The frame writes to the slots it reserves, although the VM is only borrowed. Nothing
+    /// but the frame may use these slots: the caller must not access them through other
+    /// references to the VM, nor keep anything borrowed from them after the frame is dropped.
+    pub(crate) unsafe fn scratch(vm: &'a VM<'_>) -> Self {
+        let count = vm.get_slot_count();
+        let start = SCRATCH.with(|scratch| match scratch.get() {
+            // Nobody reserved slots since the last frame, so the slots it released are free,
+            // unless strings were borrowed from them.
+            Some(state) if state.top == count => state.used.max(state.borrowed),
+            _ => {
+                scratch.set(Some(Scratch {
+                    base: count,
+                    top: count,
+                    used: count,
+                    borrowed: 0,
+                }));
+                count
+            }
+        });
+        Self {
+            // SAFETY: The caller guarantees that only the frame uses its slots.
+            vm: scratch_vm(vm),
+            start,
+            end: start,
+            _vm: PhantomData,
+        }
+    }
+
+    /// Reserves a new scratch slot.
+    pub fn slot(&mut self) -> usize {
+        let count = self.vm.get_slot_count();
+        let state = SCRATCH.with(Cell::get).filter(|state| state.top == count);
+        // If frames are not used in order, the slots after this frame may be taken.
+        let slot = match state {
+            Some(state) => self.end.max(state.used).max(state.borrowed),
+            None => self.end.max(count),
+        };
+        let contiguous = slot == self.end;
+        self.end = slot + 1;
+        if count < self.end {
+            self.vm.ensure_slots(self.end);
+        }
+        let top = self.vm.get_slot_count();
+        SCRATCH.with(|scratch| {
+            scratch.set(match state {
+                Some(state) if contiguous => Some(Scratch {
+                    top,
+                    used: self.end,
+                    ..state
+                }),
+                // We can no longer release the slots of this frame on drop, since they are
+                // interleaved with the slots of other frames.
+                _ => None,
+            })
+        });
+        slot
+    }
+}
+
+impl Drop for SlotFrame<'_> {
+    fn drop(&mut self) {
+        SCRATCH.with(|scratch| match scratch.get() {
+            Some(state) if state.is_innermost(self) => {
+                let outermost = self.start == state.base;
+                if outermost && !IN_FOREIGN_CALL.with(Cell::get) {
+                    scratch.set(None);
+                } else {
+                    scratch.set(Some(Scratch {
+                        used: self.start,
+                        ..state
+                    }));
+                }
+            }
+            // Slots were reserved without this frame knowing while it was alive. The next
+            // frame starts above all of them.
+            _ => scratch.set(None),
+        });
+    }
+}
+
+impl Scratch {
+    /// Whether `frame` is the innermost live frame, and no slots were reserved since.
+    #[inline]
+    fn is_innermost(&self, frame: &SlotFrame<'_>) -> bool {
+        self.used == frame.end && self.top == frame.vm.get_slot_count()
+    }
+}
+
+impl<'a> Deref for SlotFrame<'a> {
+    type Target = VM<'a>;
+
+    #[inline]
+    fn deref(&self) -> &VM<'a> {
+        &self.vm
+    }
+}
+
+impl<'a> DerefMut for SlotFrame<'a> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut VM<'a> {
+        &mut self.vm
+    }
+}
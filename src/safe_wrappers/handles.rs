@@ -0,0 +1,476 @@
+use libc::c_void;
+use std::any::{self, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+
+use super::convert::ToWren;
+use super::frame::SlotFrame;
+use super::registry;
+use super::wren::{ForeignRef, ForeignRefMut, Handle, VM};
+use crate::errors::{Error, Result};
+use crate::unsafe_wrappers::wren as unsafe_wren;
+use crate::Api;
+
+thread_local! {
+    /// The handles that were not released yet, keyed by their id. Handles are not `Send`,
+    /// so they are always dropped on the thread Wren runs on.
+    static HANDLES: RefCell<HashMap<u64, (unsafe_wren::VM, unsafe_wren::Handle)>> =
+        RefCell::new(HashMap::new());
+    /// Set once the VM of this thread shuts down. [`SendHandle`]s share it, so that they are
+    /// not queued for release after that. A new VM gets a new flag.
+    static SHUT_DOWN: RefCell<Arc<AtomicBool>> = RefCell::new(Arc::default());
+}
+
+/// The ids are unique across threads, so that queued releases cannot be confused.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The [`SendHandle`]s dropped on other threads, with the thread of their VM.
+static RELEASE_QUEUE: Mutex<Vec<(ThreadId, u64)>> = Mutex::new(Vec::new());
+
+/// Records a new live handle, and returns its id.
+pub(crate) fn register(vm: unsafe_wren::VM, handle: unsafe_wren::Handle) -> u64 {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    HANDLES.with(|handles| handles.borrow_mut().insert(id, (vm, handle)));
+    id
+}
+
+/// Releases the handle with `id`, unless it was already released at shutdown.
+pub(crate) fn release(id: u64) {
+    // The registry may be gone if the handle is dropped by another thread-local destructor,
+    // and then the VM is gone too.
+    let entry = HANDLES
+        .try_with(|handles| handles.borrow_mut().remove(&id))
+        .ok()
+        .flatten();
+    if let Some((vm, handle)) = entry {
+        (Api::wren().release_handle)(vm, handle);
+    }
+}
+
+/// Releases all live handles, since the VM is about to be freed. Dropping them later does
+/// nothing. Returns how many handles were released.
+pub(crate) fn release_all() -> usize {
+    {
+        // Under the lock, so that no `SendHandle` is queued after the queue is drained.
+        let _queue = RELEASE_QUEUE.lock().unwrap_or_else(|err| err.into_inner());
+        SHUT_DOWN.with(|shut_down| {
+            shut_down
+                .replace(Arc::default())
+                .store(true, Ordering::Relaxed)
+        });
+    }
+    release_queued();
+    let handles = HANDLES.with(|handles| handles.replace(HashMap::new()));
+    for &(vm, handle) in handles.values() {
+        (Api::wren().release_handle)(vm, handle);
+    }
+    handles.len()
+}
+
+/// Releases the [`SendHandle`]s of this thread that were dropped on other threads.
+pub(crate) fn release_queued() {
+    let current = thread::current().id();
+    // Release outside of the lock, as releasing calls into Wren.
+    let mut released = Vec::new();
+    {
+        let mut queue = RELEASE_QUEUE.lock().unwrap_or_else(|err| err.into_inner());
+        queue.retain(|&(owner, id)| {
+            if owner == current {
+                released.push(id);
+            }
+            owner != current
+        });
+    }
+    for id in released {
+        release(id);
+    }
+}
+
+/// A [`WrenHandle`][crate::WrenHandle] that can be sent to and shared with other threads,
+/// for example to store it in the user data of an audio channel.
+///
+/// The handle can only be used given the VM it belongs to, via [`get()`][Self::get()]. If
+/// it is dropped on another thread, it is released by the next `pre_update` hook or channel
+/// `update` callback. Like other handles, handles still alive when the VM shuts down are
+/// released then, and dropping them later does nothing.
+///
+/// # Example
+///
+/// ```
+/// use dome_cloomnik::testing::{MockHost, Value};
+/// use dome_cloomnik::SendHandle;
+/// use std::thread;
+///
+/// let host = MockHost::new();
+/// let callback = host
+///     .with_vm(&[Value::from("onFinish")], |vm| SendHandle::new(vm.get_slot_handle(0)))
+///     .unwrap();
+/// let callback = thread::spawn(move || callback).join().unwrap();
+///
+/// let result = host.with_vm(&[Value::Null], |vm| {
+///     vm.set_slot_handle(0, callback.get(vm));
+///     vm.get_slot_string(0).unwrap()
+/// });
+/// assert_eq!(result, Ok("onFinish".to_owned()));
+///
+/// thread::spawn(move || drop(callback)).join().unwrap();
+/// assert_eq!(host.live_handles(), 1);
+/// assert!(host.pre_update());
+/// assert_eq!(host.live_handles(), 0);
+/// ```
+#[derive(Debug)]
+pub struct SendHandle {
+    handle: ManuallyDrop<Handle>,
+    vm: unsafe_wren::VM,
+    /// The thread Wren runs on, which must release the handle.
+    owner: ThreadId,
+    /// Whether the VM shut down, and so released the handle.
+    shut_down: Arc<AtomicBool>,
+}
+
+// SAFETY: The handle is only used on the thread of its VM: `get()` requires the VM, and
+// `drop()` queues the handle for that thread otherwise.
+unsafe impl Send for SendHandle {}
+unsafe impl Sync for SendHandle {}
+
+impl SendHandle {
+    /// Wraps `handle`, so it can be sent to other threads.
+    #[inline]
+    pub fn new(handle: Handle) -> Self {
+        Self {
+            vm: handle.vm,
+            handle: ManuallyDrop::new(handle),
+            owner: thread::current().id(),
+            shut_down: SHUT_DOWN.with(|shut_down| Arc::clone(&shut_down.borrow())),
+        }
+    }
+
+    /// Returns the handle, for use with `vm`.
+    ///
+    /// Panics if `vm` is not the VM the handle belongs to.
+    #[inline]
+    pub fn get(&self, vm: &VM) -> &Handle {
+        assert!(vm.0 == self.vm, "The handle belongs to another VM.");
+        &self.handle
+    }
+}
+
+impl From<Handle> for SendHandle {
+    #[inline]
+    fn from(handle: Handle) -> Self {
+        Self::new(handle)
+    }
+}
+
+impl Drop for SendHandle {
+    fn drop(&mut self) {
+        if thread::current().id() == self.owner {
+            // SAFETY: The handle is not used after this.
+            unsafe { ManuallyDrop::drop(&mut self.handle) }
+        } else {
+            let mut queue = RELEASE_QUEUE.lock().unwrap_or_else(|err| err.into_inner());
+            // Otherwise, nobody would ever take it off the queue.
+            if !self.shut_down.load(Ordering::Relaxed) {
+                queue.push((self.owner, self.handle.id));
+            }
+        }
+    }
+}
+
+/// A [`WrenHandle`][crate::WrenHandle] to a Rust foreign object of type `T`.
+///
+/// The object can be borrowed given the VM, like with
+/// [`WrenVM::borrow_foreign()`][VM::borrow_foreign()], which checks its type again.
+///
+/// # Example
+///
+/// ```
+/// use dome_cloomnik::testing::{MockHost, Value};
+/// use dome_cloomnik::{dome_class, register_classes, ClassHandle, TypedHandle};
+///
+/// #[derive(Debug)]
+/// struct Synth {
+///     volume: f64,
+/// }
+///
+/// #[dome_class(module = "synth")]
+/// impl Synth {
+///     #[foreign(construct)]
+///     fn new() -> Self {
+///         Synth { volume: 1.0 }
+///     }
+/// }
+///
+/// let host = MockHost::new();
+/// register_classes!(host.context(), Synth)?;
+/// let synth = host.construct("synth", "Synth", &[]).unwrap();
+/// let synth = host
+///     .with_vm(&[synth], |vm| unsafe { TypedHandle::<Synth>::new(vm, 0) })
+///     .unwrap();
+///
+/// let volume = host.with_vm(&[Value::Null], |vm| {
+///     synth.borrow_mut(vm).unwrap().volume = 0.5;
+///     // Typed handles can be stored in slots, like untyped ones.
+///     vm.set_slot_handle(0, &synth);
+///     unsafe { vm.get_slot_foreign::<Synth>(0) }.volume
+/// });
+/// assert_eq!(volume, Ok(0.5));
+///
+/// // Class handles create instances of their class.
+/// let volume = host.with_vm(&[Value::Null], |vm| {
+///     let class = ClassHandle::of::<Synth>(vm);
+///     assert_eq!(class.name(), "Synth");
+///     class.new_instance(vm, 0, Synth { volume: 0.25 });
+///     let synth = unsafe { TypedHandle::<Synth>::new(vm, 0) };
+///     let volume = synth.borrow(vm).unwrap().volume;
+///     volume
+/// });
+/// assert_eq!(volume, Ok(0.25));
+///
+/// let errors = host.with_vm(&[Value::Null], |vm| {
+///     let borrowed = synth.borrow_mut(vm).unwrap();
+///     let borrow_error = synth.borrow(vm).unwrap_err().to_string();
+///     drop(borrowed);
+///     let class = ClassHandle::of::<Synth>(vm);
+///     (borrow_error, class.try_new_instance(vm, 0, 1.0).unwrap_err().to_string())
+/// });
+/// assert_eq!(errors, Ok((
+///     "The foreign `Synth` behind the handle is already borrowed.".to_owned(),
+///     "The objects of class Synth are of type `Synth`, not `f64`.".to_owned(),
+/// )));
+/// # Ok::<(), dome_cloomnik::Error>(())
+/// ```
+pub struct TypedHandle<T: 'static> {
+    handle: Handle,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T: 'static> TypedHandle<T> {
+    /// Retrieves a handle to the Rust foreign object of type `T` (or of a type that upcasts
+    /// to `T`) in `slot`.
+    ///
+    /// Panics if the slot is invalid or does not contain such an object. For a non-panicking
+    /// version, see [`try_new()`][Self::try_new()].
+    ///
+    /// # Safety
+    ///
+    /// Like [`WrenVM::get_slot_foreign()`][VM::get_slot_foreign()], this function cannot
+    /// verify that the foreign object is a Rust foreign object. You must make sure of that.
+    #[inline]
+    pub unsafe fn new(vm: &mut VM, slot: usize) -> Self {
+        Self::try_new(vm, slot).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Retrieves a handle to the Rust foreign object of type `T` (or of a type that upcasts
+    /// to `T`) in `slot`, or returns an error if the slot is invalid or does not contain
+    /// such an object.
+    ///
+    /// # Safety
+    ///
+    /// See [`new()`][Self::new()].
+    #[inline]
+    pub unsafe fn try_new(vm: &mut VM, slot: usize) -> Result<Self> {
+        vm.try_get_foreign_data::<T>(slot, false)?;
+        Ok(Self {
+            handle: vm.get_slot_handle_unchecked(slot),
+            _type: PhantomData,
+        })
+    }
+
+    /// Borrows the object, like [`WrenVM::borrow_foreign()`][VM::borrow_foreign()].
+    ///
+    /// Fails if the object is currently borrowed mutably.
+    pub fn borrow<'a>(&'a self, vm: &'a VM) -> Result<ForeignRef<'a, T>> {
+        // SAFETY: Only this function uses the frame. The guard borrows the object, which the
+        // handle keeps alive, and not the slot.
+        let mut frame = unsafe { SlotFrame::scratch(vm) };
+        let slot = frame.slot();
+        frame.set_slot_handle(slot, &self.handle);
+        // SAFETY: `new()` and `try_new()` require the object to be a Rust foreign object, so
+        // `foreign_data()` may read it, and returns pointers into it. The handle keeps the
+        // object alive for `'a`, so the pointers stay valid for the guard.
+        unsafe {
+            let (data, value) = Self::foreign_data(&frame, slot, false)?;
+            ForeignRef::new(data, value, slot).map_err(|_| Self::already_borrowed())
+        }
+    }
+
+    /// Borrows the object mutably, like
+    /// [`WrenVM::borrow_foreign_mut()`][VM::borrow_foreign_mut()].
+    ///
+    /// Fails if the object is currently borrowed.
+    pub fn borrow_mut<'a>(&'a self, vm: &'a VM) -> Result<ForeignRefMut<'a, T>> {
+        // SAFETY: See `borrow()`.
+        let mut frame = unsafe { SlotFrame::scratch(vm) };
+        let slot = frame.slot();
+        frame.set_slot_handle(slot, &self.handle);
+        // SAFETY: See `borrow()`.
+        unsafe {
+            let (data, value) = Self::foreign_data(&frame, slot, true)?;
+            ForeignRefMut::new(data, value, slot).map_err(|_| Self::already_borrowed())
+        }
+    }
+
+    /// Reports errors in terms of the handle, since `slot` is only a scratch slot.
+    ///
+    /// # Safety
+    ///
+    /// `slot` must contain a Rust foreign object.
+    #[inline]
+    unsafe fn foreign_data(vm: &VM, slot: usize, mutable: bool) -> Result<(*mut c_void, *mut T)> {
+        vm.try_get_foreign_data::<T>(slot, mutable)
+            .map_err(|err| match err {
+                Error::ForeignTypeMismatch {
+                    expected, actual, ..
+                } => Error::HandleTypeMismatch { expected, actual },
+                err => err,
+            })
+    }
+
+    #[inline]
+    fn already_borrowed() -> Error {
+        Error::HandleAlreadyBorrowed {
+            type_name: any::type_name::<T>(),
+        }
+    }
+
+    /// The untyped handle.
+    #[inline]
+    pub fn into_handle(self) -> Handle {
+        self.handle
+    }
+}
+
+impl<T: 'static> AsRef<Handle> for TypedHandle<T> {
+    #[inline]
+    fn as_ref(&self) -> &Handle {
+        &self.handle
+    }
+}
+
+impl<T: 'static> ToWren for TypedHandle<T> {
+    #[inline]
+    fn to_wren(&self, vm: &mut VM, slot: usize) {
+        vm.set_slot_handle(slot, self)
+    }
+}
+
+impl<T: 'static> fmt::Debug for TypedHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TypedHandle")
+            .field("type", &any::type_name::<T>())
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+/// A [`WrenHandle`][crate::WrenHandle] to a foreign class registered with
+/// [`register_modules!`][crate::register_modules!] or
+/// [`register_classes!`][crate::register_classes!].
+///
+/// See [`TypedHandle`] for an example.
+#[derive(Debug)]
+pub struct ClassHandle {
+    handle: Handle,
+    /// The Rust type of the objects of the class.
+    type_id: TypeId,
+    type_name: &'static str,
+    name: &'static str,
+}
+
+impl ClassHandle {
+    /// Retrieves a handle to the foreign class whose objects are of type `T`.
+    ///
+    /// Panics if the type is not registered, or if Wren did not import the module of the
+    /// class yet (see [`WrenVM::new_foreign()`][crate::WrenVM::new_foreign()]). For a
+    /// non-panicking version, see [`try_of()`][Self::try_of()].
+    #[inline]
+    pub fn of<T: 'static>(vm: &mut VM) -> Self {
+        Self::try_of::<T>(vm).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Retrieves a handle to the foreign class whose objects are of type `T`, or returns
+    /// an error if the type is not registered or its module is not imported yet.
+    pub fn try_of<T: 'static>(vm: &mut VM) -> Result<Self> {
+        let name = registry::class_name::<T>().ok_or(Error::ForeignClassNotRegistered {
+            type_name: any::type_name::<T>(),
+        })?;
+        let mut frame = vm.slot_frame();
+        let slot = frame.slot();
+        // SAFETY: The frame just reserved the slot.
+        unsafe { registry::load_foreign_class::<T>(&mut frame, slot)? };
+        Ok(Self {
+            handle: frame.get_slot_handle(slot),
+            type_id: TypeId::of::<T>(),
+            type_name: any::type_name::<T>(),
+            name,
+        })
+    }
+
+    /// The name of the class in Wren.
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Sets `slot` to a new instance of the class, holding `instance`.
+    ///
+    /// Panics if the slot is invalid, or if the objects of the class are not of type `T`.
+    /// For a non-panicking version, see [`try_new_instance()`][Self::try_new_instance()].
+    #[inline]
+    pub fn new_instance<'a, T: 'static>(
+        &self,
+        vm: &'a mut VM,
+        slot: usize,
+        instance: T,
+    ) -> &'a mut T {
+        self.try_new_instance(vm, slot, instance)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Sets `slot` to a new instance of the class, holding `instance`, or returns an error
+    /// if the slot is invalid or the objects of the class are not of type `T`.
+    pub fn try_new_instance<'a, T: 'static>(
+        &self,
+        vm: &'a mut VM,
+        slot: usize,
+        instance: T,
+    ) -> Result<&'a mut T> {
+        vm.try_validate_slot(slot)?;
+        if self.type_id != TypeId::of::<T>() {
+            return Err(Error::ClassTypeMismatch {
+                class: self.name,
+                expected: any::type_name::<T>(),
+                actual: self.type_name,
+            });
+        }
+        // SAFETY: We validated the slot, and the class is the foreign class of `T`.
+        unsafe {
+            vm.set_slot_handle_unchecked(slot, self);
+            Ok(vm.set_slot_new_foreign_unchecked(slot, slot, instance))
+        }
+    }
+
+    /// The untyped handle.
+    #[inline]
+    pub fn into_handle(self) -> Handle {
+        self.handle
+    }
+}
+
+impl AsRef<Handle> for ClassHandle {
+    #[inline]
+    fn as_ref(&self) -> &Handle {
+        &self.handle
+    }
+}
+
+impl ToWren for ClassHandle {
+    #[inline]
+    fn to_wren(&self, vm: &mut VM, slot: usize) {
+        vm.set_slot_handle(slot, self)
+    }
+}
@@ -0,0 +1,424 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use super::convert::{ForeignReturn, FromWren};
+use super::registry;
+use super::wren::{ForeignRef, ForeignRefMut, ForeignWrapper, Handle, VM};
+use crate::errors::{Error, Result};
+
+#[inline]
+fn argument_error(error: Error, name: &str) -> Error {
+    Error::InvalidArgument {
+        name: name.to_owned(),
+        error: Box::new(error),
+    }
+}
+
+/// Reads the argument `name` from `slot`, reporting failures in terms of the parameter.
+#[inline]
+fn get_argument<T: FromWren>(vm: &VM, slot: usize, name: &str) -> Result<T> {
+    T::from_wren(vm, slot).map_err(|error| argument_error(error, name))
+}
+
+/// Borrows the foreign argument `name` from `slot`, reporting failures in terms of the parameter.
+///
+/// # Safety
+///
+/// If `slot` contains a foreign object, it must be a Rust foreign object.
+#[inline]
+unsafe fn borrow_argument<'v, T: 'static>(
+    vm: &'v VM,
+    slot: usize,
+    name: &str,
+) -> Result<ForeignRef<'v, T>> {
+    vm.borrow_foreign(slot)
+        .map_err(|error| argument_error(error, name))
+}
+
+/// Like [`borrow_argument()`], but borrows mutably.
+///
+/// # Safety
+///
+/// If `slot` contains a foreign object, it must be a Rust foreign object.
+#[inline]
+unsafe fn borrow_argument_mut<'v, T: 'static>(
+    vm: &'v VM,
+    slot: usize,
+    name: &str,
+) -> Result<ForeignRefMut<'v, T>> {
+    vm.borrow_foreign_mut(slot)
+        .map_err(|error| argument_error(error, name))
+}
+
+/// An argument of a foreign method: either a [`FromWren`] value, a string or bytes borrowed
+/// from their slot, or a borrow of a Rust foreign object.
+#[doc(hidden)]
+pub trait Argument<'v>: Sized {
+    /// Whether the argument borrows from its slot, so the slot must be rooted while the
+    /// method can overwrite it.
+    const BORROWS: bool = false;
+
+    fn get(vm: &'v VM, slot: usize, name: &str) -> Result<Self>;
+}
+
+impl<T: FromWren> Argument<'_> for T {
+    #[inline]
+    fn get(vm: &VM, slot: usize, name: &str) -> Result<Self> {
+        get_argument(vm, slot, name)
+    }
+}
+
+impl<'v> Argument<'v> for &'v str {
+    const BORROWS: bool = true;
+
+    #[inline]
+    fn get(vm: &'v VM, slot: usize, name: &str) -> Result<Self> {
+        vm.try_get_slot_str(slot)
+            .map_err(|error| argument_error(error, name))
+    }
+}
+
+impl<'v> Argument<'v> for &'v [u8] {
+    const BORROWS: bool = true;
+
+    #[inline]
+    fn get(vm: &'v VM, slot: usize, name: &str) -> Result<Self> {
+        vm.try_get_slot_bytes_ref(slot)
+            .map_err(|error| argument_error(error, name))
+    }
+}
+
+impl<'v, T: 'static> Argument<'v> for ForeignRef<'v, T> {
+    const BORROWS: bool = true;
+
+    #[inline]
+    fn get(vm: &'v VM, slot: usize, name: &str) -> Result<Self> {
+        // SAFETY: There is no way to tell Rust foreign objects from other foreign objects
+        // (see `VM::get_slot_foreign()`), so we have to trust the Wren code here.
+        unsafe { borrow_argument(vm, slot, name) }
+    }
+}
+
+impl<'v, T: 'static> Argument<'v> for ForeignRefMut<'v, T> {
+    const BORROWS: bool = true;
+
+    #[inline]
+    fn get(vm: &'v VM, slot: usize, name: &str) -> Result<Self> {
+        // SAFETY: See above.
+        unsafe { borrow_argument_mut(vm, slot, name) }
+    }
+}
+
+/// Implemented once for every type, and once more for [`FromWren`] types, so that
+/// `<T as ForeignBorrow<_>>::CHECK` only compiles if `T` is not a `FromWren` type.
+///
+/// [`dome_class`][crate::dome_class] takes references as borrows of foreign objects, so it
+/// uses this to reject references to values, such as `&String`.
+#[doc(hidden)]
+pub trait ForeignBorrow<Ambiguity> {
+    const CHECK: () = ();
+}
+
+#[doc(hidden)]
+pub struct IsFromWren;
+
+impl<T: ?Sized> ForeignBorrow<()> for T {}
+impl<T: FromWren> ForeignBorrow<IsFromWren> for T {}
+
+/// The receiver of instance methods of foreign classes. It is borrowed from slot 0
+/// as the method requires.
+#[doc(hidden)]
+pub struct ForeignReceiver<T>(PhantomData<T>);
+
+impl<T> ForeignReceiver<T> {
+    /// # Safety
+    ///
+    /// Slot 0 must contain a Rust foreign object of type `T`.
+    #[inline]
+    pub unsafe fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+// Markers distinguishing the shapes of foreign methods.
+#[doc(hidden)]
+pub struct Static;
+#[doc(hidden)]
+pub struct Shared;
+#[doc(hidden)]
+pub struct Exclusive;
+#[doc(hidden)]
+pub struct NoVm;
+#[doc(hidden)]
+pub struct SharedVm;
+#[doc(hidden)]
+pub struct ExclusiveVm;
+
+/// Returns handles keeping the values in the slots the receiver and the arguments borrow
+/// from alive, for methods that can overwrite the slots through a `&mut VM` while the values
+/// are borrowed. Otherwise, overwriting the only reference to a borrowed object would let
+/// the GC free it. `borrowed[slot]` tells whether `slot` is borrowed.
+#[inline]
+fn root_borrowed_slots<const N: usize>(vm: &VM, borrowed: [bool; N]) -> [Option<Handle>; N] {
+    // SAFETY: We only create handles, which do not change the slots.
+    let mut handles_vm = unsafe { VM::from_raw(vm.0) };
+    let count = vm.get_slot_count();
+    let mut slot = 0;
+    borrowed.map(|borrowed| {
+        let handle = (borrowed && slot < count).then(|| handles_vm.get_slot_handle(slot));
+        slot += 1;
+        handle
+    })
+}
+
+/// A Rust function that can be bound as a foreign method.
+///
+/// `Receiver` is [`ForeignReceiver<T>`] for instance methods of foreign classes, and `()`
+/// otherwise. The function may take the receiver (by shared or mutable reference), then
+/// optionally the VM (again, by shared or mutable reference), then the arguments, each an
+/// [`Argument`]. `args` is the VM arguments are borrowed from.
+///
+/// The guards of the receiver and the arguments are dropped before `call()` returns, so
+/// the caller may then overwrite their slots. If the function takes a `&mut VM`, the
+/// borrowed objects are rooted for the duration of the call.
+#[doc(hidden)]
+pub trait ForeignMethod<'v, Receiver, Marker> {
+    type Output;
+    /// The number of arguments read from slots.
+    const ARITY: usize;
+    /// Whether the function receives the VM, and can therefore read the arguments itself.
+    const TAKES_VM: bool;
+
+    fn call(
+        self,
+        receiver: Receiver,
+        vm: &mut VM,
+        args: &'v VM,
+        names: &[&str],
+    ) -> Result<Self::Output>;
+}
+
+macro_rules! impl_foreign_method {
+    ( @impl [ $($receiver_generics:tt)* ] $receiver:ty, $receiver_marker:ident, ( $($receiver_param:tt)* )
+        ( $($vm_param:tt)* ) $vm_marker:ident, $takes_vm:literal
+        $( $arg:ident : $slot:literal ),*
+    ) => {
+        impl<'v, $($receiver_generics)* F, R, $($arg: Argument<'v>),*>
+            ForeignMethod<'v, $receiver, ($receiver_marker, $vm_marker, $($arg,)*)> for F
+        where
+            F: FnOnce($($receiver_param)* $($vm_param)* $($arg),*) -> R,
+        {
+            type Output = R;
+            const ARITY: usize = <[usize]>::len(&[$($slot),*]);
+            const TAKES_VM: bool = $takes_vm;
+
+            #[inline]
+            #[allow(non_snake_case, unused_variables, unused_mut)]
+            fn call(
+                self,
+                receiver: $receiver,
+                vm: &mut VM,
+                args: &'v VM,
+                names: &[&str],
+            ) -> Result<R> {
+                let _roots = impl_foreign_method!(@root args, $receiver_marker, $vm_marker, [$($arg),*]);
+                let mut receiver = impl_foreign_method!(@borrow receiver, args, $receiver_marker);
+                $( let $arg = $arg::get(args, $slot, names[$slot - 1])?; )*
+                Ok(impl_foreign_method!(
+                    @call self, receiver, vm, $receiver_marker, $vm_marker, $($arg),*
+                ))
+            }
+        }
+    };
+    ( @root $args:ident, Static, ExclusiveVm, [ $($arg:ident),* ] ) => {
+        root_borrowed_slots($args, [false, $($arg::BORROWS),*])
+    };
+    ( @root $args:ident, $receiver_marker:ident, ExclusiveVm, [ $($arg:ident),* ] ) => {
+        root_borrowed_slots($args, [true, $($arg::BORROWS),*])
+    };
+    ( @root $args:ident, $receiver_marker:ident, $vm_marker:ident, $arg_list:tt ) => { () };
+    // SAFETY: A `ForeignReceiver<T>` guarantees that slot 0 contains a `T`.
+    ( @borrow $receiver:ident, $args:ident, Static ) => { $receiver };
+    ( @borrow $receiver:ident, $args:ident, Shared ) => { unsafe { $args.borrow_foreign::<T>(0)? } };
+    ( @borrow $receiver:ident, $args:ident, Exclusive ) => {
+        unsafe { $args.borrow_foreign_mut::<T>(0)? }
+    };
+    ( @call $method:expr, $receiver:ident, $vm:ident, Static, NoVm, $($arg:ident),* ) => {
+        $method($($arg),*)
+    };
+    ( @call $method:expr, $receiver:ident, $vm:ident, Static, $vm_marker:ident, $($arg:ident),* ) => {
+        $method($vm, $($arg),*)
+    };
+    ( @call $method:expr, $receiver:ident, $vm:ident, Shared, NoVm, $($arg:ident),* ) => {
+        $method(&*$receiver, $($arg),*)
+    };
+    ( @call $method:expr, $receiver:ident, $vm:ident, Shared, $vm_marker:ident, $($arg:ident),* ) => {
+        $method(&*$receiver, $vm, $($arg),*)
+    };
+    ( @call $method:expr, $receiver:ident, $vm:ident, Exclusive, NoVm, $($arg:ident),* ) => {
+        $method(&mut *$receiver, $($arg),*)
+    };
+    ( @call $method:expr, $receiver:ident, $vm:ident, Exclusive, $vm_marker:ident, $($arg:ident),* ) => {
+        $method(&mut *$receiver, $vm, $($arg),*)
+    };
+    ( @vm [ $($receiver:tt)* ] $( $arg:ident : $slot:literal ),* ) => {
+        impl_foreign_method! { @impl $($receiver)* () NoVm, false $( $arg : $slot ),* }
+        impl_foreign_method! { @impl $($receiver)* (&VM,) SharedVm, true $( $arg : $slot ),* }
+        impl_foreign_method! { @impl $($receiver)* (&mut VM,) ExclusiveVm, true $( $arg : $slot ),* }
+    };
+    ( $( $arg:ident : $slot:literal ),* ) => {
+        impl_foreign_method! { @vm [ [] (), Static, () ] $( $arg : $slot ),* }
+        impl_foreign_method! {
+            @vm [ [T: 'static,] ForeignReceiver<T>, Shared, (&T,) ] $( $arg : $slot ),*
+        }
+        impl_foreign_method! {
+            @vm [ [T: 'static,] ForeignReceiver<T>, Exclusive, (&mut T,) ] $( $arg : $slot ),*
+        }
+    };
+}
+
+impl_foreign_method!();
+impl_foreign_method!(A1: 1);
+impl_foreign_method!(A1: 1, A2: 2);
+impl_foreign_method!(A1: 1, A2: 2, A3: 3);
+impl_foreign_method!(A1: 1, A2: 2, A3: 3, A4: 4);
+impl_foreign_method!(A1: 1, A2: 2, A3: 3, A4: 4, A5: 5);
+impl_foreign_method!(A1: 1, A2: 2, A3: 3, A4: 4, A5: 5, A6: 6);
+impl_foreign_method!(A1: 1, A2: 2, A3: 3, A4: 4, A5: 5, A6: 6, A7: 7);
+impl_foreign_method!(A1: 1, A2: 2, A3: 3, A4: 4, A5: 5, A6: 6, A7: 7, A8: 8);
+
+struct ArityCheck<'v, F, Receiver, Marker, const N: usize>(
+    PhantomData<(&'v (), F, Receiver, Marker)>,
+);
+
+impl<'v, F: ForeignMethod<'v, Receiver, Marker>, Receiver, Marker, const N: usize>
+    ArityCheck<'v, F, Receiver, Marker, N>
+{
+    const VALID: () = assert!(
+        F::ARITY == N || (F::ARITY == 0 && F::TAKES_VM),
+        "The Rust function must take either all arguments of the Wren method or the VM."
+    );
+}
+
+/// Calls `method` with the arguments of the Wren method, whose parameters are `names`.
+/// Foreign objects are borrowed from `vm`, and `method` gets a copy of it.
+///
+/// Checks at compile time that the arity of `method` matches.
+#[inline]
+fn call_foreign_method<'v, F, Receiver, Marker, const N: usize>(
+    vm: &'v VM,
+    method: F,
+    receiver: Receiver,
+    names: &[&str; N],
+) -> Result<F::Output>
+where
+    F: ForeignMethod<'v, Receiver, Marker>,
+{
+    #[allow(clippy::let_unit_value)]
+    let () = ArityCheck::<'v, F, Receiver, Marker, N>::VALID;
+    // SAFETY: `method` only gets the copy by reference, so it cannot keep it. The objects
+    // borrowed from `vm` are rooted if `method` can overwrite their slots.
+    let mut copy = unsafe { VM::from_raw(vm.0) };
+    method.call(receiver, &mut copy, vm, names)
+}
+
+/// The body of foreign method shims of classes in `module`: calls `method` with the receiver
+/// and the arguments of the Wren method, whose parameters are `names`, and stores the result
+/// in slot 0. If the receiver or an argument is invalid, aborts the fiber instead.
+///
+/// Wren only calls the method once it imported `module`, so this records the import, and
+/// `method` can create instances of the foreign classes of the module.
+#[doc(hidden)]
+#[inline]
+pub fn invoke<'v, F, Receiver, Marker, const N: usize>(
+    vm: &'v VM,
+    module: &str,
+    method: F,
+    receiver: Receiver,
+    names: &[&str; N],
+) where
+    F: ForeignMethod<'v, Receiver, Marker>,
+    F::Output: ForeignReturn,
+{
+    registry::mark_imported(module);
+    let result = call_foreign_method(vm, method, receiver, names);
+    // SAFETY: The guards were dropped by now, so we may overwrite slot 0.
+    let mut vm = unsafe { VM::from_raw(vm.0) };
+    match result {
+        Ok(result) => result.store(&mut vm),
+        Err(error) => vm.abort_fiber_with(error),
+    }
+}
+
+/// The body of allocator shims of foreign classes whose objects are `T`: calls the
+/// constructor `method` with the arguments of the Wren constructor, whose parameters are
+/// `names`. The result is then passed to [`finish_allocation()`].
+#[doc(hidden)]
+#[inline]
+pub fn construct<'v, T, F, Marker, const N: usize>(
+    vm: &'v VM,
+    method: F,
+    names: &[&str; N],
+) -> std::result::Result<T, String>
+where
+    F: ForeignMethod<'v, (), Marker>,
+    F::Output: ConstructorResult<T>,
+{
+    call_foreign_method(vm, method, (), names)
+        .map_err(|error| error.to_string())
+        .and_then(ConstructorResult::into_result)
+}
+
+/// The return type of constructors of foreign classes whose objects are `T`: either `T`,
+/// or `Result<T, E>` where `E: Display`. Errors are formatted with `{:#}`, like in
+/// [`ForeignReturn`][super::convert::ForeignReturn].
+#[doc(hidden)]
+pub trait ConstructorResult<T> {
+    fn into_result(self) -> std::result::Result<T, String>;
+}
+
+impl<T> ConstructorResult<T> for T {
+    #[inline]
+    fn into_result(self) -> std::result::Result<T, String> {
+        Ok(self)
+    }
+}
+
+impl<T, E: fmt::Display> ConstructorResult<T> for std::result::Result<T, E> {
+    #[inline]
+    fn into_result(self) -> std::result::Result<T, String> {
+        self.map_err(|error| format!("{:#}", error))
+    }
+}
+
+/// Finishes the allocator of a foreign class whose objects are `T`, given the result of the
+/// constructor, or `None` if it panicked (and so the fiber was already aborted).
+///
+/// Wren requires the allocator to create the object even if the constructor fails. In that
+/// case the object is left unconstructed (see [`ForeignWrapper`]): it cannot be borrowed,
+/// and the finalizer does nothing. The fiber is then aborted with the error, so the object
+/// never reaches Wren code.
+///
+/// # Safety
+///
+/// Slot 0 must contain the foreign class, as it does when Wren calls the allocator.
+#[doc(hidden)]
+#[inline]
+pub unsafe fn finish_allocation<T: 'static>(
+    vm: &mut VM,
+    instance: Option<std::result::Result<T, String>>,
+) {
+    registry::foreign_class_allocated::<T>(vm, 0);
+    match instance {
+        Some(Ok(instance)) => {
+            vm.set_slot_new_foreign_unchecked(0, 0, instance);
+        }
+        instance => {
+            let data = vm.set_slot_new_raw_foreign_unchecked(0, 0, ForeignWrapper::<T>::SIZE);
+            ForeignWrapper::<T>::write_unconstructed(data);
+            if let Some(Err(error)) = instance {
+                vm.abort_fiber_with(error);
+            }
+        }
+    }
+}
@@ -1,3 +1,11 @@
 pub(crate) mod audio;
+pub(crate) mod class;
+pub(crate) mod collections;
+pub(crate) mod convert;
 pub(crate) mod dome;
+pub(crate) mod frame;
+pub(crate) mod handles;
+pub(crate) mod method;
+pub(crate) mod registry;
+pub(crate) mod value;
 pub(crate) mod wren;
@@ -0,0 +1,239 @@
+use libc::c_void;
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use super::wren::{ForeignWrapper, Handle, Type, VM};
+use crate::errors::{Error, Result};
+
+/// A foreign class registered via [`register_modules!`][crate::register_modules!] or
+/// [`register_classes!`][crate::register_classes!].
+struct ForeignClass {
+    module: &'static str,
+    name: &'static str,
+    /// The class itself, fetched the first time an instance is created from Rust.
+    handle: Option<Handle>,
+}
+
+/// Projects a pointer to the derived type into a pointer to the base type.
+type Projection = unsafe fn(*mut c_void) -> *mut c_void;
+
+/// The types a Rust foreign type upcasts to.
+struct Bases {
+    /// Locates the derived value inside the foreign object.
+    locate: Projection,
+    /// The base types, with the projections for shared and mutable borrows.
+    bases: Vec<(TypeId, Projection, Projection)>,
+}
+
+thread_local! {
+    /// The foreign classes, keyed by the Rust type of their objects. Wren is single-threaded,
+    /// and DOME always calls plugins on the same thread.
+    static CLASSES: RefCell<HashMap<TypeId, ForeignClass>> = RefCell::new(HashMap::new());
+    /// The registered upcasts, keyed by the derived type.
+    static UPCASTS: RefCell<HashMap<TypeId, Bases>> = RefCell::new(HashMap::new());
+    /// The modules of registered foreign classes that Wren is known to have imported.
+    static IMPORTED: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+}
+
+/// Declares that Rust foreign objects of this type can be used as objects of type `Base`,
+/// for example because `Base` is a field of this type.
+///
+/// Register the relationship with
+/// [`Context::register_upcast()`][crate::Context::register_upcast()]. Then
+/// [`WrenVM::get_slot_foreign()`][crate::WrenVM::get_slot_foreign()],
+/// [`WrenVM::borrow_foreign()`][crate::WrenVM::borrow_foreign()] and foreign method
+/// arguments of type `Base` accept objects of this type too, and receive the part
+/// [`upcast()`][Self::upcast()] returns. Upcasts are transitive: if `C` upcasts to `B` and
+/// `B` upcasts to `A`, a `C` can be used as an `A`.
+///
+/// Both methods must return the same part of the object.
+pub trait Upcast<Base: 'static>: 'static {
+    fn upcast(&self) -> &Base;
+    fn upcast_mut(&mut self) -> &mut Base;
+}
+
+unsafe fn locate<T: 'static>(data: *mut c_void) -> *mut c_void {
+    ForeignWrapper::<T>::value(data) as *mut c_void
+}
+
+unsafe fn project<T: Upcast<Base>, Base: 'static>(value: *mut c_void) -> *mut c_void {
+    (*(value as *const T)).upcast() as *const Base as *mut c_void
+}
+
+unsafe fn project_mut<T: Upcast<Base>, Base: 'static>(value: *mut c_void) -> *mut c_void {
+    (*(value as *mut T)).upcast_mut() as *mut Base as *mut c_void
+}
+
+/// Records that objects of type `T` can be used as objects of type `Base`.
+pub(crate) fn register_upcast<T: Upcast<Base>, Base: 'static>() {
+    UPCASTS.with(|upcasts| {
+        let mut upcasts = upcasts.borrow_mut();
+        let bases = upcasts.entry(TypeId::of::<T>()).or_insert_with(|| Bases {
+            locate: locate::<T>,
+            bases: Vec::new(),
+        });
+        bases
+            .bases
+            .retain(|&(base, _, _)| base != TypeId::of::<Base>());
+        bases.bases.push((
+            TypeId::of::<Base>(),
+            project::<T, Base>,
+            project_mut::<T, Base>,
+        ));
+    })
+}
+
+/// Locates the `base` inside the foreign object `data` of type `derived`, following the
+/// registered upcasts. Returns `None` if `derived` does not upcast to `base`.
+///
+/// # Safety
+///
+/// `data` must contain a Rust foreign object of type `derived`.
+pub(crate) unsafe fn upcast(
+    data: *mut c_void,
+    derived: TypeId,
+    base: TypeId,
+    mutable: bool,
+) -> Option<*mut c_void> {
+    UPCASTS.with(|upcasts| {
+        let upcasts = upcasts.borrow();
+        let locate = upcasts.get(&derived)?.locate;
+        // Depth-first search, remembering the projections on the way.
+        let mut visited = HashSet::new();
+        let mut stack = vec![(derived, Vec::<Projection>::new())];
+        while let Some((current, path)) = stack.pop() {
+            if current == base {
+                let value = path
+                    .iter()
+                    .fold(locate(data), |value, projection| projection(value));
+                return Some(value);
+            }
+            if !visited.insert(current) {
+                continue;
+            }
+            for &(next, shared, exclusive) in upcasts.get(&current).map_or(&[][..], |b| &b.bases) {
+                let mut path = path.clone();
+                path.push(if mutable { exclusive } else { shared });
+                stack.push((next, path));
+            }
+        }
+        None
+    })
+}
+
+/// Records that the objects of the foreign class `name` in `module` are of type `T`.
+///
+/// Fails if `T` already backs another class, since Rust could not tell which class to
+/// create its instances with.
+#[doc(hidden)]
+pub fn register_foreign_class<T: 'static>(module: &'static str, name: &'static str) -> Result {
+    CLASSES.with(|classes| {
+        let mut classes = classes.borrow_mut();
+        if let Some(class) = classes.get(&TypeId::of::<T>()) {
+            if (class.module, class.name) != (module, name) {
+                return Err(Error::ForeignClassAlreadyRegistered {
+                    type_name: std::any::type_name::<T>(),
+                    module: class.module,
+                    class: class.name,
+                });
+            }
+        }
+        let class = ForeignClass {
+            module,
+            name,
+            handle: None,
+        };
+        classes.insert(TypeId::of::<T>(), class);
+        Ok(())
+    })
+}
+
+/// Records that Wren allocates an object of type `T` whose class is in `slot`. The module
+/// of the class is imported by then, so Rust can load its classes too.
+///
+/// # Safety
+///
+/// `slot` must contain the foreign class that allocates the `T`.
+pub(crate) unsafe fn foreign_class_allocated<T: 'static>(vm: &mut VM, slot: usize) {
+    CLASSES.with(|classes| {
+        let mut classes = classes.borrow_mut();
+        if let Some(class) = classes.get_mut(&TypeId::of::<T>()) {
+            if class.handle.is_none() {
+                class.handle = Some(vm.get_slot_handle_unchecked(slot));
+                mark_imported(class.module);
+            }
+        }
+    })
+}
+
+/// Records that Wren imported `module`.
+pub(crate) fn mark_imported(module: &str) {
+    IMPORTED.with(|imported| {
+        if !imported.borrow().contains(module) {
+            imported.borrow_mut().insert(module.to_owned());
+        }
+    })
+}
+
+/// Stores the foreign class whose objects are of type `T` in `slot`. Fails if there is no
+/// such class, or if Wren is not known to have imported its module yet.
+///
+/// DOME does not tell plugins when Wren imports a module, so a module counts as imported
+/// once Wren called a foreign method or allocated an object of one of its classes.
+///
+/// # Safety
+///
+/// `slot` must be valid.
+pub(crate) unsafe fn load_foreign_class<T: 'static>(vm: &mut VM, slot: usize) -> Result {
+    CLASSES.with(|classes| {
+        let mut classes = classes.borrow_mut();
+        let class =
+            classes
+                .get_mut(&TypeId::of::<T>())
+                .ok_or(Error::ForeignClassNotRegistered {
+                    type_name: std::any::type_name::<T>(),
+                })?;
+        match &class.handle {
+            Some(handle) => vm.set_slot_handle_unchecked(slot, handle),
+            None => {
+                let not_imported = || Error::ForeignClassNotImported {
+                    type_name: std::any::type_name::<T>(),
+                    module: class.module,
+                };
+                let imported = IMPORTED.with(|imported| imported.borrow().contains(class.module));
+                if !imported {
+                    return Err(not_imported());
+                }
+                // The module exists, but the class variable is still null if the module
+                // is being executed and did not reach the class yet.
+                vm.get_variable_unchecked(class.module, class.name, slot);
+                if let Type::Null = vm.get_slot_type_unchecked(slot) {
+                    return Err(not_imported());
+                }
+                class.handle = Some(vm.get_slot_handle_unchecked(slot));
+            }
+        }
+        Ok(())
+    })
+}
+
+/// The name of the foreign class whose objects are of type `T`, if it is registered.
+pub(crate) fn class_name<T: 'static>() -> Option<&'static str> {
+    CLASSES.with(|classes| {
+        classes
+            .borrow()
+            .get(&TypeId::of::<T>())
+            .map(|class| class.name)
+    })
+}
+
+/// Forgets all classes and upcasts, and releases the handles of the classes. Must be called
+/// before the VM is freed.
+pub(crate) fn clear() {
+    // Drop the handles outside of the borrow, as releasing them calls into Wren.
+    let classes = CLASSES.with(|classes| classes.replace(HashMap::new()));
+    drop(classes);
+    UPCASTS.with(|upcasts| upcasts.borrow_mut().clear());
+    IMPORTED.with(|imported| imported.borrow_mut().clear());
+}
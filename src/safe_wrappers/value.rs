@@ -0,0 +1,154 @@
+use super::convert::{map_to_wren, FromWren, ToWren};
+use super::frame::SlotFrame;
+use super::wren::{Handle, Type, VM};
+use crate::errors::{Error, Result};
+
+/// An owned Wren value, read recursively from a slot.
+///
+/// Lists are read element by element, using scratch slots reserved with a [`SlotFrame`].
+/// Objects that cannot be represented in Rust are kept alive via a [`Handle`], and can be
+/// written back.
+///
+/// Wren's embedding API cannot enumerate the keys of a `Map`, so maps are read as
+/// [`WrenValue::Unknown`]. [`WrenValue::Map`]s can still be written to Wren.
+///
+/// Wren lists may contain themselves, so reading is limited to a maximum depth of nested lists:
+/// [`WrenValue::DEFAULT_MAX_DEPTH`] with [`VM::get()`][crate::WrenVM::get()], and configurable
+/// with [`WrenValue::read()`].
+///
+/// # Example
+///
+/// ```
+/// use dome_cloomnik::testing::{MockHost, Value};
+/// use dome_cloomnik::WrenValue;
+///
+/// let host = MockHost::new();
+/// let notes = Value::list(vec![
+///     Value::list(vec![Value::from("C4"), Value::Num(0.5)]),
+///     Value::list(vec![Value::from("E4"), Value::Num(0.25)]),
+/// ]);
+/// let result = host.with_vm(&[notes], |vm| {
+///     let notes = vm.get::<WrenValue>(0);
+///     let durations = match &notes {
+///         WrenValue::List(notes) => notes
+///             .iter()
+///             .map(|note| match note {
+///                 WrenValue::List(note) => match note[1] {
+///                     WrenValue::Num(duration) => duration,
+///                     _ => panic!("expected a duration"),
+///                 },
+///                 _ => panic!("expected a note"),
+///             })
+///             .collect::<Vec<_>>(),
+///         _ => panic!("expected a list"),
+///     };
+///     assert_eq!(durations, [0.5, 0.25]);
+///
+///     // Writing it back creates a new list, with the same contents.
+///     vm.set(0, &notes);
+///     vm.get::<Vec<(String, f64)>>(0)
+/// });
+/// assert_eq!(result, Ok(vec![("C4".to_owned(), 0.5), ("E4".to_owned(), 0.25)]));
+///
+/// // The depth limit is configurable.
+/// let nested = Value::list(vec![Value::list(vec![Value::Null])]);
+/// let result = host.with_vm(&[nested], |vm| {
+///     assert!(WrenValue::read(vm, 0, 2).is_ok());
+///     WrenValue::read(vm, 0, 1).unwrap_err().to_string()
+/// });
+/// assert_eq!(result, Ok("Slot 0 is invalid: list nesting exceeds the maximum depth of 1.".to_owned()));
+/// ```
+#[derive(Debug)]
+pub enum WrenValue {
+    Null,
+    Bool(bool),
+    Num(f64),
+    /// A `String` that is valid UTF-8.
+    String(String),
+    /// A `String` that is not valid UTF-8.
+    Bytes(Vec<u8>),
+    List(Vec<WrenValue>),
+    /// A map, as a list of entries. Keys must be `Null`, `Bool`, `Num` or `String`.
+    Map(Vec<(WrenValue, WrenValue)>),
+    /// A foreign object.
+    Foreign(Handle),
+    /// Any other value, including maps.
+    Unknown(Handle),
+}
+
+impl WrenValue {
+    /// The maximum depth of nested lists read by [`FromWren`].
+    pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+    /// Reads the value in `slot`, with at most `max_depth` levels of nested lists.
+    pub fn read(vm: &VM, slot: usize, max_depth: usize) -> Result<Self> {
+        // SAFETY: Only `read()` uses the frame, and it returns owned values.
+        let mut frame = unsafe { SlotFrame::scratch(vm) };
+        read(&mut frame, slot, slot, max_depth, max_depth)
+    }
+}
+
+/// Reads the value in `slot`, reporting errors in terms of `root`. `depth` is the number of
+/// levels of nested lists that can still be read.
+fn read(
+    vm: &mut VM,
+    slot: usize,
+    root: usize,
+    depth: usize,
+    max_depth: usize,
+) -> Result<WrenValue> {
+    Ok(match vm.try_get_slot_type(slot)? {
+        Type::Null => WrenValue::Null,
+        Type::Bool => WrenValue::Bool(vm.get_slot_bool(slot)),
+        Type::Num => WrenValue::Num(vm.get_slot_double(slot)),
+        Type::String => match String::from_utf8(vm.get_slot_bytes(slot)) {
+            Ok(string) => WrenValue::String(string),
+            Err(err) => WrenValue::Bytes(err.into_bytes()),
+        },
+        Type::List => {
+            if depth == 0 {
+                return Err(Error::SlotConversionFailed {
+                    slot: root,
+                    reason: format!("list nesting exceeds the maximum depth of {}", max_depth),
+                });
+            }
+            let count = vm.get_list_count(slot);
+            let mut frame = vm.slot_frame();
+            let element_slot = frame.slot();
+            let mut elements = Vec::with_capacity(count);
+            for index in 0..count {
+                frame.get_list_element(slot, index, element_slot);
+                elements.push(read(&mut frame, element_slot, root, depth - 1, max_depth)?);
+            }
+            WrenValue::List(elements)
+        }
+        Type::Foreign => WrenValue::Foreign(vm.get_slot_handle(slot)),
+        Type::Map | Type::Unknown => WrenValue::Unknown(vm.get_slot_handle(slot)),
+    })
+}
+
+impl FromWren for WrenValue {
+    #[inline]
+    fn from_wren(vm: &VM, slot: usize) -> Result<Self> {
+        Self::read(vm, slot, Self::DEFAULT_MAX_DEPTH)
+    }
+}
+
+impl ToWren for WrenValue {
+    fn to_wren(&self, vm: &mut VM, slot: usize) {
+        match self {
+            WrenValue::Null => vm.set_slot_null(slot),
+            WrenValue::Bool(value) => vm.set_slot_bool(slot, *value),
+            WrenValue::Num(value) => vm.set_slot_double(slot, *value),
+            WrenValue::String(value) => vm.set_slot_string(slot, value),
+            WrenValue::Bytes(value) => vm.set_slot_bytes(slot, value),
+            WrenValue::List(elements) => elements.to_wren(vm, slot),
+            WrenValue::Map(entries) => {
+                map_to_wren(vm, slot, entries.iter().map(|(key, value)| (key, value)))
+            }
+            WrenValue::Foreign(handle) | WrenValue::Unknown(handle) => {
+                vm.set_slot_handle(slot, handle)
+            }
+        }
+    }
+}
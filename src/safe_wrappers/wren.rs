@@ -1,66 +1,388 @@
 use libc::{c_char, c_int, c_void};
 use std::any::TypeId;
+use std::borrow::Cow;
 use std::convert::TryInto;
 use std::ffi::CString;
+use std::fmt;
 use std::marker::PhantomData;
 use std::mem;
+use std::ops::{Deref, DerefMut};
 use std::ptr;
 use std::slice;
 use std::str;
 
+use super::collections::{WrenList, WrenMap};
+use super::convert::{FromWren, ToWren};
 use super::dome;
+use super::frame::{self, SlotFrame};
+use super::handles;
+use super::registry;
+use crate::errors::{Error, Result};
 use crate::unsafe_wrappers::wren as unsafe_wren;
 use crate::Api;
 pub use unsafe_wren::Type;
 
-// This is repr(C) so we know that the type ID is always at byte 0,
-// and align(1) because the memory is allocated by Wren and we do
-// not control its alignment.
-#[repr(C, align(1))]
-pub struct ForeignWrapper<T: 'static> {
-    type_id: TypeId,
-    foreign: T,
+/// The layout of Rust foreign objects inside the memory Wren allocates for them.
+///
+/// Wren only aligns foreign objects to 8 bytes, so we cannot just put a `T` there. Instead,
+/// the block starts with a header (stored unaligned) holding the [`TypeId`] of `T`, a
+/// function returning the name of `T` (for error messages) and the borrow flag, and the `T`
+/// lives at the first address after it that is properly aligned. We allocate enough padding
+/// for the worst case. Wren never moves objects, so the address of the `T` is stable.
+///
+/// The borrow flag works like `RefCell`'s: it is the number of active [`ForeignRef`]s,
+/// or -1 if there is an active [`ForeignRefMut`].
+///
+/// Wren requires allocators to always create the object, even when the constructor fails.
+/// Such objects are left unconstructed: their header holds the [`TypeId`] of a private marker
+/// type instead of that of `T`, so they never match any type, and finalizers skip them.
+pub struct ForeignWrapper<T: 'static>(PhantomData<T>);
+
+/// The type of unconstructed foreign objects (see [`ForeignWrapper`]).
+struct Unconstructed;
+
+type TypeNameFn = fn() -> &'static str;
+
+const TYPE_NAME_OFFSET: usize = mem::size_of::<TypeId>();
+const BORROW_FLAG_OFFSET: usize = TYPE_NAME_OFFSET + mem::size_of::<TypeNameFn>();
+const HEADER_SIZE: usize = BORROW_FLAG_OFFSET + mem::size_of::<isize>();
+
+impl<T: 'static> ForeignWrapper<T> {
+    /// The size of the block to allocate.
+    pub(crate) const SIZE: usize = HEADER_SIZE + mem::size_of::<T>() + mem::align_of::<T>() - 1;
+
+    /// Returns the aligned location of the `T` inside `data`.
+    #[inline]
+    pub(crate) fn value(data: *mut c_void) -> *mut T {
+        let start = (data as *mut u8).wrapping_add(HEADER_SIZE);
+        let padding = (start as usize).wrapping_neg() & (mem::align_of::<T>() - 1);
+        start.wrapping_add(padding) as *mut T
+    }
+
+    /// Writes `value` into `data`, unborrowed.
+    ///
+    /// # Safety
+    ///
+    /// `data` must point to a block of at least [`Self::SIZE`] bytes, valid for writes.
+    #[inline]
+    unsafe fn write(data: *mut c_void, value: T) -> *mut T {
+        write_header::<T>(data);
+        let foreign = Self::value(data);
+        ptr::write(foreign, value);
+        foreign
+    }
+
+    /// Marks `data` as an unconstructed object, that does not contain a `T`.
+    ///
+    /// # Safety
+    ///
+    /// `data` must point to a block of at least [`Self::SIZE`] bytes, valid for writes.
+    #[inline]
+    pub(crate) unsafe fn write_unconstructed(data: *mut c_void) {
+        write_header::<Unconstructed>(data);
+    }
+
+    /// Returns the `T` stored in `data`.
+    ///
+    /// # Safety
+    ///
+    /// `data` must contain a Rust foreign object of type `T`.
+    #[inline]
+    unsafe fn get<'a>(data: *mut c_void) -> &'a mut T {
+        &mut *Self::value(data)
+    }
+
+    /// Drops the `T` stored in `data`, unless the object is unconstructed. Used by finalizers.
+    ///
+    /// # Safety
+    ///
+    /// `data` must contain a Rust foreign object of type `T` or an unconstructed object
+    /// allocated for it, which must not be used again.
+    #[inline]
+    pub unsafe fn finalize(data: *mut c_void) {
+        if foreign_type_id(data) == TypeId::of::<T>() {
+            ptr::drop_in_place(Self::value(data))
+        }
+    }
+
+    /// Whether finalizers need to drop the `T`.
+    #[inline]
+    pub const fn needs_drop() -> bool {
+        mem::needs_drop::<T>()
+    }
+}
+
+/// Writes the header of a Rust foreign object of type `T`, unborrowed.
+///
+/// # Safety
+///
+/// `data` must point to a block of at least [`HEADER_SIZE`] bytes, valid for writes.
+#[inline]
+unsafe fn write_header<T: 'static>(data: *mut c_void) {
+    ptr::write_unaligned(data as *mut TypeId, TypeId::of::<T>());
+    ptr::write_unaligned(
+        (data as *mut u8).add(TYPE_NAME_OFFSET) as *mut TypeNameFn,
+        std::any::type_name::<T>,
+    );
+    set_borrow_flag(data, 0);
+}
+
+/// Returns the [`TypeId`] of the Rust object stored in `data`.
+///
+/// # Safety
+///
+/// `data` must contain a Rust foreign object (of any type).
+#[inline]
+unsafe fn foreign_type_id(data: *mut c_void) -> TypeId {
+    ptr::read_unaligned(data as *const TypeId)
+}
+
+/// Returns the name of the type of the Rust object stored in `data`.
+///
+/// # Safety
+///
+/// `data` must contain a Rust foreign object (of any type).
+#[inline]
+unsafe fn foreign_type_name(data: *mut c_void) -> &'static str {
+    ptr::read_unaligned((data as *const u8).add(TYPE_NAME_OFFSET) as *const TypeNameFn)()
+}
+
+/// Locates the `T` in the Rust foreign object `data`: the object itself if it is a `T`,
+/// or the `T` it upcasts to (see [`Upcast`][registry::Upcast]). Upcasts project the object
+/// through a mutable reference if `mutable`, and through a shared reference otherwise.
+///
+/// # Safety
+///
+/// `data` must contain a Rust foreign object (of any type).
+#[inline]
+unsafe fn foreign_value<T: 'static>(data: *mut c_void, mutable: bool) -> Option<*mut T> {
+    let type_id = foreign_type_id(data);
+    if type_id == TypeId::of::<T>() {
+        Some(ForeignWrapper::<T>::value(data))
+    } else {
+        registry::upcast(data, type_id, TypeId::of::<T>(), mutable).map(|value| value as *mut T)
+    }
+}
+
+/// # Safety
+///
+/// `data` must contain a Rust foreign object (of any type).
+#[inline]
+unsafe fn borrow_flag(data: *mut c_void) -> isize {
+    ptr::read_unaligned((data as *const u8).add(BORROW_FLAG_OFFSET) as *const isize)
+}
+
+/// # Safety
+///
+/// `data` must contain a Rust foreign object (of any type).
+#[inline]
+unsafe fn set_borrow_flag(data: *mut c_void, flag: isize) {
+    ptr::write_unaligned(
+        (data as *mut u8).add(BORROW_FLAG_OFFSET) as *mut isize,
+        flag,
+    )
+}
+
+/// A shared borrow of a Rust foreign object. Created by [`VM::borrow_foreign()`].
+///
+/// The object cannot be borrowed mutably until this guard is dropped.
+pub struct ForeignRef<'a, T: 'static> {
+    value: &'a T,
+    data: *mut c_void,
+}
+
+impl<'a, T: 'static> ForeignRef<'a, T> {
+    /// Borrows `value`, the `T` in the Rust foreign object `data`, which is in `slot`.
+    ///
+    /// # Safety
+    ///
+    /// `data` must contain a Rust foreign object, and `value` must point into it. Both must
+    /// be valid for `'a`.
+    #[inline]
+    pub(super) unsafe fn new(data: *mut c_void, value: *mut T, slot: usize) -> Result<Self> {
+        let flag = borrow_flag(data);
+        if flag < 0 {
+            return Err(Error::ForeignAlreadyBorrowed { slot });
+        }
+        set_borrow_flag(data, flag + 1);
+        Ok(ForeignRef {
+            value: &*value,
+            data,
+        })
+    }
+}
+
+impl<T: 'static> Deref for ForeignRef<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T: 'static> Drop for ForeignRef<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: The object is alive as long as the guard.
+        unsafe {
+            let flag = borrow_flag(self.data);
+            debug_assert!(flag > 0, "The borrowed foreign object was freed.");
+            set_borrow_flag(self.data, flag - 1)
+        }
+    }
+}
+
+impl<T: fmt::Debug + 'static> fmt::Debug for ForeignRef<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.value, f)
+    }
+}
+
+/// A mutable borrow of a Rust foreign object. Created by [`VM::borrow_foreign_mut()`].
+///
+/// The object cannot be borrowed again until this guard is dropped.
+pub struct ForeignRefMut<'a, T: 'static> {
+    value: &'a mut T,
+    data: *mut c_void,
+}
+
+impl<'a, T: 'static> ForeignRefMut<'a, T> {
+    /// Borrows `value`, the `T` in the Rust foreign object `data`, which is in `slot`, mutably.
+    ///
+    /// # Safety
+    ///
+    /// `data` must contain a Rust foreign object, and `value` must point into it. Both must
+    /// be valid for `'a`.
+    #[inline]
+    pub(super) unsafe fn new(data: *mut c_void, value: *mut T, slot: usize) -> Result<Self> {
+        if borrow_flag(data) != 0 {
+            return Err(Error::ForeignAlreadyBorrowed { slot });
+        }
+        set_borrow_flag(data, -1);
+        Ok(ForeignRefMut {
+            value: &mut *value,
+            data,
+        })
+    }
+}
+
+impl<T: 'static> Deref for ForeignRefMut<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T: 'static> DerefMut for ForeignRefMut<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
 }
 
-impl<T> ForeignWrapper<T> {
+impl<T: 'static> Drop for ForeignRefMut<'_, T> {
     #[inline]
-    fn new(foreign: T) -> Self {
-        Self {
-            type_id: TypeId::of::<T>(),
-            foreign,
+    fn drop(&mut self) {
+        // SAFETY: The object is alive as long as the guard.
+        unsafe {
+            debug_assert_eq!(
+                borrow_flag(self.data),
+                -1,
+                "The borrowed foreign object was freed."
+            );
+            set_borrow_flag(self.data, 0)
         }
     }
 }
 
+impl<T: fmt::Debug + 'static> fmt::Debug for ForeignRefMut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.value, f)
+    }
+}
+
 /// This is the gate for all operations using Wren.
 ///
-/// You can only get one in foreign methods.
+/// You can only get one in foreign methods. `'vm` is the duration of the call, so the VM
+/// cannot outlive it:
+///
+/// ```compile_fail
+/// use dome_cloomnik::WrenVM;
+///
+/// static mut STASHED: Option<WrenVM<'static>> = None;
+///
+/// extern "C" fn my_fn(vm: WrenVM) {
+///     unsafe { STASHED = Some(vm) };
+/// }
+/// ```
 #[derive(Debug)]
 #[repr(transparent)]
-pub struct VM(pub(crate) unsafe_wren::VM);
+pub struct VM<'vm>(pub(crate) unsafe_wren::VM, PhantomData<&'vm ()>);
 
 /// A handle is a long-lived value, as opposed to a slot which is short-lived.
 ///
+/// All handles that are still alive when the plugin shuts down are released, because the VM
+/// is freed after that. Dropping them later does nothing. In debug builds, the number of such
+/// handles is logged.
+///
 /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+///
+/// # Example
+///
+/// ```
+/// use dome_cloomnik::testing::{MockHost, Value};
+///
+/// let host = MockHost::new();
+/// let handle = host.with_vm(&[Value::from("kept")], |vm| vm.get_slot_handle(0)).unwrap();
+/// assert_eq!(host.live_handles(), 1);
+/// assert!(host.shutdown());
+/// assert_eq!(host.live_handles(), 0);
+/// # #[cfg(debug_assertions)]
+/// assert!(host.log().contains("1 Wren handle(s) were still alive at shutdown"));
+/// drop(handle);
+/// ```
 #[derive(Debug)]
 pub struct Handle {
     handle: unsafe_wren::Handle,
-    vm: unsafe_wren::VM,
+    pub(super) vm: unsafe_wren::VM,
+    /// The id of the handle in the registry of live handles.
+    pub(super) id: u64,
 }
 
 impl Drop for Handle {
+    #[inline]
     fn drop(&mut self) {
-        (Api::wren().release_handle)(self.vm, self.handle);
+        handles::release(self.id);
     }
 }
 
-pub(crate) type ForeignMethodFn = extern "C" fn(VM);
+impl AsRef<Handle> for Handle {
+    #[inline]
+    fn as_ref(&self) -> &Handle {
+        self
+    }
+}
+
+pub(crate) type ForeignMethodFn = extern "C" fn(VM<'_>);
 pub(crate) type FinalizerFn = extern "C" fn(*mut c_void);
 
-impl VM {
+impl<'vm> VM<'vm> {
+    /// Wraps a raw VM, for the duration of a call from Wren.
+    ///
+    /// # Safety
+    ///
+    /// `vm` must be the VM calling us, and stay valid for `'vm`.
+    #[inline]
+    pub(crate) unsafe fn from_raw(vm: unsafe_wren::VM) -> Self {
+        VM(vm, PhantomData)
+    }
+
     /// Retrieve a [`Context`] from this [`VM`].
     #[inline]
-    pub fn get_context(&self) -> dome::Context {
+    pub fn get_context(&self) -> dome::Context<'_> {
         dome::Context((Api::dome().get_context)(self.0), PhantomData)
     }
 
@@ -80,15 +402,26 @@ impl VM {
         (Api::wren().get_slot_count)(self.0).try_into().unwrap()
     }
 
+    /// Opens a [`SlotFrame`], to reserve scratch slots that do not overlap the slots in use.
+    #[inline]
+    pub fn slot_frame(&mut self) -> SlotFrame<'_> {
+        SlotFrame::new(self)
+    }
+
+    #[inline]
+    pub(super) fn try_validate_slot(&self, slot: usize) -> Result {
+        let count = self.get_slot_count();
+        if slot < count {
+            Ok(())
+        } else {
+            Err(Error::SlotOutOfBounds { slot, count })
+        }
+    }
+
     #[inline]
     fn validate_slot(&self, slot: usize) {
-        let slots_count = self.get_slot_count();
-        assert!(
-            slot < slots_count,
-            "Slot out of bounds: the count is {} but the slot is {}.",
-            slots_count,
-            slot
-        );
+        self.try_validate_slot(slot)
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 
     /// Returns the type of the object is `slot`.
@@ -111,17 +444,34 @@ impl VM {
         // SAFETY: We verified that the slot exists.
         unsafe { self.get_slot_type_unchecked(slot) }
     }
+    /// Returns the type of the object is `slot`, or an error if the slot does not exist.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    #[inline]
+    pub fn try_get_slot_type(&self, slot: usize) -> Result<Type> {
+        self.try_validate_slot(slot)?;
+        // SAFETY: We verified that the slot exists.
+        Ok(unsafe { self.get_slot_type_unchecked(slot) })
+    }
+
+    #[inline]
+    fn try_validate_slot_type(&self, slot: usize, expected: Type) -> Result {
+        let actual = self.try_get_slot_type(slot)?;
+        if actual == expected {
+            Ok(())
+        } else {
+            Err(Error::SlotTypeMismatch {
+                slot,
+                expected,
+                actual,
+            })
+        }
+    }
 
     #[inline]
     fn validate_slot_type(&self, slot: usize, expected: Type) {
-        let slot_type = self.get_slot_type(slot);
-        assert!(
-            slot_type == expected,
-            "Slot {} is of the incorrect type - expected {:?}, got {:?}.",
-            slot,
-            expected,
-            slot_type
-        );
+        self.try_validate_slot_type(slot, expected)
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 
     /// Sets `slot` to `null`.
@@ -200,7 +550,7 @@ impl VM {
             self.0,
             slot.try_into().unwrap(),
             data.as_ptr() as *const c_char,
-            data.len().try_into().unwrap(),
+            data.len(),
         )
     }
     /// Sets `slot` to a `String` from Rust bytes slice.
@@ -298,20 +648,21 @@ impl VM {
             self.0,
             slot.try_into().unwrap(),
             class_slot.try_into().unwrap(),
-            length.try_into().unwrap(),
+            length,
         )
     }
     /// Sets `slot` to a new Rust foreign object, where the foreign class is stored in `class_slot`
     /// and the Rust type is passed as a generic parameter.
     ///
     /// Note that this is **not** equal to the following:
-    /// ```
+    /// ```ignore
     /// let p = self.set_slot_new_raw_foreign_unchecked(slot, class_slot, std::mem::size_of::<T>());
     /// std::ptr::write(p, instance);
     /// ```
     /// Because this method does some bookkeeping to ensure that we get the right type back
     /// (it stores a [`TypeId`]), and also to work around the fact that Rust types are
-    /// required to be properly aligned, but Wren does not provide alignment guarantees.
+    /// required to be properly aligned, but Wren does not provide alignment guarantees
+    /// (it allocates a bit more memory, and places the object at an aligned address inside it).
     ///
     /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
     ///
@@ -323,6 +674,67 @@ impl VM {
     /// There isn't a safe counterpart to this method, because there is no way from the C API
     /// to verify that a slot contains a foreign class ([`get_slot_type()`] returns [`Type::Unknown`]
     /// for them).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use dome_cloomnik::testing::{MockHost, Value};
+    /// use dome_cloomnik::{register_modules, WrenVM};
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// static DROPPED: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// #[repr(align(64))]
+    /// struct Aligned([u64; 3]);
+    ///
+    /// impl Aligned {
+    ///     fn new(vm: &WrenVM) -> Self {
+    ///         let x = vm.get_slot_double(1) as u64;
+    ///         Aligned([x, x * 2, x * 3])
+    ///     }
+    ///
+    ///     fn sum(&self) -> f64 {
+    ///         assert_eq!(self as *const Self as usize % 64, 0);
+    ///         self.0.iter().sum::<u64>() as f64
+    ///     }
+    /// }
+    ///
+    /// impl Drop for Aligned {
+    ///     fn drop(&mut self) {
+    ///         assert_eq!(self as *mut Self as usize % 64, 0);
+    ///         DROPPED.fetch_add(1, Ordering::SeqCst);
+    ///     }
+    /// }
+    ///
+    /// let host = MockHost::new();
+    /// let mut ctx = host.context();
+    /// (register_modules! {
+    ///     ctx,
+    ///     module "aligned" {
+    ///         foreign class Aligned = new of Aligned {
+    ///             "construct new(x) {}"
+    ///             foreign sum() = sum
+    ///         }
+    ///     }
+    /// })?;
+    ///
+    /// // The mock host, like Wren, only aligns foreign objects to 8 bytes.
+    /// let aligned = host.construct("aligned", "Aligned", &[Value::Num(1.0)]).unwrap();
+    /// assert_eq!(host.call("aligned", "Aligned.sum()", &[aligned.clone()]), Ok(Value::Num(6.0)));
+    /// // Releasing the object runs the finalizer.
+    /// drop(aligned);
+    /// assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+    ///
+    /// let class = host.class("aligned", "Aligned").unwrap();
+    /// let sum = host.with_vm(&[class], |vm| {
+    ///     let aligned = unsafe { vm.set_slot_new_foreign_unchecked(0, 0, Aligned([4, 5, 6])) };
+    ///     assert_eq!(aligned as *mut Aligned as usize % 64, 0);
+    ///     unsafe { vm.get_slot_foreign::<Aligned>(0) }.sum()
+    /// });
+    /// assert_eq!(sum, Ok(15.0));
+    /// assert_eq!(DROPPED.load(Ordering::SeqCst), 2);
+    /// # Ok::<(), dome_cloomnik::Error>(())
+    /// ```
     #[inline]
     pub unsafe fn set_slot_new_foreign_unchecked<T: 'static>(
         &mut self,
@@ -330,11 +742,96 @@ impl VM {
         class_slot: usize,
         instance: T,
     ) -> &mut T {
-        let foreign_size = mem::size_of::<ForeignWrapper<T>>();
-        let foreign = self.set_slot_new_raw_foreign_unchecked(slot, class_slot, foreign_size);
-        let foreign = foreign as *mut ForeignWrapper<T>;
-        ptr::write(foreign, ForeignWrapper::new(instance));
-        &mut (*foreign).foreign
+        let foreign =
+            self.set_slot_new_raw_foreign_unchecked(slot, class_slot, ForeignWrapper::<T>::SIZE);
+        &mut *ForeignWrapper::write(foreign, instance)
+    }
+
+    /// Sets `slot` to a new instance of the foreign class whose objects are of type `T`,
+    /// holding `instance`.
+    ///
+    /// The class must have been registered with [`register_modules!`][crate::register_modules!]
+    /// or [`register_classes!`][crate::register_classes!], which remember the class of every
+    /// Rust type, and Wren must have imported its module. DOME does not tell plugins about
+    /// imports, so a module counts as imported once Wren called a foreign method or allocated
+    /// an object of one of its classes. Therefore, the foreign methods of a module can always
+    /// create instances of its classes. The class is looked up once, and then cached. Panics
+    /// if the slot is invalid, the type is not registered or its module is not imported yet.
+    /// For a non-panicking version, see [`try_new_foreign()`][Self::try_new_foreign()].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use dome_cloomnik::testing::{MockHost, Value};
+    /// use dome_cloomnik::{register_modules, ForeignRef, WrenVM};
+    ///
+    /// struct Vec2(f64, f64);
+    /// impl Vec2 {
+    ///     fn new(vm: &WrenVM) -> Self {
+    ///         Vec2(vm.get_slot_double(1), vm.get_slot_double(2))
+    ///     }
+    ///     fn add(&self, vm: &mut WrenVM, other: ForeignRef<Vec2>) {
+    ///         vm.new_foreign(0, Vec2(self.0 + other.0, self.1 + other.1));
+    ///     }
+    ///     fn x(&self) -> f64 {
+    ///         self.0
+    ///     }
+    /// }
+    ///
+    /// let host = MockHost::new();
+    /// let mut ctx = host.context();
+    /// (register_modules! {
+    ///     ctx,
+    ///     module "vector" {
+    ///         foreign class Vec2 = new of Vec2 {
+    ///             "construct new(x, y) {}"
+    ///             foreign add(other) = add
+    ///             foreign x = x
+    ///         }
+    ///     }
+    /// })?;
+    ///
+    /// // The class only exists once Wren imported its module.
+    /// let result = host.with_vm(&[Value::Null], |vm| vm.try_new_foreign(0, Vec2(0.0, 0.0)).map(|_| ()));
+    /// assert_eq!(
+    ///     result.unwrap().unwrap_err().to_string(),
+    ///     "The foreign class of Rust type `Vec2` is in module 'vector', which is not imported yet.",
+    /// );
+    /// host.import("vector");
+    ///
+    /// let a = host.construct("vector", "Vec2", &[1.0.into(), 2.0.into()]).unwrap();
+    /// let b = host.construct("vector", "Vec2", &[3.0.into(), 4.0.into()]).unwrap();
+    /// // Replacing the receiver frees it, but it stays alive until `add()` returns.
+    /// let sum = host.call("vector", "Vec2.add(_)", [a, b]).unwrap();
+    /// assert_eq!(host.call("vector", "Vec2.x", &[sum]), Ok(Value::Num(4.0)));
+    ///
+    /// // Types that do not back a foreign class cannot be instantiated.
+    /// let result = host.with_vm(&[Value::Null], |vm| vm.try_new_foreign(0, 1u8).map(|_| ()));
+    /// assert_eq!(
+    ///     result.unwrap().unwrap_err().to_string(),
+    ///     "Rust type `u8` is not registered as a foreign class.",
+    /// );
+    /// # Ok::<(), dome_cloomnik::Error>(())
+    /// ```
+    #[inline]
+    pub fn new_foreign<T: 'static>(&mut self, slot: usize, instance: T) -> &mut T {
+        self.try_new_foreign(slot, instance)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Sets `slot` to a new instance of the foreign class whose objects are of type `T`,
+    /// or returns an error if the slot does not exist, the type is not registered or its
+    /// module is not imported yet.
+    ///
+    /// See [`new_foreign()`][Self::new_foreign()] for more.
+    #[inline]
+    pub fn try_new_foreign<T: 'static>(&mut self, slot: usize, instance: T) -> Result<&mut T> {
+        self.try_validate_slot(slot)?;
+        // SAFETY: We validated the slot. Registered classes are foreign classes whose
+        // objects are `T`s, so the class can allocate `instance`.
+        unsafe {
+            registry::load_foreign_class::<T>(self, slot)?;
+            Ok(self.set_slot_new_foreign_unchecked(slot, slot, instance))
+        }
     }
 
     /// Gets `Bool` from `slot`.
@@ -357,6 +854,16 @@ impl VM {
         // SAFETY: We verified that the slot exists and contains a `Bool`.
         unsafe { self.get_slot_bool_unchecked(slot) }
     }
+    /// Gets `Bool` from `slot`, or an error if the slot does not exist or does not contain
+    /// a `Bool`.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    #[inline]
+    pub fn try_get_slot_bool(&self, slot: usize) -> Result<bool> {
+        self.try_validate_slot_type(slot, Type::Bool)?;
+        // SAFETY: We verified that the slot exists and contains a `Bool`.
+        Ok(unsafe { self.get_slot_bool_unchecked(slot) })
+    }
 
     /// Gets `Num` from `slot`.
     ///
@@ -378,6 +885,28 @@ impl VM {
         // SAFETY: We verified that the slot exists and contains a `Num`.
         unsafe { self.get_slot_double_unchecked(slot) }
     }
+    /// Gets `Num` from `slot`, or an error if the slot does not exist or does not contain a `Num`.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # let host = dome_cloomnik::testing::MockHost::new();
+    /// use dome_cloomnik::testing::Value;
+    ///
+    /// host.with_vm(&[Value::Null, Value::from("text")], |vm| {
+    ///     let error = vm.try_get_slot_double(1).unwrap_err();
+    ///     assert_eq!(error.to_string(), "Argument 1 must be Num, got String.");
+    /// })
+    /// .unwrap();
+    /// ```
+    #[inline]
+    pub fn try_get_slot_double(&self, slot: usize) -> Result<f64> {
+        self.try_validate_slot_type(slot, Type::Num)?;
+        // SAFETY: We verified that the slot exists and contains a `Num`.
+        Ok(unsafe { self.get_slot_double_unchecked(slot) })
+    }
 
     /// Gets a `String` as a sequence of bytes from `slot`.
     ///
@@ -391,10 +920,7 @@ impl VM {
     /// You must provide this function a `slot` that is valid and contains a `String`.
     #[inline]
     pub unsafe fn get_slot_bytes_unchecked(&self, slot: usize) -> Vec<u8> {
-        let mut length: c_int = 0;
-        let data = (Api::wren().get_slot_bytes)(self.0, slot.try_into().unwrap(), &mut length)
-            as *const u8;
-        slice::from_raw_parts(data, length.try_into().unwrap()).to_owned()
+        self.slot_bytes(slot).to_owned()
     }
     /// Gets a `String` as a sequence of bytes from `slot`.
     ///
@@ -408,6 +934,19 @@ impl VM {
         // SAFETY: We verified that the slot exists and contains a `String`.
         unsafe { self.get_slot_bytes_unchecked(slot) }
     }
+    /// Gets a `String` as a sequence of bytes from `slot`, or an error if the slot does not
+    /// exist or does not contain a `String`.
+    ///
+    /// This function copies the string and so it remains valid even after you give
+    /// the control back to Wren.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    #[inline]
+    pub fn try_get_slot_bytes(&self, slot: usize) -> Result<Vec<u8>> {
+        self.try_validate_slot_type(slot, Type::String)?;
+        // SAFETY: We verified that the slot exists and contains a `String`.
+        Ok(unsafe { self.get_slot_bytes_unchecked(slot) })
+    }
 
     /// Gets a `String` as Rust [`String`] from `slot`.
     ///
@@ -429,10 +968,7 @@ impl VM {
         &self,
         slot: usize,
     ) -> std::result::Result<String, str::Utf8Error> {
-        let mut length: c_int = 0;
-        let data = (Api::wren().get_slot_bytes)(self.0, slot.try_into().unwrap(), &mut length)
-            as *const u8;
-        Ok(str::from_utf8(slice::from_raw_parts(data, length.try_into().unwrap()))?.to_owned())
+        str::from_utf8(self.slot_bytes(slot)).map(ToOwned::to_owned)
     }
     /// Gets a `String` as Rust [`String`] from `slot`.
     ///
@@ -451,6 +987,210 @@ impl VM {
         // SAFETY: We verified that the slot exists and contains a `String`.
         unsafe { self.get_slot_string_unchecked(slot) }
     }
+    /// Gets a `String` as Rust [`String`] from `slot`, or an error if the slot does not
+    /// exist, does not contain a `String` or the string is not valid UTF-8.
+    ///
+    /// This function copies the string and so it remains valid even after you give
+    /// the control back to Wren.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    #[inline]
+    pub fn try_get_slot_string(&self, slot: usize) -> Result<String> {
+        self.try_validate_slot_type(slot, Type::String)?;
+        // SAFETY: We verified that the slot exists and contains a `String`.
+        unsafe { self.get_slot_string_unchecked(slot) }.map_err(|err| Error::SlotConversionFailed {
+            slot,
+            reason: err.to_string(),
+        })
+    }
+
+    /// Borrows a `String` as a sequence of bytes from `slot`, without copying it.
+    ///
+    /// The bytes are borrowed from the VM, so they cannot outlive the string in the slot:
+    /// writing to slots requires a mutable borrow, and the scratch slots that reading values
+    /// with a shared borrow needs (e.g. [`get()`][Self::get()] for lists) are always
+    /// reserved above the borrowed slots.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    ///
+    /// # Safety
+    ///
+    /// You must provide this function a `slot` that is valid and contains a `String`.
+    #[inline]
+    pub unsafe fn get_slot_bytes_ref_unchecked(&self, slot: usize) -> &[u8] {
+        frame::borrow_slot(slot);
+        self.slot_bytes(slot)
+    }
+    /// Borrows a `String` as a sequence of bytes from `slot`, without copying it.
+    ///
+    /// The bytes are borrowed from the VM, so they cannot outlive the string in the slot:
+    /// writing to slots requires a mutable borrow, and the scratch slots that reading values
+    /// with a shared borrow needs (e.g. [`get()`][Self::get()] for lists) are always
+    /// reserved above the borrowed slots.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    #[inline]
+    pub fn get_slot_bytes_ref(&self, slot: usize) -> &[u8] {
+        self.validate_slot_type(slot, Type::String);
+        // SAFETY: We verified that the slot exists and contains a `String`.
+        unsafe { self.get_slot_bytes_ref_unchecked(slot) }
+    }
+    /// Borrows a `String` as a sequence of bytes from `slot`, without copying it, or returns
+    /// an error if the slot does not exist or does not contain a `String`.
+    ///
+    /// The bytes are borrowed from the VM, so they cannot outlive the string in the slot:
+    /// writing to slots requires a mutable borrow, and the scratch slots that reading values
+    /// with a shared borrow needs (e.g. [`get()`][Self::get()] for lists) are always
+    /// reserved above the borrowed slots.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    #[inline]
+    pub fn try_get_slot_bytes_ref(&self, slot: usize) -> Result<&[u8]> {
+        self.try_validate_slot_type(slot, Type::String)?;
+        // SAFETY: We verified that the slot exists and contains a `String`.
+        Ok(unsafe { self.get_slot_bytes_ref_unchecked(slot) })
+    }
+
+    /// The bytes of the `String` in `slot`, for copying them right away. Unlike
+    /// [`get_slot_bytes_ref_unchecked()`][Self::get_slot_bytes_ref_unchecked()], scratch
+    /// slots may overwrite the slot afterwards.
+    ///
+    /// # Safety
+    ///
+    /// `slot` must be valid and contain a `String`.
+    #[inline]
+    unsafe fn slot_bytes(&self, slot: usize) -> &[u8] {
+        let mut length: c_int = 0;
+        let data = (Api::wren().get_slot_bytes)(self.0, slot.try_into().unwrap(), &mut length)
+            as *const u8;
+        slice::from_raw_parts(data, length.try_into().unwrap())
+    }
+
+    /// Borrows a `String` as a Rust [`str`] from `slot`, without copying it. Fails if the
+    /// string is not valid UTF-8; see
+    /// [`get_slot_str_lossy_unchecked()`][Self::get_slot_str_lossy_unchecked()] for a
+    /// conversion that does not.
+    ///
+    /// The string is borrowed from the VM, so it cannot outlive the string in the slot:
+    /// writing to slots requires a mutable borrow, and the scratch slots that reading values
+    /// with a shared borrow needs (e.g. [`get()`][Self::get()] for lists) are always
+    /// reserved above the borrowed slots.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    ///
+    /// # Safety
+    ///
+    /// You must provide this function a `slot` that is valid and contains a `String`.
+    #[inline]
+    pub unsafe fn get_slot_str_unchecked(
+        &self,
+        slot: usize,
+    ) -> std::result::Result<&str, str::Utf8Error> {
+        str::from_utf8(self.get_slot_bytes_ref_unchecked(slot))
+    }
+    /// Borrows a `String` as a Rust [`str`] from `slot`, without copying it. Fails if the
+    /// string is not valid UTF-8; see [`get_slot_str_lossy()`][Self::get_slot_str_lossy()]
+    /// for a conversion that does not.
+    ///
+    /// The string is borrowed from the VM, so it cannot outlive the string in the slot:
+    /// writing to slots requires a mutable borrow, and the scratch slots that reading values
+    /// with a shared borrow needs (e.g. [`get()`][Self::get()] for lists) are always
+    /// reserved above the borrowed slots.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use dome_cloomnik::testing::{MockHost, Value};
+    /// use dome_cloomnik::WrenType;
+    ///
+    /// let host = MockHost::new();
+    /// let result = host.with_vm(&["north".into(), Value::String(b"sou\xFFth".to_vec())], |vm| {
+    ///     assert_eq!(vm.get_slot_str(0), Ok("north"));
+    ///     assert!(vm.get_slot_str(1).is_err());
+    ///     assert_eq!(vm.get_slot_bytes_ref(1), b"sou\xFFth");
+    ///     assert_eq!(vm.get_slot_str_lossy(1), "sou\u{FFFD}th");
+    ///
+    ///     let direction = vm.get_slot_str(0).unwrap();
+    ///     // Does not compile: the string is borrowed from `vm`.
+    ///     // vm.set_slot_null(0);
+    ///     direction.len()
+    /// });
+    /// assert_eq!(result, Ok(5));
+    ///
+    /// // Reading lists needs scratch slots, but never reuses the slot of a borrowed string.
+    /// let numbers = Value::list(vec![Value::Num(1.0), Value::Num(2.0)]);
+    /// let result = host.with_vm(&[numbers], |vm| {
+    ///     let mut frame = vm.slot_frame();
+    ///     let slot = frame.slot();
+    ///     frame.set_slot_string(slot, "kept");
+    ///     drop(frame);
+    ///     let kept = vm.get_slot_str(slot).unwrap();
+    ///     let numbers: Vec<f64> = vm.get(0);
+    ///     (kept.to_owned(), vm.get_slot_type(slot), numbers)
+    /// });
+    /// assert_eq!(result, Ok(("kept".to_owned(), WrenType::String, vec![1.0, 2.0])));
+    /// ```
+    #[inline]
+    pub fn get_slot_str(&self, slot: usize) -> std::result::Result<&str, str::Utf8Error> {
+        self.validate_slot_type(slot, Type::String);
+        // SAFETY: We verified that the slot exists and contains a `String`.
+        unsafe { self.get_slot_str_unchecked(slot) }
+    }
+    /// Borrows a `String` as a Rust [`str`] from `slot`, without copying it, or returns an
+    /// error if the slot does not exist, does not contain a `String` or the string is not
+    /// valid UTF-8.
+    ///
+    /// The string is borrowed from the VM, so it cannot outlive the string in the slot:
+    /// writing to slots requires a mutable borrow, and the scratch slots that reading values
+    /// with a shared borrow needs (e.g. [`get()`][Self::get()] for lists) are always
+    /// reserved above the borrowed slots.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    #[inline]
+    pub fn try_get_slot_str(&self, slot: usize) -> Result<&str> {
+        self.try_validate_slot_type(slot, Type::String)?;
+        // SAFETY: We verified that the slot exists and contains a `String`.
+        unsafe { self.get_slot_str_unchecked(slot) }.map_err(|err| Error::SlotConversionFailed {
+            slot,
+            reason: err.to_string(),
+        })
+    }
+
+    /// Gets a `String` as a Rust [`str`] from `slot`, replacing invalid UTF-8 sequences
+    /// with `U+FFFD REPLACEMENT CHARACTER`. Copies the string only if it is not valid UTF-8.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    ///
+    /// # Safety
+    ///
+    /// You must provide this function a `slot` that is valid and contains a `String`.
+    #[inline]
+    pub unsafe fn get_slot_str_lossy_unchecked(&self, slot: usize) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.get_slot_bytes_ref_unchecked(slot))
+    }
+    /// Gets a `String` as a Rust [`str`] from `slot`, replacing invalid UTF-8 sequences
+    /// with `U+FFFD REPLACEMENT CHARACTER`. Copies the string only if it is not valid UTF-8.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    #[inline]
+    pub fn get_slot_str_lossy(&self, slot: usize) -> Cow<'_, str> {
+        self.validate_slot_type(slot, Type::String);
+        // SAFETY: We verified that the slot exists and contains a `String`.
+        unsafe { self.get_slot_str_lossy_unchecked(slot) }
+    }
+    /// Gets a `String` as a Rust [`str`] from `slot`, replacing invalid UTF-8 sequences
+    /// with `U+FFFD REPLACEMENT CHARACTER`, or returns an error if the slot does not exist
+    /// or does not contain a `String`. Copies the string only if it is not valid UTF-8.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    #[inline]
+    pub fn try_get_slot_str_lossy(&self, slot: usize) -> Result<Cow<'_, str>> {
+        self.try_validate_slot_type(slot, Type::String)?;
+        // SAFETY: We verified that the slot exists and contains a `String`.
+        Ok(unsafe { self.get_slot_str_lossy_unchecked(slot) })
+    }
 
     /// Gets a foreign object from `slot`.
     ///
@@ -473,6 +1213,16 @@ impl VM {
         // SAFETY: We verified that the slot exists and contains a foreign object.
         unsafe { self.get_slot_raw_foreign_unchecked(slot) }
     }
+    /// Gets a foreign object from `slot`, or an error if the slot does not exist or
+    /// does not contain a foreign object.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    #[inline]
+    pub fn try_get_slot_raw_foreign(&self, slot: usize) -> Result<*mut c_void> {
+        self.try_validate_slot_type(slot, Type::Foreign)?;
+        // SAFETY: We verified that the slot exists and contains a foreign object.
+        Ok(unsafe { self.get_slot_raw_foreign_unchecked(slot) })
+    }
     /// Gets a Rust foreign object from `slot`.
     ///
     /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
@@ -482,10 +1232,9 @@ impl VM {
     /// You must provide this function a `slot` that is valid and contains a Rust foreign
     /// class instance, created using [`set_slot_new_foreign::<T>()`], with the same `T`.
     #[inline]
+    #[allow(clippy::mut_from_ref)]
     pub unsafe fn get_slot_foreign_unchecked<T: 'static>(&self, slot: usize) -> &mut T {
-        let foreign = self.get_slot_raw_foreign_unchecked(slot);
-        let foreign = foreign as *mut ForeignWrapper<T>;
-        &mut (*foreign).foreign
+        ForeignWrapper::get(self.get_slot_raw_foreign_unchecked(slot))
     }
     /// Gets a Rust foreign object from `slot`.
     ///
@@ -493,9 +1242,11 @@ impl VM {
     ///
     /// # Safety
     ///
-    /// This function is less unsafe than [`get_slot_foreign_unchecked()`] because it validates
-    /// the slot it takes, so it will panic on invalid slot or if it does not contain a foreign
-    /// object, or if it _does_ contain a Rust foreign object but not of the type `T`.
+    /// This function is less unsafe than [`get_slot_foreign_unchecked()`] because it
+    /// validates the slot it takes, so it will panic on invalid slot or if it does not
+    /// contain a foreign object, or if it _does_ contain a Rust foreign object but not of
+    /// the type `T` (or of a type that upcasts to `T`, see
+    /// [`Context::register_upcast()`][crate::Context::register_upcast()]).
     ///
     /// See the gap? This function _does_ validate that it got a foreign instance,
     /// but _does not_ validate that this instance is a Rust instance created via
@@ -503,15 +1254,159 @@ impl VM {
     ///
     /// Still, you should prefer using this function over [`get_slot_foreign_unchecked()`]
     /// when performance are not a concern, because there is less risk for bugs.
+    ///
+    /// Neither function tracks borrows, so you must also make sure that the same object is
+    /// not borrowed twice (e.g. when it is passed in two slots).
+    /// [`borrow_foreign()`][Self::borrow_foreign()] and
+    /// [`borrow_foreign_mut()`][Self::borrow_foreign_mut()] check that for you.
     #[inline]
+    #[allow(clippy::mut_from_ref)]
     pub unsafe fn get_slot_foreign<T: 'static>(&self, slot: usize) -> &mut T {
-        let foreign = self.get_slot_raw_foreign(slot);
-        let foreign = foreign as *mut ForeignWrapper<T>;
-        assert!(
-            TypeId::of::<T>() == (*foreign).type_id,
-            "Incorrect type in slot."
-        );
-        &mut (*foreign).foreign
+        self.try_get_slot_foreign(slot)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Gets a Rust foreign object from `slot`, or an error if the slot does not exist, does not
+    /// contain a foreign object, or contains a Rust foreign object of another type. The error
+    /// names the expected and actual Rust types.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    ///
+    /// # Safety
+    ///
+    /// Like [`get_slot_foreign()`][Self::get_slot_foreign()], this function cannot verify
+    /// that the foreign object is a Rust foreign object, nor that it is not borrowed
+    /// elsewhere. You must make sure of that.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dome_cloomnik::testing::{MockHost, Value};
+    /// # use dome_cloomnik::{register_modules, WrenVM};
+    /// # let host = MockHost::new();
+    /// # let mut ctx = host.context();
+    /// struct Synth;
+    /// struct Envelope;
+    /// # impl Synth {
+    /// #     fn new(_vm: &WrenVM) -> Self { Synth }
+    /// # }
+    /// # impl Envelope {
+    /// #     fn new(_vm: &WrenVM) -> Self { Envelope }
+    /// # }
+    /// (register_modules! {
+    ///     ctx,
+    ///     module "synth" {
+    ///         foreign class SynthClass_ = new of Synth {}
+    ///         foreign class Envelope = new of Envelope {}
+    ///     }
+    /// })?;
+    ///
+    /// let envelope = host.construct("synth", "Envelope", &[]).unwrap();
+    /// let result = host.with_vm(&[Value::Null, envelope], |vm| {
+    ///     unsafe { vm.try_get_slot_foreign::<Synth>(1) }.map(|_| ()).unwrap_err().to_string()
+    /// });
+    /// assert_eq!(
+    ///     result.unwrap(),
+    ///     "Argument 1 must be foreign `Synth`, got foreign `Envelope` (Wren class SynthClass_).",
+    /// );
+    /// # Ok::<(), dome_cloomnik::Error>(())
+    /// ```
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn try_get_slot_foreign<T: 'static>(&self, slot: usize) -> Result<&mut T> {
+        let (_, value) = self.try_get_foreign_data::<T>(slot, true)?;
+        Ok(&mut *value)
+    }
+    /// Validates that `slot` contains a Rust foreign object of type `T` (or upcastable to `T`),
+    /// and returns its data and the `T`.
+    ///
+    /// # Safety
+    ///
+    /// If `slot` contains a foreign object, it must be a Rust foreign object.
+    #[inline]
+    pub(super) unsafe fn try_get_foreign_data<T: 'static>(
+        &self,
+        slot: usize,
+        mutable: bool,
+    ) -> Result<(*mut c_void, *mut T)> {
+        let foreign = self.try_get_slot_raw_foreign(slot)?;
+        match foreign_value(foreign, mutable) {
+            Some(value) => Ok((foreign, value)),
+            None => Err(Error::ForeignTypeMismatch {
+                slot,
+                expected: std::any::type_name::<T>(),
+                actual: foreign_type_name(foreign),
+                class: registry::class_name::<T>(),
+            }),
+        }
+    }
+    /// Borrows the Rust foreign object of type `T` in `slot`, like
+    /// [`RefCell::borrow()`][std::cell::RefCell::borrow()].
+    ///
+    /// Fails if the slot does not exist, does not contain a foreign object of type `T`,
+    /// or if the object is currently borrowed mutably.
+    ///
+    /// # Safety
+    ///
+    /// Like [`get_slot_foreign()`][Self::get_slot_foreign()], this function cannot verify
+    /// that the foreign object is a Rust foreign object. You must make sure of that.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use dome_cloomnik::testing::{MockHost, Value};
+    /// # use dome_cloomnik::register_modules;
+    /// # let host = MockHost::new();
+    /// # let mut ctx = host.context();
+    /// # struct Point(f64, f64);
+    /// # impl Point {
+    /// #     fn new(_vm: &dome_cloomnik::WrenVM) -> Self {
+    /// #         Point(0.0, 0.0)
+    /// #     }
+    /// # }
+    /// # (register_modules! {
+    /// #     ctx,
+    /// #     module "point" {
+    /// #         foreign class Point = new of Point {
+    /// #             "construct new() {}"
+    /// #         }
+    /// #     }
+    /// # }).unwrap();
+    /// let point = host.construct("point", "Point", &[])?;
+    /// host.with_vm(&[point.clone(), point], |vm| unsafe {
+    ///     let mut a = vm.borrow_foreign_mut::<Point>(0).unwrap();
+    ///     // Slot 1 contains the same object.
+    ///     assert!(vm.borrow_foreign::<Point>(1).is_err());
+    ///     a.0 = 1.0;
+    ///     drop(a);
+    ///
+    ///     let a = vm.borrow_foreign::<Point>(0).unwrap();
+    ///     let b = vm.borrow_foreign::<Point>(1).unwrap();
+    ///     assert_eq!(a.0, b.0);
+    /// })?;
+    /// # Ok::<(), Value>(())
+    /// ```
+    #[inline]
+    pub unsafe fn borrow_foreign<T: 'static>(&self, slot: usize) -> Result<ForeignRef<'_, T>> {
+        let (data, value) = self.try_get_foreign_data::<T>(slot, false)?;
+        ForeignRef::new(data, value, slot)
+    }
+    /// Borrows the Rust foreign object of type `T` in `slot` mutably, like
+    /// [`RefCell::borrow_mut()`][std::cell::RefCell::borrow_mut()].
+    ///
+    /// Fails if the slot does not exist, does not contain a foreign object of type `T`,
+    /// or if the object is currently borrowed.
+    ///
+    /// # Safety
+    ///
+    /// Like [`get_slot_foreign()`][Self::get_slot_foreign()], this function cannot verify
+    /// that the foreign object is a Rust foreign object. You must make sure of that.
+    #[inline]
+    pub unsafe fn borrow_foreign_mut<T: 'static>(
+        &self,
+        slot: usize,
+    ) -> Result<ForeignRefMut<'_, T>> {
+        let (data, value) = self.try_get_foreign_data::<T>(slot, true)?;
+        ForeignRefMut::new(data, value, slot)
     }
 
     /// Retrieves the list length from the list object at `slot`.
@@ -536,6 +1431,16 @@ impl VM {
         // SAFETY: We verified that the slot exists and contains a `List`.
         unsafe { self.get_list_count_unchecked(slot) }
     }
+    /// Retrieves the list length from the list object at `slot`, or an error if the slot
+    /// does not exist or does not contain a `List`.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    #[inline]
+    pub fn try_get_list_count(&self, slot: usize) -> Result<usize> {
+        self.try_validate_slot_type(slot, Type::List)?;
+        // SAFETY: We verified that the slot exists and contains a `List`.
+        Ok(unsafe { self.get_list_count_unchecked(slot) })
+    }
 
     #[inline]
     fn validate_list_element(&self, list_slot: usize, index: usize) {
@@ -572,6 +1477,7 @@ impl VM {
         )
     }
     /// Retrieves the index-th list element from the list object at `list_slot` into `element_slot`.
+    /// Reserve `element_slot` with a [`SlotFrame`] to avoid overwriting slots in use.
     ///
     /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
     #[inline]
@@ -661,6 +1567,18 @@ impl VM {
         unsafe { self.insert_in_list_unchecked(list_slot, index, element_slot) }
     }
 
+    #[inline]
+    fn validate_map_key(&self, key_slot: usize) {
+        let key_type = self.get_slot_type(key_slot);
+        // Wren can also hash ranges and classes, but we cannot tell them apart from
+        // other objects via the C API.
+        assert!(
+            matches!(key_type, Type::Null | Type::Bool | Type::Num | Type::String),
+            "{:?} cannot be used as a map key.",
+            key_type
+        );
+    }
+
     /// Gets the number of elements in the `Map` at `slot`.
     ///
     /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
@@ -683,6 +1601,16 @@ impl VM {
         // SAFETY: We verified that `slot` exists and contains a `Map`.
         unsafe { self.get_map_count_unchecked(slot) }
     }
+    /// Gets the number of elements in the `Map` at `slot`, or an error if the slot
+    /// does not exist or does not contain a `Map`.
+    ///
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    #[inline]
+    pub fn try_get_map_count(&self, slot: usize) -> Result<usize> {
+        self.try_validate_slot_type(slot, Type::Map)?;
+        // SAFETY: We verified that `slot` exists and contains a `Map`.
+        Ok(unsafe { self.get_map_count_unchecked(slot) })
+    }
 
     /// Inserts the value with the key at `key_slot` in the `Map` at `map_slot` into `value_slot`.
     ///
@@ -708,18 +1636,22 @@ impl VM {
         );
     }
     /// Inserts the value with the key at `key_slot` in the `Map` at `map_slot` into `value_slot`.
+    /// Reserve `key_slot` and `value_slot` with a [`SlotFrame`] to avoid overwriting slots in use.
     ///
-    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    /// The key must be `Null`, `Bool`, `Num` or `String`. Wren can also hash ranges and
+    /// classes, but they can only be used with
+    /// [`get_map_value_unchecked()`][Self::get_map_value_unchecked()]. For a typed
+    /// interface, see [`map()`][Self::map()].
     ///
-    /// # Safety
-    ///
-    /// The value inside `key_slot` must be hashable.
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
     #[inline]
-    pub unsafe fn get_map_value(&mut self, map_slot: usize, key_slot: usize, value_slot: usize) {
+    pub fn get_map_value(&mut self, map_slot: usize, key_slot: usize, value_slot: usize) {
         self.validate_slot_type(map_slot, Type::Map);
-        self.validate_slot(key_slot);
+        self.validate_map_key(key_slot);
         self.validate_slot(value_slot);
-        self.get_map_value_unchecked(map_slot, key_slot, value_slot);
+        // SAFETY: We verified that `map_slot` exists and contains a `Map`, `key_slot`
+        // exists and contains a hashable value and `value_slot` exists.
+        unsafe { self.get_map_value_unchecked(map_slot, key_slot, value_slot) }
     }
 
     /// Sets the value with the key at `key_slot` in the `Map` at `map_slot` to `value_slot`.
@@ -747,17 +1679,19 @@ impl VM {
     }
     /// Sets the value with the key at `key_slot` in the `Map` at `map_slot` to `value_slot`.
     ///
-    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    /// The key must be `Null`, `Bool`, `Num` or `String`. Wren can also hash ranges and
+    /// classes, but they can only be used with
+    /// [`set_map_value_unchecked()`][Self::set_map_value_unchecked()].
     ///
-    /// # Safety
-    ///
-    /// The value inside `key_slot` must be hashable.
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
     #[inline]
-    pub unsafe fn set_map_value(&mut self, map_slot: usize, key_slot: usize, value_slot: usize) {
+    pub fn set_map_value(&mut self, map_slot: usize, key_slot: usize, value_slot: usize) {
         self.validate_slot_type(map_slot, Type::Map);
-        self.validate_slot(key_slot);
+        self.validate_map_key(key_slot);
         self.validate_slot(value_slot);
-        self.set_map_value_unchecked(map_slot, key_slot, value_slot);
+        // SAFETY: We verified that `map_slot` exists and contains a `Map`, `key_slot`
+        // exists and contains a hashable value and `value_slot` exists.
+        unsafe { self.set_map_value_unchecked(map_slot, key_slot, value_slot) }
     }
 
     /// Returns `true` if the `Map` at `map_slot` contains `key_slot`.
@@ -778,16 +1712,18 @@ impl VM {
     }
     /// Returns `true` if the `Map` at `map_slot` contains `key_slot`.
     ///
-    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
+    /// The key must be `Null`, `Bool`, `Num` or `String`. Wren can also hash ranges and
+    /// classes, but they can only be used with
+    /// [`map_contains_key_unchecked()`][Self::map_contains_key_unchecked()].
     ///
-    /// # Safety
-    ///
-    /// The value inside `key_slot` must be hashable.
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
     #[inline]
-    pub unsafe fn map_contains_key(&self, map_slot: usize, key_slot: usize) {
+    pub fn map_contains_key(&self, map_slot: usize, key_slot: usize) -> bool {
         self.validate_slot_type(map_slot, Type::Map);
-        self.validate_slot(key_slot);
-        self.map_contains_key_unchecked(map_slot, key_slot);
+        self.validate_map_key(key_slot);
+        // SAFETY: We verified that `map_slot` exists and contains a `Map`, and `key_slot`
+        // exists and contains a hashable value.
+        unsafe { self.map_contains_key_unchecked(map_slot, key_slot) }
     }
 
     /// Removes the value with the key at `key_slot` in the `Map` at `map_slot` and stores
@@ -817,22 +1753,24 @@ impl VM {
     /// Removes the value with the key at `key_slot` in the `Map` at `map_slot` and stores
     /// the removed value at `value_slot`.
     ///
-    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
-    ///
-    /// # Safety
+    /// The key must be `Null`, `Bool`, `Num` or `String`. Wren can also hash ranges and
+    /// classes, but they can only be used with
+    /// [`remove_map_value_unchecked()`][Self::remove_map_value_unchecked()].
     ///
-    /// The value inside `key_slot` must be hashable.
+    /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
     #[inline]
-    pub unsafe fn remove_map_value(
+    pub fn remove_map_value(
         &mut self,
         map_slot: usize,
         key_slot: usize,
         removed_value_slot: usize,
     ) {
         self.validate_slot_type(map_slot, Type::Map);
-        self.validate_slot(key_slot);
+        self.validate_map_key(key_slot);
         self.validate_slot(removed_value_slot);
-        self.remove_map_value_unchecked(map_slot, key_slot, removed_value_slot);
+        // SAFETY: We verified that `map_slot` exists and contains a `Map`, `key_slot`
+        // exists and contains a hashable value and `removed_value_slot` exists.
+        unsafe { self.remove_map_value_unchecked(map_slot, key_slot, removed_value_slot) }
     }
 
     /// Aborts the current fiber with the error at `slot`.
@@ -851,6 +1789,37 @@ impl VM {
         // SAFETY: We verified that `slot` exists.
         unsafe { self.abort_fiber_unchecked(slot) }
     }
+    /// Aborts the current fiber with `error` as the error message.
+    ///
+    /// This uses a new slot for the message, and so it does not override any slot.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # let host = dome_cloomnik::testing::MockHost::new();
+    /// use dome_cloomnik::testing::Value;
+    /// use dome_cloomnik::WrenVM;
+    ///
+    /// fn sqrt(vm: &mut WrenVM) -> dome_cloomnik::Result {
+    ///     let value = vm.try_get_slot_double(1)?;
+    ///     vm.set_slot_double(0, value.sqrt());
+    ///     Ok(())
+    /// }
+    ///
+    /// let result = host.with_vm(&[Value::Null, Value::Null], |vm| {
+    ///     if let Err(err) = sqrt(vm) {
+    ///         vm.abort_fiber_with(err);
+    ///     }
+    /// });
+    /// assert_eq!(result, Err(Value::from("Argument 1 must be Num, got Null.")));
+    /// ```
+    #[inline]
+    pub fn abort_fiber_with(&mut self, error: impl fmt::Display) {
+        let mut frame = self.slot_frame();
+        let slot = frame.slot();
+        frame.set_slot_string(slot, &error.to_string());
+        frame.abort_fiber(slot);
+    }
 
     /// Retrieves the variable with `name` in `module` int `slot`..
     ///
@@ -888,9 +1857,11 @@ impl VM {
     /// You must provide this function a valid `slot`.
     #[inline]
     pub unsafe fn get_slot_handle_unchecked(&mut self, slot: usize) -> Handle {
+        let handle = (Api::wren().get_slot_handle)(self.0, slot.try_into().unwrap());
         Handle {
-            handle: (Api::wren().get_slot_handle)(self.0, slot.try_into().unwrap()),
+            handle,
             vm: self.0,
+            id: handles::register(self.0, handle),
         }
     }
     /// Retrieves a long-lived [`Handle`] from a short-lived `slot`.
@@ -903,7 +1874,8 @@ impl VM {
         unsafe { self.get_slot_handle_unchecked(slot) }
     }
 
-    /// Sets `slot` to `handle`.
+    /// Sets `slot` to `handle`. Typed handles, like [`TypedHandle`][crate::TypedHandle]
+    /// and [`ClassHandle`][crate::ClassHandle], can be used too.
     ///
     /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
     ///
@@ -911,16 +1883,90 @@ impl VM {
     ///
     /// You must provide this function a valid `slot`.
     #[inline]
-    pub unsafe fn set_slot_handle_unchecked(&mut self, slot: usize, handle: &Handle) {
-        (Api::wren().set_slot_handle)(self.0, slot.try_into().unwrap(), handle.handle)
+    pub unsafe fn set_slot_handle_unchecked(&mut self, slot: usize, handle: &impl AsRef<Handle>) {
+        (Api::wren().set_slot_handle)(self.0, slot.try_into().unwrap(), handle.as_ref().handle)
     }
-    /// Retrieves a long-lived [`Handle`] from a short-lived `slot`.
+    /// Sets `slot` to `handle`. Typed handles, like [`TypedHandle`][crate::TypedHandle]
+    /// and [`ClassHandle`][crate::ClassHandle], can be used too.
     ///
     /// See [Wren docs](https://wren.io/embedding/slots-and-handles.html) for more.
     #[inline]
-    pub fn set_slot_handle(&mut self, slot: usize, handle: &Handle) {
+    pub fn set_slot_handle(&mut self, slot: usize, handle: &impl AsRef<Handle>) {
         self.validate_slot(slot);
         // SAFETY: We just validated the slot.
         unsafe { self.set_slot_handle_unchecked(slot, handle) }
     }
+
+    /// Reads `slot` as a Rust value of type `T`.
+    ///
+    /// This panics if the slot does not contain a value convertible to `T`. For a non-panicking
+    /// version, see [`try_get()`][Self::try_get()].
+    /// See [`FromWren`] for the available conversions.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # let host = dome_cloomnik::testing::MockHost::new();
+    /// use dome_cloomnik::testing::Value;
+    ///
+    /// let list = Value::list(vec![Value::Num(1.0), Value::Null]);
+    /// host.with_vm(&[Value::from("text"), list], |vm| {
+    ///     assert_eq!(vm.get::<String>(0), "text");
+    ///     assert_eq!(vm.get::<Vec<Option<i32>>>(1), [Some(1), None]);
+    ///
+    ///     vm.set(0, (true, vec![1.5, 2.5]));
+    ///     assert_eq!(vm.get::<(bool, Vec<f64>)>(0), (true, vec![1.5, 2.5]));
+    /// })
+    /// .unwrap();
+    /// ```
+    #[inline]
+    pub fn get<T: FromWren>(&self, slot: usize) -> T {
+        self.try_get(slot).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Reads `slot` as a Rust value of type `T`, or returns an error if the slot does not
+    /// contain a value convertible to `T`.
+    ///
+    /// See [`FromWren`] for the available conversions.
+    #[inline]
+    pub fn try_get<T: FromWren>(&self, slot: usize) -> Result<T> {
+        T::from_wren(self, slot)
+    }
+
+    /// Stores the Rust `value` in `slot`.
+    ///
+    /// See [`ToWren`] for the available conversions.
+    #[inline]
+    pub fn set<T: ToWren>(&mut self, slot: usize, value: T) {
+        value.to_wren(self, slot)
+    }
+
+    /// Views the `List` in `slot`, to read and write its elements as Rust values.
+    ///
+    /// Panics if `slot` does not contain a `List`.
+    #[inline]
+    pub fn list(&mut self, slot: usize) -> WrenList<'_> {
+        self.try_list(slot).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Views the `List` in `slot`, to read and write its elements as Rust values, or returns
+    /// an error if `slot` does not contain a `List`.
+    #[inline]
+    pub fn try_list(&mut self, slot: usize) -> Result<WrenList<'_>> {
+        self.try_validate_slot_type(slot, Type::List)?;
+        Ok(WrenList::new(self, slot))
+    }
+
+    /// Views the `Map` in `slot`, to read and write its entries as Rust values.
+    ///
+    /// Panics if `slot` does not contain a `Map`.
+    #[inline]
+    pub fn map(&mut self, slot: usize) -> WrenMap<'_> {
+        self.try_map(slot).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Views the `Map` in `slot`, to read and write its entries as Rust values, or returns
+    /// an error if `slot` does not contain a `Map`.
+    #[inline]
+    pub fn try_map(&mut self, slot: usize) -> Result<WrenMap<'_>> {
+        self.try_validate_slot_type(slot, Type::Map)?;
+        Ok(WrenMap::new(self, slot))
+    }
 }
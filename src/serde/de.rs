@@ -0,0 +1,346 @@
+use ::serde::de::{self, Error as _, IntoDeserializer, Unexpected, Visitor};
+use ::serde::forward_to_deserialize_any;
+
+use super::Message;
+use crate::{SlotFrame, WrenType as Type, WrenVM as VM};
+
+type Result<T> = std::result::Result<T, Message>;
+
+/// Deserializes the value in `slot`. Nested values are read into scratch slots, each
+/// with its own deserializer.
+pub(super) struct Deserializer<'a> {
+    vm: VM<'a>,
+    slot: usize,
+    /// The number of levels of nested lists and maps that can still be read.
+    depth: usize,
+}
+
+impl<'a> Deserializer<'a> {
+    #[inline]
+    pub(super) fn new(vm: &VM<'a>, slot: usize, depth: usize) -> Self {
+        Self {
+            // SAFETY: The VM is valid for `'a`. Deserializers only write to scratch slots.
+            vm: unsafe { VM::from_raw(vm.0) },
+            slot,
+            depth,
+        }
+    }
+
+    /// A copy of the VM, for frames that are used while `self` is borrowed or moved.
+    #[inline]
+    fn vm(&self) -> VM<'a> {
+        // SAFETY: The VM is valid for `'a`. Deserializers only write to scratch slots.
+        unsafe { VM::from_raw(self.vm.0) }
+    }
+
+    /// A deserializer for a value nested in this one, stored in `slot`.
+    #[inline]
+    fn nested(&self, slot: usize) -> Self {
+        Self::new(&self.vm, slot, self.depth - 1)
+    }
+
+    #[inline]
+    fn slot_type(&self) -> Type {
+        self.vm.get_slot_type(self.slot)
+    }
+
+    /// Fails if this value is a list or map nested too deep.
+    #[inline]
+    fn check_depth(&self) -> Result<()> {
+        if self.depth == 0 {
+            Err(Message::custom(format!(
+                "lists and maps are nested deeper than {} levels",
+                super::MAX_DEPTH
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn invalid_type(&self, expected: &dyn de::Expected) -> Message {
+        let unexpected = match self.slot_type() {
+            Type::Null => Unexpected::Unit,
+            Type::Bool => Unexpected::Bool(self.vm.get_slot_bool(self.slot)),
+            Type::Num => Unexpected::Float(self.vm.get_slot_double(self.slot)),
+            Type::String => Unexpected::Other("string"),
+            Type::List => Unexpected::Seq,
+            Type::Map => Unexpected::Map,
+            Type::Foreign => Unexpected::Other("foreign object"),
+            Type::Unknown => Unexpected::Other("object"),
+        };
+        Message::invalid_type(unexpected, expected)
+    }
+
+    fn seq<'de, V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.check_depth()?;
+        let count = self.vm.get_list_count(self.slot);
+        let vm = self.vm();
+        // SAFETY: Only this deserializer uses the slots of the frame, and it only lends
+        // the visitor strings from them during the call.
+        let mut frame = unsafe { SlotFrame::scratch(&vm) };
+        let element_slot = frame.slot();
+        let mut access = SeqAccess {
+            de: self,
+            element_slot,
+            index: 0,
+            count,
+        };
+        let value = visitor.visit_seq(&mut access)?;
+        if access.index < count {
+            return Err(Message::invalid_length(
+                count,
+                &format!("{} elements", access.index).as_str(),
+            ));
+        }
+        Ok(value)
+    }
+
+    /// Stores `key` in `key_slot`, and returns whether the map contains it.
+    fn lookup(&mut self, key_slot: usize, key: &str) -> bool {
+        self.vm.set_slot_string(key_slot, key);
+        // SAFETY: This is a map, and strings are hashable.
+        unsafe { self.vm.map_contains_key_unchecked(self.slot, key_slot) }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'_> {
+    type Error = Message;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.slot_type() {
+            Type::Null => visitor.visit_unit(),
+            Type::Bool => visitor.visit_bool(self.vm.get_slot_bool(self.slot)),
+            Type::Num => {
+                let number = self.vm.get_slot_double(self.slot);
+                // Integer visitors do not accept floats, but float visitors accept integers.
+                if number.fract() != 0.0 {
+                    visitor.visit_f64(number)
+                } else if number >= 0.0 && number < u64::MAX as f64 {
+                    visitor.visit_u64(number as u64)
+                } else if number >= i64::MIN as f64 {
+                    visitor.visit_i64(number as i64)
+                } else {
+                    visitor.visit_f64(number)
+                }
+            }
+            Type::String => match self.vm.get_slot_str(self.slot) {
+                Ok(string) => visitor.visit_str(string),
+                Err(_) => visitor.visit_bytes(self.vm.get_slot_bytes_ref(self.slot)),
+            },
+            Type::List => self.seq(visitor),
+            Type::Map => Err(Message::custom(
+                "maps can only be deserialized into structs and enums",
+            )),
+            Type::Foreign | Type::Unknown => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.slot_type() {
+            Type::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    #[inline]
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self.slot_type() {
+            Type::Map => {
+                self.check_depth()?;
+                let vm = self.vm();
+                // SAFETY: Only this deserializer uses the slots of the frame, and it only lends
+                // the visitor strings from them during the call.
+                let mut frame = unsafe { SlotFrame::scratch(&vm) };
+                let key_slot = frame.slot();
+                let value_slot = frame.slot();
+                visitor.visit_map(StructAccess {
+                    de: self,
+                    fields: fields.iter(),
+                    key_slot,
+                    value_slot,
+                })
+            }
+            Type::List => self.seq(visitor),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        mut self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self.slot_type() {
+            Type::String => match self.vm.get_slot_str(self.slot) {
+                Ok(variant) => visitor.visit_enum(variant.into_deserializer()),
+                Err(_) => Err(self.invalid_type(&visitor)),
+            },
+            Type::Map => {
+                self.check_depth()?;
+                let vm = self.vm();
+                // SAFETY: Only this deserializer uses the slots of the frame, and it only lends
+                // the visitor strings from them during the call.
+                let mut frame = unsafe { SlotFrame::scratch(&vm) };
+                let key_slot = frame.slot();
+                let value_slot = frame.slot();
+                let variant = match variants
+                    .iter()
+                    .find(|variant| self.lookup(key_slot, variant))
+                {
+                    // Other entries would be silently ignored otherwise.
+                    Some(variant) if self.vm.get_map_count(self.slot) == 1 => variant,
+                    _ => {
+                        return Err(Message::custom(format!(
+                            "expected a map with a single entry, one of the variants {}",
+                            variants.join(", ")
+                        )))
+                    }
+                };
+                // SAFETY: This is a map, and the key is the variant name we just looked up.
+                unsafe {
+                    self.vm
+                        .get_map_value_unchecked(self.slot, key_slot, value_slot)
+                };
+                visitor.visit_enum(EnumAccess {
+                    variant,
+                    value: self.nested(value_slot),
+                })
+            }
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    #[inline]
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit unit_struct seq tuple tuple_struct map identifier
+    }
+}
+
+struct SeqAccess<'a> {
+    de: Deserializer<'a>,
+    element_slot: usize,
+    index: usize,
+    count: usize,
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess<'_> {
+    type Error = Message;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>> {
+        if self.index == self.count {
+            return Ok(None);
+        }
+        self.de
+            .vm
+            .get_list_element(self.de.slot, self.index, self.element_slot);
+        self.index += 1;
+        seed.deserialize(self.de.nested(self.element_slot))
+            .map(Some)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.count - self.index)
+    }
+}
+
+/// Reads the fields of a struct from a map. Keys that are not fields are ignored, since
+/// they cannot be enumerated.
+struct StructAccess<'a> {
+    de: Deserializer<'a>,
+    fields: std::slice::Iter<'static, &'static str>,
+    key_slot: usize,
+    value_slot: usize,
+}
+
+impl<'de> de::MapAccess<'de> for StructAccess<'_> {
+    type Error = Message;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        for field in &mut self.fields {
+            if self.de.lookup(self.key_slot, field) {
+                return seed.deserialize(field.into_deserializer()).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        // SAFETY: This is a map, and `key_slot` holds the key `next_key_seed()` found.
+        unsafe {
+            self.de
+                .vm
+                .get_map_value_unchecked(self.de.slot, self.key_slot, self.value_slot)
+        };
+        seed.deserialize(self.de.nested(self.value_slot))
+    }
+}
+
+/// An enum variant other than a unit variant: a map from the variant name to its contents.
+struct EnumAccess<'a> {
+    variant: &'static str,
+    value: Deserializer<'a>,
+}
+
+impl<'a, 'de> de::EnumAccess<'de> for EnumAccess<'a> {
+    type Error = Message;
+    type Variant = Deserializer<'a>;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant)> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, self.value))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Deserializer<'_> {
+    type Error = Message;
+
+    #[inline]
+    fn unit_variant(self) -> Result<()> {
+        de::Deserialize::deserialize(self)
+    }
+
+    #[inline]
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(self)
+    }
+
+    #[inline]
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    #[inline]
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        de::Deserializer::deserialize_struct(self, "", fields, visitor)
+    }
+}
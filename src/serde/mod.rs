@@ -0,0 +1,183 @@
+//! Converting Rust values from and to Wren slots with [serde](https://serde.rs).
+//!
+//! Requires the `serde` feature.
+//!
+//! Wren values map to the serde data model as follows:
+//!
+//!  - `Null` is `None` and `()`. Every other value is `Some`.
+//!  - `Bool`s are `bool`s, and `Num`s are floats and integers. Reading an integer requires
+//!    the number to be integral and in range.
+//!  - `String`s are strings and chars, or bytes if they are not valid UTF-8.
+//!  - `List`s are sequences and tuples.
+//!  - `Map`s are structs, and maps when serializing. Wren's embedding API cannot enumerate
+//!    the keys of a map, so deserialization looks up the fields the struct declares, and
+//!    deserializing other maps (e.g. `HashMap`) is not supported.
+//!  - Enums are externally tagged, like in `serde_json`: unit variants are `String`s, and
+//!    other variants are maps with a single entry mapping the name of the variant to its contents.
+//!
+//! Foreign objects and other values that cannot be represented fail to deserialize.
+//!
+//! # Example
+//!
+//! ```
+//! use dome_cloomnik::serde::{from_slot, to_slot, Serde};
+//! use dome_cloomnik::testing::{MockHost, Value};
+//! use dome_cloomnik::register_modules;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Debug, PartialEq, Deserialize, Serialize)]
+//! struct Level {
+//!     name: String,
+//!     size: (u32, u32),
+//!     enemies: Vec<Enemy>,
+//!     music: Option<String>,
+//! }
+//!
+//! #[derive(Debug, PartialEq, Deserialize, Serialize)]
+//! enum Enemy {
+//!     Bat,
+//!     Ghost { speed: f64 },
+//! }
+//!
+//! struct Game;
+//! impl Game {
+//!     fn load(level: Serde<Level>) -> String {
+//!         format!("{} ({} enemies)", level.0.name, level.0.enemies.len())
+//!     }
+//! }
+//!
+//! let host = MockHost::new();
+//! let mut ctx = host.context();
+//! (register_modules! {
+//!     ctx,
+//!     module "game" {
+//!         class Game = Game {
+//!             foreign static load(level) = load
+//!         }
+//!     }
+//! })?;
+//!
+//! let level = Value::map(vec![
+//!     ("name".into(), "Crypt".into()),
+//!     ("size".into(), Value::list(vec![Value::Num(64.0), Value::Num(48.0)])),
+//!     ("enemies".into(), Value::list(vec![
+//!         "Bat".into(),
+//!         Value::map(vec![("Ghost".into(), Value::map(vec![("speed".into(), Value::Num(2.5))]))]),
+//!     ])),
+//! ]);
+//! let result = host.call("game", "static Game.load(_)", &[Value::Null, level.clone()]);
+//! assert_eq!(result, Ok(Value::from("Crypt (2 enemies)")));
+//!
+//! // Values can also be written back.
+//! let result = host.with_vm(&[level], |vm| {
+//!     let level = from_slot::<Level>(vm, 0).unwrap();
+//!     assert_eq!(level.enemies, [Enemy::Bat, Enemy::Ghost { speed: 2.5 }]);
+//!     to_slot(vm, 0, &level).unwrap();
+//!     from_slot::<Level>(vm, 0).unwrap()
+//! });
+//! assert_eq!(result.unwrap().size, (64, 48));
+//!
+//! // Integers can be as large as their type allows.
+//! let result = host.with_vm(&[Value::Num(2f64.powi(63))], |vm| from_slot::<u64>(vm, 0).unwrap());
+//! assert_eq!(result, Ok(1 << 63));
+//!
+//! // Enum variants with contents are maps with exactly one entry.
+//! let enemy = Value::map(vec![("Ghost".into(), Value::Null), ("Bat".into(), Value::Null)]);
+//! let result = host.with_vm(&[enemy], |vm| from_slot::<Enemy>(vm, 0).unwrap_err().to_string());
+//! assert_eq!(
+//!     result,
+//!     Ok("Slot 0 is invalid: expected a map with a single entry, one of the variants Bat, Ghost.".to_owned()),
+//! );
+//!
+//! let result = host.call("game", "static Game.load(_)", &[Value::Null, Value::map(vec![])]);
+//! assert_eq!(result, Err(Value::from("Invalid argument `level`. Argument 1 is invalid: missing field `name`.")));
+//! # Ok::<(), dome_cloomnik::Error>(())
+//! ```
+
+mod de;
+mod ser;
+
+use std::fmt;
+
+use ::serde::de::DeserializeOwned;
+use ::serde::Serialize;
+
+use crate::errors::{Error, Result};
+use crate::{FromWren, ToWren, WrenVM};
+
+/// The maximum depth of nested lists and maps. Wren values may contain themselves.
+const MAX_DEPTH: usize = 64;
+
+/// Errors while (de)serializing. Converted to [`Error`] with the slot at the end.
+#[derive(Debug)]
+struct Message(String);
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Message {}
+
+impl ::serde::de::Error for Message {
+    #[inline]
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Message(msg.to_string())
+    }
+}
+
+impl ::serde::ser::Error for Message {
+    #[inline]
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Message(msg.to_string())
+    }
+}
+
+/// Deserializes a `T` from `slot`.
+///
+/// Fails if the slot does not exist or its contents do not match `T`. See the
+/// [module documentation][self] for how Wren values map to Rust values.
+///
+/// Reserves the scratch slots it needs with a [`SlotFrame`][crate::SlotFrame].
+#[inline]
+pub fn from_slot<T: DeserializeOwned>(vm: &WrenVM, slot: usize) -> Result<T> {
+    vm.try_get_slot_type(slot)?;
+    T::deserialize(de::Deserializer::new(vm, slot, MAX_DEPTH))
+        .map_err(|Message(reason)| Error::SlotConversionFailed { slot, reason })
+}
+
+/// Serializes `value` into `slot`, creating new lists and maps for it.
+///
+/// Fails if the slot does not exist or `value` cannot be represented in Wren, e.g. if it
+/// contains a map whose keys are not `Null`, `Bool`, `Num` or `String`.
+///
+/// Reserves the scratch slots it needs with a [`SlotFrame`][crate::SlotFrame].
+#[inline]
+pub fn to_slot<T: Serialize + ?Sized>(vm: &mut WrenVM, slot: usize, value: &T) -> Result {
+    vm.try_get_slot_type(slot)?;
+    value
+        .serialize(ser::Serializer::new(vm, slot, MAX_DEPTH))
+        .map_err(|Message(reason)| Error::SlotSerializationFailed { slot, reason })
+}
+
+/// A wrapper that converts `T` with serde, to use as foreign method parameters and return
+/// values.
+///
+/// Converting it to Wren panics if [`to_slot()`] fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Serde<T>(pub T);
+
+impl<T: DeserializeOwned> FromWren for Serde<T> {
+    #[inline]
+    fn from_wren(vm: &WrenVM, slot: usize) -> Result<Self> {
+        from_slot(vm, slot).map(Serde)
+    }
+}
+
+impl<T: Serialize> ToWren for Serde<T> {
+    #[inline]
+    fn to_wren(&self, vm: &mut WrenVM, slot: usize) {
+        to_slot(vm, slot, &self.0).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
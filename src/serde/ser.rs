@@ -0,0 +1,473 @@
+use ::serde::ser::{self, Error as _, Serialize};
+
+use super::Message;
+use crate::safe_wrappers::convert::scratch_vm;
+use crate::{SlotFrame, WrenType as Type, WrenVM as VM};
+
+type Result<T = ()> = std::result::Result<T, Message>;
+
+/// Serializes a value into `slot`. Nested values are written into scratch slots, each
+/// with its own serializer.
+///
+/// The VM is shared, as the scratch slots of the enclosing values are reserved with frames.
+/// [`to_slot()`][super::to_slot()] still borrows it mutably.
+pub(super) struct Serializer<'a> {
+    vm: &'a VM<'a>,
+    slot: usize,
+    /// The number of levels of nested lists and maps that can still be written.
+    depth: usize,
+}
+
+impl<'a> Serializer<'a> {
+    #[inline]
+    pub(super) fn new(vm: &'a VM, slot: usize, depth: usize) -> Self {
+        Self { vm, slot, depth }
+    }
+
+    /// Fails if a list or map here would be nested too deep.
+    #[inline]
+    fn check_depth(&self) -> Result {
+        if self.depth == 0 {
+            Err(Message::custom(format!(
+                "lists and maps are nested deeper than {} levels",
+                super::MAX_DEPTH
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn list(self) -> Result<ListSerializer<'a>> {
+        self.check_depth()?;
+        // SAFETY: Only the returned serializer writes to the slots of the frame.
+        let mut frame = unsafe { SlotFrame::scratch(self.vm) };
+        let element_slot = frame.slot();
+        frame.set_slot_new_list(self.slot);
+        Ok(ListSerializer {
+            frame,
+            slot: self.slot,
+            element_slot,
+            depth: self.depth - 1,
+            len: 0,
+        })
+    }
+
+    fn map(self) -> Result<MapSerializer<'a>> {
+        self.check_depth()?;
+        // SAFETY: Only the returned serializer writes to the slots of the frame.
+        let mut frame = unsafe { SlotFrame::scratch(self.vm) };
+        let key_slot = frame.slot();
+        let value_slot = frame.slot();
+        frame.set_slot_new_map(self.slot);
+        Ok(MapSerializer {
+            frame,
+            slot: self.slot,
+            key_slot,
+            value_slot,
+            depth: self.depth - 1,
+        })
+    }
+
+    /// Creates the map `{variant: value}` in this slot, then returns a serializer for the
+    /// value and the entry to add once it is written.
+    fn variant(self, variant: &'static str) -> Result<(Serializer<'a>, Entry<'a>)> {
+        self.check_depth()?;
+        // SAFETY: Only the returned serializer writes to the slots of the frame.
+        let mut frame = unsafe { SlotFrame::scratch(self.vm) };
+        let key_slot = frame.slot();
+        let value_slot = frame.slot();
+        frame.set_slot_new_map(self.slot);
+        frame.set_slot_string(key_slot, variant);
+        let entry = Entry {
+            frame,
+            map_slot: self.slot,
+            key_slot,
+            value_slot,
+        };
+        Ok((Serializer::new(self.vm, value_slot, self.depth - 1), entry))
+    }
+
+    /// The VM, to write the value into `slot`.
+    #[inline]
+    fn write(&self) -> VM<'_> {
+        // SAFETY: Only this serializer writes to `slot`, which `to_slot()` or the frame of
+        // the enclosing value reserved for it.
+        unsafe { scratch_vm(self.vm) }
+    }
+}
+
+impl<'a> ser::Serializer for Serializer<'a> {
+    type Ok = ();
+    type Error = Message;
+
+    type SerializeSeq = ListSerializer<'a>;
+    type SerializeTuple = ListSerializer<'a>;
+    type SerializeTupleStruct = ListSerializer<'a>;
+    type SerializeTupleVariant = VariantSerializer<'a, ListSerializer<'a>>;
+    type SerializeMap = MapSerializer<'a>;
+    type SerializeStruct = MapSerializer<'a>;
+    type SerializeStructVariant = VariantSerializer<'a, MapSerializer<'a>>;
+
+    #[inline]
+    fn serialize_bool(self, v: bool) -> Result {
+        self.write().set_slot_bool(self.slot, v);
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_i8(self, v: i8) -> Result {
+        self.serialize_f64(v.into())
+    }
+
+    #[inline]
+    fn serialize_i16(self, v: i16) -> Result {
+        self.serialize_f64(v.into())
+    }
+
+    #[inline]
+    fn serialize_i32(self, v: i32) -> Result {
+        self.serialize_f64(v.into())
+    }
+
+    #[inline]
+    fn serialize_i64(self, v: i64) -> Result {
+        self.serialize_f64(v as f64)
+    }
+
+    #[inline]
+    fn serialize_u8(self, v: u8) -> Result {
+        self.serialize_f64(v.into())
+    }
+
+    #[inline]
+    fn serialize_u16(self, v: u16) -> Result {
+        self.serialize_f64(v.into())
+    }
+
+    #[inline]
+    fn serialize_u32(self, v: u32) -> Result {
+        self.serialize_f64(v.into())
+    }
+
+    #[inline]
+    fn serialize_u64(self, v: u64) -> Result {
+        self.serialize_f64(v as f64)
+    }
+
+    #[inline]
+    fn serialize_f32(self, v: f32) -> Result {
+        self.serialize_f64(v.into())
+    }
+
+    #[inline]
+    fn serialize_f64(self, v: f64) -> Result {
+        self.write().set_slot_double(self.slot, v);
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_char(self, v: char) -> Result {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    #[inline]
+    fn serialize_str(self, v: &str) -> Result {
+        self.write().set_slot_string(self.slot, v);
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_bytes(self, v: &[u8]) -> Result {
+        self.write().set_slot_bytes(self.slot, v);
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_none(self) -> Result {
+        self.serialize_unit()
+    }
+
+    #[inline]
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result {
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_unit(self) -> Result {
+        self.write().set_slot_null(self.slot);
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_unit_struct(self, _name: &'static str) -> Result {
+        self.serialize_unit()
+    }
+
+    #[inline]
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result {
+        self.serialize_str(variant)
+    }
+
+    #[inline]
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result {
+        let (serializer, entry) = self.variant(variant)?;
+        value.serialize(serializer)?;
+        entry.insert();
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        self.list()
+    }
+
+    #[inline]
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        self.list()
+    }
+
+    #[inline]
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        self.list()
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        let (serializer, entry) = self.variant(variant)?;
+        Ok(VariantSerializer {
+            value: serializer.list()?,
+            entry,
+        })
+    }
+
+    #[inline]
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        self.map()
+    }
+
+    #[inline]
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        self.map()
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        let (serializer, entry) = self.variant(variant)?;
+        Ok(VariantSerializer {
+            value: serializer.map()?,
+            entry,
+        })
+    }
+}
+
+/// Appends elements to the list in `slot`.
+pub(super) struct ListSerializer<'a> {
+    frame: SlotFrame<'a>,
+    slot: usize,
+    element_slot: usize,
+    depth: usize,
+    /// The number of elements in the list.
+    len: usize,
+}
+
+impl ListSerializer<'_> {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result {
+        value.serialize(Serializer::new(&self.frame, self.element_slot, self.depth))?;
+        self.frame
+            .insert_in_list(self.slot, self.len, self.element_slot);
+        self.len += 1;
+        Ok(())
+    }
+}
+
+macro_rules! impl_list_serializer {
+    ( $( $trait:ident :: $method:ident ),* ) => {
+        $(
+            impl ser::$trait for ListSerializer<'_> {
+                type Ok = ();
+                type Error = Message;
+
+                #[inline]
+                fn $method<T: Serialize + ?Sized>(&mut self, value: &T) -> Result {
+                    self.push(value)
+                }
+
+                #[inline]
+                fn end(self) -> Result {
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_list_serializer!(
+    SerializeSeq::serialize_element,
+    SerializeTuple::serialize_element,
+    SerializeTupleStruct::serialize_field
+);
+
+/// Adds entries to the map in `slot`.
+pub(super) struct MapSerializer<'a> {
+    frame: SlotFrame<'a>,
+    slot: usize,
+    key_slot: usize,
+    value_slot: usize,
+    depth: usize,
+}
+
+impl MapSerializer<'_> {
+    /// Adds the entry in `key_slot` and `value_slot`.
+    #[inline]
+    fn insert(&mut self) {
+        // SAFETY: This is a map, and the key was checked to be hashable.
+        unsafe {
+            self.frame
+                .set_map_value_unchecked(self.slot, self.key_slot, self.value_slot)
+        };
+    }
+}
+
+impl ser::SerializeMap for MapSerializer<'_> {
+    type Ok = ();
+    type Error = Message;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result {
+        key.serialize(Serializer::new(&self.frame, self.key_slot, self.depth))?;
+        // Wren can also hash ranges and classes, but we cannot tell them apart from
+        // other objects via the C API.
+        match self.frame.get_slot_type(self.key_slot) {
+            Type::Null | Type::Bool | Type::Num | Type::String => Ok(()),
+            key_type => Err(Message::custom(format!(
+                "{:?} cannot be used as a map key",
+                key_type
+            ))),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result {
+        value.serialize(Serializer::new(&self.frame, self.value_slot, self.depth))?;
+        self.insert();
+        Ok(())
+    }
+
+    #[inline]
+    fn end(self) -> Result {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer<'_> {
+    type Ok = ();
+    type Error = Message;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result {
+        self.frame.set_slot_string(self.key_slot, key);
+        ser::SerializeMap::serialize_value(self, value)
+    }
+
+    #[inline]
+    fn end(self) -> Result {
+        Ok(())
+    }
+}
+
+/// The entry `{variant: value}` of an enum variant, added once the value is written.
+pub(super) struct Entry<'a> {
+    frame: SlotFrame<'a>,
+    map_slot: usize,
+    key_slot: usize,
+    value_slot: usize,
+}
+
+impl Entry<'_> {
+    #[inline]
+    fn insert(mut self) {
+        // SAFETY: This is a map, and the key is a string.
+        unsafe {
+            self.frame
+                .set_map_value_unchecked(self.map_slot, self.key_slot, self.value_slot)
+        };
+    }
+}
+
+/// Writes the contents of a tuple or struct variant with `S`, then adds the entry.
+pub(super) struct VariantSerializer<'a, S> {
+    // Declared first, so its frame is dropped before the frame of the entry.
+    value: S,
+    entry: Entry<'a>,
+}
+
+impl<S> VariantSerializer<'_, S> {
+    /// Finishes the value, then adds the entry.
+    #[inline]
+    fn finish(self) -> Result {
+        let VariantSerializer { value, entry } = self;
+        drop(value);
+        entry.insert();
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<'_, ListSerializer<'_>> {
+    type Ok = ();
+    type Error = Message;
+
+    #[inline]
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result {
+        self.value.push(value)
+    }
+
+    #[inline]
+    fn end(self) -> Result {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<'_, MapSerializer<'_>> {
+    type Ok = ();
+    type Error = Message;
+
+    #[inline]
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result {
+        ser::SerializeStruct::serialize_field(&mut self.value, key, value)
+    }
+
+    #[inline]
+    fn end(self) -> Result {
+        self.finish()
+    }
+}
@@ -0,0 +1,233 @@
+use libc::{c_float, c_void};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+use super::{state, State};
+use crate::unsafe_wrappers::audio::{self as unsafe_audio, ChannelId, ChannelRef, ChannelState};
+use crate::unsafe_wrappers::dome as unsafe_dome;
+
+#[derive(Clone, Copy)]
+struct Channel {
+    mix: unsafe_audio::ChannelMix,
+    update: unsafe_audio::ChannelCallback,
+    finish: unsafe_audio::ChannelCallback,
+    user_data: *mut c_void,
+    state: ChannelState,
+    stop_requested: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct AudioState {
+    channels: RefCell<BTreeMap<ChannelId, Channel>>,
+    next_id: Cell<ChannelId>,
+}
+
+impl AudioState {
+    #[inline]
+    pub(crate) fn channel_count(&self) -> usize {
+        self.channels.borrow().len()
+    }
+
+    #[inline]
+    fn ids(&self) -> Vec<ChannelId> {
+        self.channels.borrow().keys().copied().collect()
+    }
+
+    #[inline]
+    fn channel(&self, id: ChannelId) -> Option<Channel> {
+        self.channels.borrow().get(&id).copied()
+    }
+
+    #[inline]
+    fn with_channel(&self, id: ChannelId, callback: impl FnOnce(&mut Channel)) {
+        if let Some(channel) = self.channels.borrow_mut().get_mut(&id) {
+            callback(channel);
+        }
+    }
+}
+
+#[inline]
+fn channel_ref(state: &State, id: ChannelId) -> ChannelRef {
+    ChannelRef {
+        id,
+        engine: state.as_ptr() as unsafe_audio::Engine,
+    }
+}
+
+/// Runs the `update` callback of every channel that did not stop, then finishes and removes
+/// the stopped ones. A channel that was asked to stop via `stop()` becomes stopped here.
+pub(crate) fn update(state: &State) {
+    let audio = &state.audio;
+    for id in audio.ids() {
+        if let Some(channel) = audio.channel(id) {
+            if !matches!(channel.state, ChannelState::Stopped) {
+                state.call(|vm| (channel.update)(channel_ref(state, id), vm));
+            }
+        }
+        audio.with_channel(id, |channel| {
+            if channel.stop_requested {
+                channel.state = ChannelState::Stopped;
+            }
+        });
+        if let Some(channel) = audio.channel(id) {
+            if let ChannelState::Stopped = channel.state {
+                state.call(|vm| (channel.finish)(channel_ref(state, id), vm));
+                audio.channels.borrow_mut().remove(&id);
+            }
+        }
+    }
+}
+
+/// Pulls `frames` frames from every playing channel and mixes them together.
+///
+/// Like DOME, only channels in the [`ChannelState::Playing`] state are mixed.
+/// Unlike DOME, the result is not clamped to `[-1, 1]`.
+pub(crate) fn render(state: &State, frames: usize) -> Vec<[f32; 2]> {
+    let audio = &state.audio;
+    let mut output = vec![[0.0; 2]; frames];
+    let mut buffer = vec![[0.0; 2]; frames];
+    for id in audio.ids() {
+        let channel = match audio.channel(id) {
+            Some(channel) if matches!(channel.state, ChannelState::Playing) => channel,
+            _ => continue,
+        };
+        // DOME hands the channel a zeroed buffer.
+        buffer.iter_mut().for_each(|frame| *frame = [0.0; 2]);
+        (channel.mix)(
+            channel_ref(state, id),
+            buffer.as_mut_ptr() as *mut c_float,
+            frames,
+        );
+        for (output, frame) in output.iter_mut().zip(&buffer) {
+            output[0] += frame[0];
+            output[1] += frame[1];
+        }
+    }
+    output
+}
+
+/// Finishes all channels, no matter what state they are in. DOME does that on shutdown.
+pub(crate) fn finish_all(state: &State) {
+    let audio = &state.audio;
+    for id in audio.ids() {
+        if let Some(channel) = audio.channel(id) {
+            state.call(|vm| (channel.finish)(channel_ref(state, id), vm));
+            audio.channels.borrow_mut().remove(&id);
+        }
+    }
+}
+
+/// The sample rate DOME plays audio at, in frames per second.
+pub const SAMPLE_RATE: u32 = 44_100;
+
+/// Writes `frames` (as returned by [`Host::render_audio()`][super::Host::render_audio()])
+/// as a 16-bit stereo WAV file at [`SAMPLE_RATE`], clamping the samples to `[-1, 1]`.
+///
+/// # Example
+///
+/// ```
+/// let mut wav = Vec::new();
+/// dome_cloomnik::testing::write_wav(&mut wav, &[[0.0, 1.0], [-2.0, 0.5]]).unwrap();
+/// assert_eq!(&wav[..4], b"RIFF");
+/// assert_eq!(wav.len(), 44 + 2 * 4);
+/// assert_eq!(&wav[44..], &[0, 0, 0xFF, 0x7F, 0x01, 0x80, 0xFF, 0x3F]);
+/// ```
+pub fn write_wav(mut writer: impl Write, frames: &[[f32; 2]]) -> io::Result<()> {
+    const CHANNELS: u16 = 2;
+    const BYTES_PER_SAMPLE: u16 = 2;
+
+    let data_size = u32::try_from(frames.len() * usize::from(CHANNELS * BYTES_PER_SAMPLE))
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Too many frames for WAV."))?;
+    let block_align = CHANNELS * BYTES_PER_SAMPLE;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?; // PCM
+    writer.write_all(&CHANNELS.to_le_bytes())?;
+    writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    writer.write_all(&(SAMPLE_RATE * u32::from(block_align)).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&(BYTES_PER_SAMPLE * 8).to_le_bytes())?;
+
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    for sample in frames.iter().flatten() {
+        let sample = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+        writer.write_all(&sample.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+#[inline]
+fn engine_state<'a>(channel_ref: ChannelRef) -> &'a State {
+    // SAFETY: The mock host hands out pointers to its state as engines.
+    unsafe { state(channel_ref.engine as *mut c_void) }
+}
+
+extern "C" fn channel_create(
+    ctx: unsafe_dome::Context,
+    mix: unsafe_audio::ChannelMix,
+    update: unsafe_audio::ChannelCallback,
+    finish: unsafe_audio::ChannelCallback,
+    user_data: *mut c_void,
+) -> ChannelRef {
+    // SAFETY: The mock host hands out pointers to its state as contexts.
+    let state = unsafe { state(ctx as *mut c_void) };
+    let audio = &state.audio;
+    let id = audio.next_id.get() + 1;
+    audio.next_id.set(id);
+    audio.channels.borrow_mut().insert(
+        id,
+        Channel {
+            mix,
+            update,
+            finish,
+            user_data,
+            state: ChannelState::Initialize,
+            stop_requested: false,
+        },
+    );
+    channel_ref(state, id)
+}
+
+extern "C" fn get_state(channel_ref: ChannelRef) -> ChannelState {
+    let channels = engine_state(channel_ref).audio.channels.borrow();
+    channels
+        .get(&channel_ref.id)
+        .map_or(ChannelState::Stopped, |channel| channel.state)
+}
+
+extern "C" fn set_state(channel_ref: ChannelRef, state: ChannelState) {
+    engine_state(channel_ref)
+        .audio
+        .with_channel(channel_ref.id, |channel| channel.state = state);
+}
+
+// Like DOME, stopping only marks the channel. It becomes stopped (and finished)
+// in the next update.
+extern "C" fn stop(channel_ref: ChannelRef) {
+    engine_state(channel_ref)
+        .audio
+        .with_channel(channel_ref.id, |channel| channel.stop_requested = true);
+}
+
+extern "C" fn get_data(channel_ref: ChannelRef) -> *mut c_void {
+    let channels = engine_state(channel_ref).audio.channels.borrow();
+    channels
+        .get(&channel_ref.id)
+        .map_or(std::ptr::null_mut(), |channel| channel.user_data)
+}
+
+pub(crate) static API: unsafe_audio::ApiV0 = unsafe_audio::ApiV0 {
+    channel_create,
+    get_state,
+    set_state,
+    stop,
+    get_data,
+};
@@ -0,0 +1,255 @@
+use libc::{c_char, c_void};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::mem;
+use std::rc::Rc;
+
+use super::wren::Class;
+use super::{state, State};
+use crate::unsafe_wrappers::dome::{self as unsafe_dome, Result as DomeResult};
+use crate::unsafe_wrappers::wren as unsafe_wren;
+
+/// A call the plugin made to DOME's plugin API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiCall {
+    RegisterModule {
+        name: String,
+        source: String,
+    },
+    RegisterFn {
+        module: String,
+        signature: String,
+    },
+    RegisterClass {
+        module: String,
+        class: String,
+        has_finalizer: bool,
+    },
+    LockModule {
+        name: String,
+    },
+}
+
+pub(crate) struct Module {
+    source: String,
+    locked: bool,
+    classes: BTreeMap<String, Rc<Class>>,
+    methods: BTreeMap<String, unsafe_dome::ForeignFn>,
+}
+
+#[derive(Default)]
+pub(crate) struct DomeState {
+    modules: RefCell<BTreeMap<String, Module>>,
+    calls: RefCell<Vec<ApiCall>>,
+    log: RefCell<String>,
+}
+
+impl DomeState {
+    #[inline]
+    pub(crate) fn calls(&self) -> Vec<ApiCall> {
+        self.calls.borrow().clone()
+    }
+
+    #[inline]
+    pub(crate) fn log(&self) -> String {
+        self.log.borrow().clone()
+    }
+
+    #[inline]
+    pub(crate) fn module_source(&self, name: &str) -> Option<String> {
+        Some(self.modules.borrow().get(name)?.source.clone())
+    }
+
+    #[inline]
+    pub(crate) fn is_module_locked(&self, name: &str) -> bool {
+        self.modules
+            .borrow()
+            .get(name)
+            .is_some_and(|module| module.locked)
+    }
+
+    #[inline]
+    pub(crate) fn class(&self, module: &str, name: &str) -> Option<Rc<Class>> {
+        Some(
+            self.modules
+                .borrow()
+                .get(module)?
+                .classes
+                .get(name)?
+                .clone(),
+        )
+    }
+
+    #[inline]
+    pub(crate) fn method(&self, module: &str, signature: &str) -> Option<unsafe_dome::ForeignFn> {
+        Some(*self.modules.borrow().get(module)?.methods.get(signature)?)
+    }
+
+    pub(crate) fn clear(&self) {
+        let modules = self.modules.take();
+        drop(modules);
+    }
+
+    fn with_unlocked_module(&self, module: &str, callback: impl FnOnce(&mut Module)) -> DomeResult {
+        match self.modules.borrow_mut().get_mut(module) {
+            Some(module) if !module.locked => {
+                callback(module);
+                DomeResult::Success
+            }
+            _ => DomeResult::Failure,
+        }
+    }
+}
+
+#[inline]
+fn ctx_state<'a>(ctx: unsafe_dome::Context) -> &'a State {
+    // SAFETY: The mock host hands out pointers to its state as contexts.
+    unsafe { state(ctx as *mut c_void) }
+}
+
+#[inline]
+unsafe fn string(s: *const c_char) -> String {
+    CStr::from_ptr(s).to_string_lossy().into_owned()
+}
+
+extern "C" fn register_module(
+    ctx: unsafe_dome::Context,
+    name: *const c_char,
+    source: *const c_char,
+) -> DomeResult {
+    // SAFETY: The crate always passes valid C strings.
+    let (name, source) = unsafe { (string(name), string(source)) };
+    let dome = &ctx_state(ctx).dome;
+    dome.calls.borrow_mut().push(ApiCall::RegisterModule {
+        name: name.clone(),
+        source: source.clone(),
+    });
+    let mut modules = dome.modules.borrow_mut();
+    if modules.contains_key(&name) {
+        return DomeResult::Failure;
+    }
+    modules.insert(
+        name,
+        Module {
+            source,
+            locked: false,
+            classes: BTreeMap::new(),
+            methods: BTreeMap::new(),
+        },
+    );
+    DomeResult::Success
+}
+
+extern "C" fn register_fn(
+    ctx: unsafe_dome::Context,
+    name: *const c_char,
+    signature: *const c_char,
+    method: unsafe_dome::ForeignFn,
+) -> DomeResult {
+    // SAFETY: The crate always passes valid C strings.
+    let (module, signature) = unsafe { (string(name), string(signature)) };
+    let dome = &ctx_state(ctx).dome;
+    dome.calls.borrow_mut().push(ApiCall::RegisterFn {
+        module: module.clone(),
+        signature: signature.clone(),
+    });
+    dome.with_unlocked_module(&module, |module| {
+        module.methods.insert(signature, method);
+    })
+}
+
+extern "C" fn register_class(
+    ctx: unsafe_dome::Context,
+    module_name: *const c_char,
+    class_name: *const c_char,
+    allocate: unsafe_dome::ForeignFn,
+    finalize: Option<unsafe_dome::FinalizerFn>,
+) -> DomeResult {
+    // SAFETY: The crate always passes valid C strings.
+    let (module, class) = unsafe { (string(module_name), string(class_name)) };
+    let dome = &ctx_state(ctx).dome;
+    dome.calls.borrow_mut().push(ApiCall::RegisterClass {
+        module: module.clone(),
+        class: class.clone(),
+        has_finalizer: finalize.is_some(),
+    });
+    dome.with_unlocked_module(&module.clone(), |module_entry| {
+        let class_entry = Class::new(module, class.clone(), Some(allocate), finalize);
+        module_entry.classes.insert(class, Rc::new(class_entry));
+    })
+}
+
+extern "C" fn lock_module(ctx: unsafe_dome::Context, name: *const c_char) {
+    // SAFETY: The crate always passes a valid C string.
+    let name = unsafe { string(name) };
+    let dome = &ctx_state(ctx).dome;
+    dome.calls
+        .borrow_mut()
+        .push(ApiCall::LockModule { name: name.clone() });
+    if let Some(module) = dome.modules.borrow_mut().get_mut(&name) {
+        module.locked = true;
+    }
+}
+
+extern "C" fn get_context(vm: unsafe_wren::VM) -> unsafe_dome::Context {
+    vm as unsafe_dome::Context
+}
+
+/// A very small `printf()`, that only understands `%s` and `%%`. This is all this crate uses.
+fn format(fmt: &[u8], args: &[*const c_char]) -> String {
+    let mut result = Vec::new();
+    let mut args = args.iter();
+    let mut bytes = fmt.iter();
+    while let Some(&byte) = bytes.next() {
+        if byte != b'%' {
+            result.push(byte);
+            continue;
+        }
+        match bytes.next() {
+            Some(b's') => {
+                let arg = args.next().expect("Too many format specifiers.");
+                // SAFETY: `%s` arguments are C strings.
+                result.extend_from_slice(unsafe { CStr::from_ptr(*arg) }.to_bytes());
+            }
+            Some(b'%') => result.push(b'%'),
+            specifier => panic!("Unsupported format specifier: {:?}.", specifier),
+        }
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+// Stable Rust cannot define C-variadic functions, so we define one with the maximum number
+// of arguments this crate passes and transmute it. This relies on variadic arguments being
+// passed just like regular ones, which holds for pointer arguments on every major platform
+// except Apple's AArch64.
+type LogFn = unsafe extern "C" fn(
+    ctx: unsafe_dome::Context,
+    text: *const c_char,
+    *const c_char,
+    *const c_char,
+);
+
+unsafe extern "C" fn log(
+    ctx: unsafe_dome::Context,
+    text: *const c_char,
+    arg0: *const c_char,
+    arg1: *const c_char,
+) {
+    // Arguments that weren't passed are never read, since `format()` only reads as many
+    // arguments as there are format specifiers.
+    let text = format(CStr::from_ptr(text).to_bytes(), &[arg0, arg1]);
+    ctx_state(ctx).dome.log.borrow_mut().push_str(&text);
+}
+
+pub(crate) static API: unsafe_dome::ApiV0 = unsafe_dome::ApiV0 {
+    register_module,
+    register_fn,
+    register_class,
+    lock_module,
+    get_context,
+    // SAFETY: See `LogFn`.
+    log: unsafe {
+        mem::transmute::<LogFn, unsafe extern "C" fn(unsafe_dome::Context, *const c_char, ...)>(log)
+    },
+};
@@ -0,0 +1,217 @@
+//! Bindings to the vendored Wren VM, for [`WrenHost`][super::WrenHost].
+//!
+//! The slot functions have the same signatures as the Wren API table DOME gives plugins,
+//! so most of them fill [`API`] as they are.
+
+use libc::{c_char, c_double, c_int, c_void, size_t};
+use std::ffi::CStr;
+
+use crate::unsafe_wrappers::dome as unsafe_dome;
+use crate::unsafe_wrappers::wren::{self as unsafe_wren, Handle, Type, VM};
+
+pub(super) type LoadModuleCompleteFn =
+    extern "C" fn(vm: VM, name: *const c_char, result: LoadModuleResult);
+
+#[repr(C)]
+pub(super) struct LoadModuleResult {
+    pub(super) source: *const c_char,
+    pub(super) on_complete: Option<LoadModuleCompleteFn>,
+    pub(super) user_data: *mut c_void,
+}
+
+#[repr(C)]
+pub(super) struct ForeignClassMethods {
+    pub(super) allocate: Option<unsafe_dome::ForeignFn>,
+    pub(super) finalize: Option<unsafe_dome::FinalizerFn>,
+}
+
+// Only Wren creates the values.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub(super) enum ErrorType {
+    Compile,
+    Runtime,
+    StackTrace,
+}
+
+// Only Wren creates the values.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub(super) enum InterpretResult {
+    Success,
+    CompileError,
+    RuntimeError,
+}
+
+#[repr(C)]
+pub(super) struct Configuration {
+    pub(super) reallocate_fn:
+        Option<extern "C" fn(*mut c_void, size_t, *mut c_void) -> *mut c_void>,
+    pub(super) resolve_module_fn: Option<
+        extern "C" fn(vm: VM, importer: *const c_char, name: *const c_char) -> *const c_char,
+    >,
+    pub(super) load_module_fn:
+        Option<extern "C" fn(vm: VM, name: *const c_char) -> LoadModuleResult>,
+    pub(super) bind_foreign_method_fn: Option<
+        extern "C" fn(
+            vm: VM,
+            module: *const c_char,
+            class_name: *const c_char,
+            is_static: bool,
+            signature: *const c_char,
+        ) -> Option<unsafe_dome::ForeignFn>,
+    >,
+    pub(super) bind_foreign_class_fn: Option<
+        extern "C" fn(
+            vm: VM,
+            module: *const c_char,
+            class_name: *const c_char,
+        ) -> ForeignClassMethods,
+    >,
+    pub(super) write_fn: Option<extern "C" fn(vm: VM, text: *const c_char)>,
+    pub(super) error_fn: Option<
+        extern "C" fn(
+            vm: VM,
+            error_type: ErrorType,
+            module: *const c_char,
+            line: c_int,
+            message: *const c_char,
+        ),
+    >,
+    pub(super) initial_heap_size: size_t,
+    pub(super) min_heap_size: size_t,
+    pub(super) heap_growth_percent: c_int,
+    pub(super) user_data: *mut c_void,
+}
+
+extern "C" {
+    pub(super) fn wrenInitConfiguration(configuration: *mut Configuration);
+    pub(super) fn wrenNewVM(configuration: *mut Configuration) -> VM;
+    pub(super) fn wrenFreeVM(vm: VM);
+    pub(super) fn wrenCollectGarbage(vm: VM);
+    pub(super) fn wrenInterpret(
+        vm: VM,
+        module: *const c_char,
+        source: *const c_char,
+    ) -> InterpretResult;
+    pub(super) fn wrenHasVariable(vm: VM, module: *const c_char, name: *const c_char) -> bool;
+    pub(super) fn wrenHasModule(vm: VM, module: *const c_char) -> bool;
+    pub(super) fn wrenGetUserData(vm: VM) -> *mut c_void;
+
+    fn wrenEnsureSlots(vm: VM, slot_count: c_int);
+
+    fn wrenSetSlotNull(vm: VM, slot: c_int);
+    fn wrenSetSlotBool(vm: VM, slot: c_int, value: bool);
+    fn wrenSetSlotDouble(vm: VM, slot: c_int, value: c_double);
+    fn wrenSetSlotString(vm: VM, slot: c_int, text: *const c_char);
+    fn wrenSetSlotBytes(vm: VM, slot: c_int, data: *const c_char, length: size_t);
+    fn wrenSetSlotNewForeign(vm: VM, slot: c_int, class_slot: c_int, length: size_t)
+        -> *mut c_void;
+    fn wrenSetSlotNewList(vm: VM, slot: c_int);
+    fn wrenSetSlotNewMap(vm: VM, slot: c_int);
+
+    fn wrenGetSlotBool(vm: VM, slot: c_int) -> bool;
+    fn wrenGetSlotDouble(vm: VM, slot: c_int) -> c_double;
+    fn wrenGetSlotString(vm: VM, slot: c_int) -> *const c_char;
+    fn wrenGetSlotBytes(vm: VM, slot: c_int, length: *mut c_int) -> *const c_char;
+    fn wrenGetSlotForeign(vm: VM, slot: c_int) -> *mut c_void;
+
+    fn wrenAbortFiber(vm: VM, slot: c_int);
+    fn wrenGetSlotCount(vm: VM) -> c_int;
+    fn wrenGetSlotType(vm: VM, slot: c_int) -> Type;
+
+    fn wrenGetListCount(vm: VM, slot: c_int) -> c_int;
+    fn wrenGetListElement(vm: VM, list_slot: c_int, index: c_int, element_slot: c_int);
+    fn wrenSetListElement(vm: VM, list_slot: c_int, index: c_int, element_slot: c_int);
+    fn wrenInsertInList(vm: VM, list_slot: c_int, index: c_int, element_slot: c_int);
+
+    fn wrenGetMapCount(vm: VM, slot: c_int) -> c_int;
+    fn wrenGetMapContainsKey(vm: VM, map_slot: c_int, key_slot: c_int) -> bool;
+    fn wrenGetMapValue(vm: VM, map_slot: c_int, key_slot: c_int, value_slot: c_int);
+    fn wrenSetMapValue(vm: VM, map_slot: c_int, key_slot: c_int, value_slot: c_int);
+    fn wrenRemoveMapValue(vm: VM, map_slot: c_int, key_slot: c_int, removed_value_slot: c_int);
+
+    fn wrenGetVariable(vm: VM, module: *const c_char, name: *const c_char, slot: c_int);
+    fn wrenGetSlotHandle(vm: VM, slot: c_int) -> Handle;
+    fn wrenSetSlotHandle(vm: VM, slot: c_int, handle: Handle);
+    fn wrenReleaseHandle(vm: VM, handle: Handle);
+}
+
+// The API table has safe function pointers for the functions that cannot be misused.
+
+extern "C" fn ensure_slots(vm: VM, slot_count: c_int) {
+    // SAFETY: The crate only passes VMs it got from Wren.
+    unsafe { wrenEnsureSlots(vm, slot_count) }
+}
+
+extern "C" fn get_user_data(vm: VM) -> unsafe_dome::Context {
+    // SAFETY: See `ensure_slots()`.
+    unsafe { wrenGetUserData(vm) as unsafe_dome::Context }
+}
+
+extern "C" fn get_slot_count(vm: VM) -> c_int {
+    // SAFETY: See `ensure_slots()`.
+    unsafe { wrenGetSlotCount(vm) }
+}
+
+extern "C" fn get_variable(vm: VM, module: *const c_char, name: *const c_char, slot: c_int) {
+    // SAFETY: See `ensure_slots()`. The crate always passes valid C strings.
+    unsafe {
+        // Wren would only fail an assertion, without saying which variable is missing.
+        if !wrenHasModule(vm, module) || !wrenHasVariable(vm, module, name) {
+            panic!(
+                "Variable '{}' does not exist in module '{}'.",
+                CStr::from_ptr(name).to_string_lossy(),
+                CStr::from_ptr(module).to_string_lossy(),
+            );
+        }
+        wrenGetVariable(vm, module, name, slot)
+    }
+}
+
+extern "C" fn release_handle(vm: VM, handle: Handle) {
+    // SAFETY: See `ensure_slots()`. The crate releases each handle once.
+    unsafe { wrenReleaseHandle(vm, handle) }
+}
+
+pub(super) static API: unsafe_wren::ApiV0 = unsafe_wren::ApiV0 {
+    ensure_slots,
+
+    set_slot_null: wrenSetSlotNull,
+    set_slot_bool: wrenSetSlotBool,
+    set_slot_double: wrenSetSlotDouble,
+    set_slot_string: wrenSetSlotString,
+    set_slot_bytes: wrenSetSlotBytes,
+    set_slot_new_foreign: wrenSetSlotNewForeign,
+    set_slot_new_list: wrenSetSlotNewList,
+    set_slot_new_map: wrenSetSlotNewMap,
+
+    get_user_data,
+    get_slot_bool: wrenGetSlotBool,
+    get_slot_double: wrenGetSlotDouble,
+    get_slot_string: wrenGetSlotString,
+    get_slot_bytes: wrenGetSlotBytes,
+    get_slot_foreign: wrenGetSlotForeign,
+
+    abort_fiber: wrenAbortFiber,
+    get_slot_count,
+    get_slot_type: wrenGetSlotType,
+
+    get_list_count: wrenGetListCount,
+    get_list_element: wrenGetListElement,
+    set_list_element: wrenSetListElement,
+    insert_in_list: wrenInsertInList,
+
+    get_map_count: wrenGetMapCount,
+    get_map_contains_key: wrenGetMapContainsKey,
+    get_map_value: wrenGetMapValue,
+    set_map_value: wrenSetMapValue,
+    remove_map_value: wrenRemoveMapValue,
+
+    get_variable,
+    get_slot_handle: wrenGetSlotHandle,
+    set_slot_handle: wrenSetSlotHandle,
+    release_handle,
+};
@@ -0,0 +1,523 @@
+//! An in-process mock of DOME, for unit-testing plugins without DOME.
+//!
+//! [`MockHost`] implements DOME's plugin API in Rust. It records the modules, classes and
+//! methods the plugin registers and what it logs, and simulates Wren's slots, lists, maps,
+//! foreign objects and handles. It cannot run Wren code, but it can call foreign methods
+//! and allocators directly, which is enough to test the Rust side of a plugin.
+//!
+//! With the `wren-host` feature, [`WrenHost`] runs Wren code on a real Wren VM instead,
+//! including the modules the plugin registers.
+//!
+//! It also stands in for DOME's audio engine: [`Host::render_audio()`] pulls frames
+//! from the plugin's channels, and [`write_wav()`] saves them for listening or comparison.
+//!
+//! # Example
+//!
+//! ```
+//! use dome_cloomnik::testing::{MockHost, Value};
+//! use dome_cloomnik::{register_modules, Context, HookResult, Hooks, WrenVM};
+//!
+//! struct Counter(f64);
+//! impl Counter {
+//!     fn new(vm: &WrenVM) -> Self {
+//!         Counter(vm.get_slot_double(1))
+//!     }
+//!     fn increment(&mut self, vm: &mut WrenVM) {
+//!         self.0 += 1.0;
+//!         vm.set_slot_double(0, self.0);
+//!     }
+//! }
+//!
+//! fn on_init(mut ctx: Context) -> HookResult {
+//!     (register_modules! {
+//!         ctx,
+//!         module "counter" {
+//!             foreign class Counter = new of Counter {
+//!                 "construct new(start) {}"
+//!                 foreign increment() = increment
+//!             }
+//!         }
+//!     })?;
+//!     Ok(())
+//! }
+//!
+//! let host = MockHost::new();
+//! assert!(host.init(Hooks {
+//!     on_init: Some(on_init),
+//!     ..Hooks::default()
+//! }));
+//! assert!(host.is_module_locked("counter"));
+//!
+//! let counter = host.construct("counter", "Counter", &[Value::Num(41.0)]).unwrap();
+//! let result = host.call("counter", "Counter.increment()", &[counter]);
+//! assert_eq!(result, Ok(Value::Num(42.0)));
+//! ```
+
+mod audio;
+mod dome;
+#[cfg(feature = "wren-host")]
+mod ffi;
+mod wren;
+#[cfg(feature = "wren-host")]
+mod wren_host;
+
+use libc::{c_int, c_void};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::safe_wrappers::frame::foreign_call;
+use crate::safe_wrappers::registry;
+use crate::unsafe_wrappers::audio as unsafe_audio;
+use crate::unsafe_wrappers::dome::{self as unsafe_dome, Result as DomeResult};
+use crate::unsafe_wrappers::wren as unsafe_wren;
+use crate::{ApiType, Context, Hooks, WrenVM};
+pub use audio::{write_wav, SAMPLE_RATE};
+pub use dome::ApiCall;
+pub use wren::{Class, ForeignObject, Value};
+#[cfg(feature = "wren-host")]
+pub use wren_host::WrenHost;
+
+#[derive(Default)]
+pub(crate) struct State {
+    dome: dome::DomeState,
+    vm: wren::VmState,
+    audio: audio::AudioState,
+    #[cfg(feature = "wren-host")]
+    host: wren_host::HostState,
+}
+
+impl State {
+    /// The pointer we hand out as DOME context, Wren VM and audio engine.
+    #[inline]
+    fn as_ptr(&self) -> *mut c_void {
+        self as *const State as *mut c_void
+    }
+
+    /// Runs `callback` in a fresh slot frame with `slots`, like Wren does for foreign calls.
+    ///
+    /// Returns the slots at the end of the call, or the fiber error if it was aborted.
+    fn call_with_slots(
+        &self,
+        slots: Vec<Value>,
+        callback: impl FnOnce(unsafe_wren::VM),
+    ) -> Result<Vec<Value>, Value> {
+        let vm = self.as_ptr() as unsafe_wren::VM;
+        let ((), slots, error) = self.vm.with_frame(slots, || callback(vm));
+        match error {
+            Some(error) => Err(error),
+            None => Ok(slots),
+        }
+    }
+
+    /// Runs `callback` with the VM, outside of any foreign call. This is the real Wren VM
+    /// for a [`WrenHost`], and a fresh slot frame otherwise.
+    #[inline]
+    fn call(&self, callback: impl FnOnce(unsafe_wren::VM)) {
+        #[cfg(feature = "wren-host")]
+        let vm = self.host.vm();
+        #[cfg(not(feature = "wren-host"))]
+        let vm = None;
+        match vm {
+            Some(vm) => callback(vm),
+            None => {
+                let _ = self.call_with_slots(Vec::new(), callback);
+            }
+        }
+    }
+}
+
+/// # Safety
+///
+/// `ptr` must be a pointer handed out by a live [`MockHost`].
+#[inline]
+pub(crate) unsafe fn state<'a>(ptr: *mut c_void) -> &'a State {
+    &*(ptr as *const State)
+}
+
+/// Hands out the API tables the current host installed with [`install_api()`].
+extern "C" fn get_api(api: ApiType, version: c_int) -> *mut c_void {
+    // SAFETY: Only the host holding the host lock installs the API, before it hands out
+    // this function.
+    unsafe {
+        match api {
+            ApiType::Dome if version == unsafe_dome::API_VERSION => crate::API.dome as *mut c_void,
+            ApiType::Wren if version == unsafe_wren::API_VERSION => crate::API.wren as *mut c_void,
+            ApiType::Audio if version == unsafe_audio::API_VERSION => {
+                crate::API.audio as *mut c_void
+            }
+            _ => std::ptr::null_mut(),
+        }
+    }
+}
+
+/// Installs the API tables of a host, and forgets the hooks of the previous host.
+///
+/// # Safety
+///
+/// The caller must hold [`HOST_LOCK`].
+unsafe fn install_api(dome: &'static unsafe_dome::ApiV0, wren: &'static unsafe_wren::ApiV0) {
+    crate::API.dome = dome as *const unsafe_dome::ApiV0 as *mut unsafe_dome::ApiV0;
+    crate::API.wren = wren as *const unsafe_wren::ApiV0 as *mut unsafe_wren::ApiV0;
+    crate::API.audio = &audio::API as *const unsafe_audio::ApiV0 as *mut unsafe_audio::ApiV0;
+    crate::HOOKS = Hooks::default();
+}
+
+// The plugin API, the hooks and the panic hook are all process-wide, so only one host
+// may be active at a time.
+pub(super) static HOST_LOCK: Mutex<()> = Mutex::new(());
+
+/// What all hosts have in common: the plugin API, the hooks, the registered modules and
+/// the audio engine. [`MockHost`] and [`WrenHost`] dereference to it.
+///
+/// Only one host can exist at a time: creating a host blocks until all other hosts
+/// (possibly on other threads, e.g. other tests) are dropped. This means that you cannot
+/// create a second host on a thread that already has one.
+///
+/// When the host is dropped, all audio channels are finished and all Wren values it holds
+/// are released, running their finalizers. [`WrenHandle`][crate::WrenHandle]s must be
+/// dropped before the host.
+pub struct Host {
+    state: Box<State>,
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl Host {
+    /// Waits until no other host exists, then installs the API tables of the new host.
+    fn new(dome: &'static unsafe_dome::ApiV0, wren: &'static unsafe_wren::ApiV0) -> Self {
+        let guard = HOST_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        // SAFETY: We hold the host lock, so nobody else uses the API or the hooks.
+        // We install the API right away, so that `Host::context()` is usable
+        // even without initializing the plugin.
+        unsafe { install_api(dome, wren) };
+        Self {
+            state: Box::default(),
+            _guard: guard,
+        }
+    }
+
+    #[inline]
+    fn ctx(&self) -> unsafe_dome::Context {
+        self.state.as_ptr() as unsafe_dome::Context
+    }
+
+    /// Loads a plugin by calling its `PLUGIN_onInit()` function, just like DOME does.
+    /// Returns whether it succeeded.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// #[dome_cloomnik::plugin]
+    /// mod plugin {
+    ///     use dome_cloomnik::{Context, HookResult};
+    ///
+    ///     fn on_init(mut ctx: Context) -> HookResult {
+    ///         ctx.log("Loaded.");
+    ///         Ok(())
+    ///     }
+    ///
+    ///     fn pre_update(_ctx: Context) -> HookResult {
+    ///         Err(anyhow::anyhow!("Not ready."))
+    ///     }
+    /// }
+    ///
+    /// let host = dome_cloomnik::testing::MockHost::new();
+    /// assert!(host.load(plugin::PLUGIN_onInit));
+    /// assert_eq!(host.log(), "Loaded.");
+    /// assert!(host.post_update());
+    /// assert!(!host.pre_update());
+    /// ```
+    pub fn load(&self, on_init: extern "C" fn(*mut c_void, *mut c_void) -> c_int) -> bool {
+        let get_api: crate::GetApiFunction = get_api;
+        on_init(get_api as *mut c_void, self.ctx() as *mut c_void) == DomeResult::Success as c_int
+    }
+
+    /// Initializes the plugin with `hooks`, as if `PLUGIN_onInit()` called
+    /// [`init_plugin()`][crate::init_plugin()] with them. Returns whether it succeeded.
+    pub fn init(&self, hooks: Hooks) -> bool {
+        let get_api: crate::GetApiFunction = get_api;
+        // SAFETY: We pass a valid API getter and context.
+        let result =
+            unsafe { crate::init_plugin(get_api as *mut c_void, self.ctx() as *mut c_void, hooks) };
+        result == DomeResult::Success as c_int
+    }
+
+    /// Invokes the `pre_update` hook. Returns whether it succeeded.
+    #[inline]
+    pub fn pre_update(&self) -> bool {
+        matches!(crate::PLUGIN_preUpdate(self.ctx()), DomeResult::Success)
+    }
+
+    /// Invokes the `post_update` hook. Returns whether it succeeded.
+    #[inline]
+    pub fn post_update(&self) -> bool {
+        matches!(crate::PLUGIN_postUpdate(self.ctx()), DomeResult::Success)
+    }
+
+    /// Invokes the `pre_draw` hook. Returns whether it succeeded.
+    #[inline]
+    pub fn pre_draw(&self) -> bool {
+        matches!(crate::PLUGIN_preDraw(self.ctx()), DomeResult::Success)
+    }
+
+    /// Invokes the `post_draw` hook. Returns whether it succeeded.
+    #[inline]
+    pub fn post_draw(&self) -> bool {
+        matches!(crate::PLUGIN_postDraw(self.ctx()), DomeResult::Success)
+    }
+
+    /// Invokes the `on_shutdown` hook. Returns whether it succeeded.
+    #[inline]
+    pub fn shutdown(&self) -> bool {
+        matches!(crate::PLUGIN_onShutdown(self.ctx()), DomeResult::Success)
+    }
+
+    /// Returns a [`Context`] for this host, as if it was passed to a hook.
+    #[inline]
+    pub fn context(&self) -> Context<'_> {
+        Context(self.ctx(), PhantomData)
+    }
+
+    /// All calls the plugin made to register and lock modules, in order.
+    #[inline]
+    pub fn calls(&self) -> Vec<ApiCall> {
+        self.state.dome.calls()
+    }
+
+    /// Everything the plugin logged so far, concatenated.
+    #[inline]
+    pub fn log(&self) -> String {
+        self.state.dome.log()
+    }
+
+    /// The source of the module `name`, if it was registered.
+    #[inline]
+    pub fn module_source(&self, name: &str) -> Option<String> {
+        self.state.dome.module_source(name)
+    }
+
+    /// Whether the module `name` was registered and locked.
+    #[inline]
+    pub fn is_module_locked(&self, name: &str) -> bool {
+        self.state.dome.is_module_locked(name)
+    }
+
+    /// Runs the `update` callback of all audio channels, and finishes the stopped ones.
+    /// DOME does that once a frame.
+    #[inline]
+    pub fn update_audio(&self) {
+        audio::update(&self.state)
+    }
+
+    /// Pulls `frames` stereo frames from every playing audio channel and mixes them together,
+    /// like DOME's audio thread does. The result is not clamped.
+    ///
+    /// Together with [`write_wav()`], this allows golden-testing synthesizers.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use dome_cloomnik::testing::MockHost;
+    /// use dome_cloomnik::{CallbackChannel, ChannelState, WrenVM};
+    ///
+    /// fn mix(channel: &CallbackChannel<f32>, buffer: &mut [[f32; 2]]) {
+    ///     let volume = *channel.data();
+    ///     buffer.iter_mut().for_each(|frame| *frame = [volume, -volume]);
+    /// }
+    /// fn update(_channel: &CallbackChannel<f32>, _vm: &WrenVM) {}
+    ///
+    /// let host = MockHost::new();
+    /// let mut channel = host.context().create_channel(mix, update, 0.5);
+    /// // Channels only play once they are in the playing state.
+    /// assert_eq!(host.render_audio(2), [[0.0, 0.0]; 2]);
+    /// channel.set_state(ChannelState::Playing);
+    /// assert_eq!(host.render_audio(2), [[0.5, -0.5]; 2]);
+    ///
+    /// // Stopped channels are finished in the next update.
+    /// channel.stop();
+    /// assert_eq!(host.channel_count(), 1);
+    /// host.update_audio();
+    /// assert_eq!(host.channel_count(), 0);
+    /// ```
+    #[inline]
+    pub fn render_audio(&self, frames: usize) -> Vec<[f32; 2]> {
+        audio::render(&self.state, frames)
+    }
+
+    /// The number of audio channels that weren't finished yet.
+    #[inline]
+    pub fn channel_count(&self) -> usize {
+        self.state.audio.channel_count()
+    }
+
+    /// Finishes all audio channels, and releases the classes and handles the plugin holds.
+    /// Must be called before the VM is freed.
+    fn release(&self) {
+        crate::safe_wrappers::registry::clear();
+        audio::finish_all(&self.state);
+        crate::safe_wrappers::handles::release_all();
+    }
+}
+
+/// A fake DOME host. See the [module documentation][self] for more.
+///
+/// The hooks, the registered modules and the audio engine are accessible through [`Host`],
+/// which the host dereferences to.
+pub struct MockHost {
+    host: Host,
+}
+
+impl MockHost {
+    /// Creates a new host. The plugin is not loaded until [`Host::load()`] or
+    /// [`Host::init()`] is called.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            host: Host::new(&dome::API, &wren::API),
+        }
+    }
+
+    /// Runs `callback` with a [`WrenVM`] whose slots are `slots`, as if it was a foreign call.
+    ///
+    /// Returns whatever `callback` returns, or the fiber error if it aborted the fiber.
+    pub fn with_vm<R>(
+        &self,
+        slots: &[Value],
+        callback: impl FnOnce(&mut WrenVM) -> R,
+    ) -> Result<R, Value> {
+        let mut result = None;
+        self.host
+            .state
+            .call_with_slots(slots.to_vec(), |vm| {
+                // SAFETY: The mock VM is valid during the call.
+                let mut vm = unsafe { WrenVM::from_raw(vm) };
+                result = Some(foreign_call(|| callback(&mut vm)));
+            })
+            .map(|_| result.unwrap())
+    }
+
+    /// The foreign class `name` in `module`, if it was registered.
+    #[inline]
+    pub fn class(&self, module: &str, name: &str) -> Option<Value> {
+        self.host.state.dome.class(module, name).map(Value::Class)
+    }
+
+    /// Makes the variable `name` in `module` visible to `WrenVM::get_variable()`.
+    ///
+    /// Since the mock host cannot run Wren code, only registered foreign classes
+    /// are visible otherwise.
+    #[inline]
+    pub fn set_variable(&self, module: &str, name: &str, value: Value) {
+        self.host.state.vm.set_variable(module, name, value)
+    }
+
+    /// Imports the module `name`, as if Wren code imported it.
+    ///
+    /// Since the mock host cannot run Wren code, this only records the import, which lets
+    /// [`WrenVM::new_foreign()`] and [`ClassHandle::of()`][crate::ClassHandle::of()] create
+    /// instances of the classes registered with [`register_modules!`][crate::register_modules!]
+    /// and [`register_classes!`][crate::register_classes!]. [`construct()`][Self::construct()]
+    /// and [`call()`][Self::call()] import their module first, like Wren must have.
+    #[inline]
+    pub fn import(&self, name: &str) {
+        registry::mark_imported(name)
+    }
+
+    /// Creates an instance of the foreign class `class` in `module`, by calling its allocator
+    /// with `args` at slots 1 and up.
+    ///
+    /// Returns the new object, or the fiber error if the allocator aborted the fiber.
+    ///
+    /// Note that the Wren constructor itself does not run, since the mock host cannot
+    /// run Wren code.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the class does not exist.
+    pub fn construct(&self, module: &str, class: &str, args: &[Value]) -> Result<Value, Value> {
+        let class = self
+            .host
+            .state
+            .dome
+            .class(module, class)
+            .unwrap_or_else(|| panic!("Class '{}' does not exist in module '{}'.", class, module));
+        let allocate = class.allocate().expect("Class has no allocator.");
+        self.import(module);
+        let mut slots = vec![Value::Class(class)];
+        slots.extend_from_slice(args);
+        let slots = self.host.state.call_with_slots(slots, |vm| allocate(vm))?;
+        Ok(slots.into_iter().next().unwrap_or(Value::Null))
+    }
+
+    /// Calls the foreign method with `signature` (as passed to
+    /// [`Context::register_fn()`][crate::Context::register_fn()]) in `module`, with `slots`.
+    ///
+    /// Slot 0 is the receiver: a foreign object for instance methods of foreign classes,
+    /// and whatever you like otherwise. The arguments are in slots 1 and up.
+    ///
+    /// Returns the value at slot 0 after the call, or the fiber error if the method
+    /// aborted the fiber.
+    ///
+    /// When `slots` is passed by value (e.g. as an array), the slots hold the only references
+    /// to objects that are not referenced elsewhere, so objects whose slot is overwritten
+    /// during the call are freed right away, like they could be by Wren's GC.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the method does not exist.
+    pub fn call(
+        &self,
+        module: &str,
+        signature: &str,
+        slots: impl Into<Vec<Value>>,
+    ) -> Result<Value, Value> {
+        let method = self
+            .host
+            .state
+            .dome
+            .method(module, signature)
+            .unwrap_or_else(|| {
+                panic!(
+                    "Method '{}' does not exist in module '{}'.",
+                    signature, module
+                )
+            });
+        self.import(module);
+        let slots = self
+            .host
+            .state
+            .call_with_slots(slots.into(), |vm| method(vm))?;
+        Ok(slots.into_iter().next().unwrap_or(Value::Null))
+    }
+
+    /// The number of handles that were not released yet.
+    #[inline]
+    pub fn live_handles(&self) -> usize {
+        self.host.state.vm.live_handles()
+    }
+}
+
+impl Deref for MockHost {
+    type Target = Host;
+
+    #[inline]
+    fn deref(&self) -> &Host {
+        &self.host
+    }
+}
+
+impl Default for MockHost {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for MockHost {
+    fn drop(&mut self) {
+        self.host.release();
+        self.host.state.vm.clear();
+        self.host.state.dome.clear();
+        wren::dealloc_freed();
+    }
+}
@@ -0,0 +1,762 @@
+use libc::{c_char, c_double, c_int, c_void, size_t};
+use std::alloc::{self, Layout};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::ffi::CStr;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+use std::slice;
+
+use super::{state, State};
+use crate::unsafe_wrappers::dome as unsafe_dome;
+use crate::unsafe_wrappers::wren as unsafe_wren;
+use crate::WrenType;
+
+/// A Wren value, as seen by the mock host.
+///
+/// Lists, maps, foreign objects and classes have reference semantics, just like in Wren:
+/// cloning a [`Value`] clones the reference, not the object.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Num(f64),
+    /// A Wren string. Wren strings are not required to be valid UTF-8.
+    String(Vec<u8>),
+    List(Rc<RefCell<Vec<Value>>>),
+    /// A Wren map. Entries are kept in insertion order.
+    Map(Rc<RefCell<Vec<(Value, Value)>>>),
+    Foreign(Rc<ForeignObject>),
+    Class(Rc<Class>),
+    /// An object of a type that isn't accessible by the C API.
+    Unknown,
+}
+
+impl Value {
+    /// Creates a new list holding `elements`.
+    #[inline]
+    pub fn list(elements: Vec<Value>) -> Self {
+        Value::List(Rc::new(RefCell::new(elements)))
+    }
+
+    /// Creates a new map holding `entries`.
+    #[inline]
+    pub fn map(entries: Vec<(Value, Value)>) -> Self {
+        Value::Map(Rc::new(RefCell::new(entries)))
+    }
+
+    /// Returns the Wren type of this value, as reported by `wrenGetSlotType()`.
+    #[inline]
+    pub fn wren_type(&self) -> WrenType {
+        match self {
+            Value::Null => WrenType::Null,
+            Value::Bool(_) => WrenType::Bool,
+            Value::Num(_) => WrenType::Num,
+            Value::String(_) => WrenType::String,
+            Value::List(_) => WrenType::List,
+            Value::Map(_) => WrenType::Map,
+            Value::Foreign(_) => WrenType::Foreign,
+            Value::Class(_) | Value::Unknown => WrenType::Unknown,
+        }
+    }
+
+    #[inline]
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    #[inline]
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_num(&self) -> Option<f64> {
+        match *self {
+            Value::Num(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::String(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Returns the string, or `None` if this is not a string or it is not valid UTF-8.
+    #[inline]
+    pub fn as_str(&self) -> Option<&str> {
+        std::str::from_utf8(self.as_bytes()?).ok()
+    }
+
+    /// Returns a copy of the list elements.
+    #[inline]
+    pub fn as_list(&self) -> Option<Vec<Value>> {
+        match self {
+            Value::List(list) => Some(list.borrow().clone()),
+            _ => None,
+        }
+    }
+
+    /// Returns a copy of the map entries.
+    #[inline]
+    pub fn as_map(&self) -> Option<Vec<(Value, Value)>> {
+        match self {
+            Value::Map(map) => Some(map.borrow().clone()),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_foreign(&self) -> Option<&Rc<ForeignObject>> {
+        match self {
+            Value::Foreign(foreign) => Some(foreign),
+            _ => None,
+        }
+    }
+}
+
+/// Values are compared like Wren's `==` does by default: by value for `null`, booleans,
+/// numbers and strings, and by identity for everything else.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Null, Value::Null) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Num(a), Value::Num(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+            (Value::Foreign(a), Value::Foreign(b)) => Rc::ptr_eq(a, b),
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl From<()> for Value {
+    #[inline]
+    fn from(_: ()) -> Self {
+        Value::Null
+    }
+}
+impl From<bool> for Value {
+    #[inline]
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+impl From<f64> for Value {
+    #[inline]
+    fn from(value: f64) -> Self {
+        Value::Num(value)
+    }
+}
+impl From<&str> for Value {
+    #[inline]
+    fn from(value: &str) -> Self {
+        Value::String(value.as_bytes().to_owned())
+    }
+}
+impl From<String> for Value {
+    #[inline]
+    fn from(value: String) -> Self {
+        Value::String(value.into_bytes())
+    }
+}
+impl From<&[u8]> for Value {
+    #[inline]
+    fn from(value: &[u8]) -> Self {
+        Value::String(value.to_owned())
+    }
+}
+impl From<Vec<Value>> for Value {
+    #[inline]
+    fn from(elements: Vec<Value>) -> Self {
+        Value::list(elements)
+    }
+}
+
+/// A foreign class registered through
+/// [`Context::register_class()`][crate::Context::register_class()].
+#[derive(Debug)]
+pub struct Class {
+    module: String,
+    name: String,
+    allocate: Option<unsafe_dome::ForeignFn>,
+    finalize: Option<unsafe_dome::FinalizerFn>,
+}
+
+impl Class {
+    pub(crate) fn new(
+        module: String,
+        name: String,
+        allocate: Option<unsafe_dome::ForeignFn>,
+        finalize: Option<unsafe_dome::FinalizerFn>,
+    ) -> Self {
+        Self {
+            module,
+            name,
+            allocate,
+            finalize,
+        }
+    }
+
+    #[inline]
+    pub fn module(&self) -> &str {
+        &self.module
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub(crate) fn allocate(&self) -> Option<unsafe_dome::ForeignFn> {
+        self.allocate
+    }
+
+    #[cfg(feature = "wren-host")]
+    #[inline]
+    pub(crate) fn finalize(&self) -> Option<unsafe_dome::FinalizerFn> {
+        self.finalize
+    }
+}
+
+/// The memory of a foreign object. The finalizer of its class runs when the last
+/// reference to it goes away.
+pub struct ForeignObject {
+    class: Rc<Class>,
+    block: *mut u8,
+    layout: Layout,
+}
+
+// Wren places the foreign data right after a 24-byte object header, so it is only
+// 8-aligned. We mimic that (instead of handing out nicely aligned memory) so that
+// plugins which depend on a stronger alignment break under the mock too.
+const FOREIGN_HEADER_SIZE: usize = 8;
+
+impl ForeignObject {
+    fn new(class: Rc<Class>, length: usize) -> Self {
+        let layout = Layout::from_size_align(FOREIGN_HEADER_SIZE + length, 16).unwrap();
+        // SAFETY: The layout is never zero-sized.
+        let block = unsafe { alloc::alloc_zeroed(layout) };
+        if block.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+        Self {
+            class,
+            block,
+            layout,
+        }
+    }
+
+    /// The class of this object.
+    #[inline]
+    pub fn class(&self) -> &Rc<Class> {
+        &self.class
+    }
+
+    /// A pointer to the foreign data, as returned by `wrenGetSlotForeign()`.
+    #[inline]
+    pub fn data(&self) -> *mut c_void {
+        // SAFETY: The block is always larger than the header.
+        unsafe { self.block.add(FOREIGN_HEADER_SIZE) as *mut c_void }
+    }
+}
+
+impl fmt::Debug for ForeignObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ForeignObject")
+            .field("class", &self.class.name)
+            .field("data", &self.data())
+            .finish()
+    }
+}
+
+impl Drop for ForeignObject {
+    fn drop(&mut self) {
+        if let Some(finalize) = self.class.finalize {
+            finalize(self.data());
+        }
+        // SAFETY: The block is as large as the layout.
+        unsafe { self.block.write_bytes(FREED_POISON, self.layout.size()) };
+        FREED.with(|freed| freed.borrow_mut().push((self.block, self.layout)));
+    }
+}
+
+// Freed foreign objects are poisoned and kept until the host is dropped, so that a plugin
+// using an object after it was freed reads garbage (which trips the borrow flag assertions)
+// instead of another object that reused the memory.
+const FREED_POISON: u8 = 0xdd;
+
+thread_local! {
+    static FREED: RefCell<Vec<(*mut u8, Layout)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Deallocates the foreign objects freed so far.
+pub(crate) fn dealloc_freed() {
+    for (block, layout) in FREED.with(|freed| freed.take()) {
+        // SAFETY: We allocated the block with this layout.
+        unsafe { alloc::dealloc(block, layout) }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct VmState {
+    slots: RefCell<Vec<Value>>,
+    error: RefCell<Option<Value>>,
+    handles: RefCell<HashSet<usize>>,
+    variables: RefCell<HashMap<(String, String), Value>>,
+}
+
+impl VmState {
+    /// Runs `callback` with a fresh slot array containing `slots`, like Wren does
+    /// for every foreign call, and returns the final slots and the fiber error, if any.
+    pub(crate) fn with_frame<R>(
+        &self,
+        slots: Vec<Value>,
+        callback: impl FnOnce() -> R,
+    ) -> (R, Vec<Value>, Option<Value>) {
+        let prev_slots = self.slots.replace(slots);
+        let prev_error = self.error.replace(None);
+        let result = callback();
+        let slots = self.slots.replace(prev_slots);
+        let error = self.error.replace(prev_error);
+        (result, slots, error)
+    }
+
+    pub(crate) fn set_variable(&self, module: &str, name: &str, value: Value) {
+        self.variables
+            .borrow_mut()
+            .insert((module.to_owned(), name.to_owned()), value);
+    }
+
+    #[inline]
+    pub(crate) fn live_handles(&self) -> usize {
+        self.handles.borrow().len()
+    }
+
+    pub(crate) fn clear(&self) {
+        // Drop outside of the borrows, since finalizers run arbitrary code.
+        let slots = self.slots.take();
+        let error = self.error.take();
+        let variables = self.variables.take();
+        drop((slots, error, variables));
+    }
+}
+
+#[inline]
+fn vm_state<'a>(vm: unsafe_wren::VM) -> &'a State {
+    // SAFETY: The mock host hands out pointers to its state as VMs.
+    unsafe { state(vm as *mut c_void) }
+}
+
+#[inline]
+fn index(slot: c_int) -> usize {
+    slot.try_into().expect("Negative slot.")
+}
+
+fn get(vm: unsafe_wren::VM, slot: c_int) -> Value {
+    let slots = vm_state(vm).vm.slots.borrow();
+    let slot = index(slot);
+    match slots.get(slot) {
+        Some(value) => value.clone(),
+        None => panic!(
+            "Slot out of bounds: the count is {} but the slot is {}.",
+            slots.len(),
+            slot
+        ),
+    }
+}
+
+fn set(vm: unsafe_wren::VM, slot: c_int, value: Value) {
+    let mut slots = vm_state(vm).vm.slots.borrow_mut();
+    let slot = index(slot);
+    let slots_count = slots.len();
+    let old = mem::replace(
+        slots.get_mut(slot).unwrap_or_else(|| {
+            panic!(
+                "Slot out of bounds: the count is {} but the slot is {}.",
+                slots_count, slot
+            )
+        }),
+        value,
+    );
+    drop(slots);
+    // The old value may be the last reference to a foreign object, so drop it only
+    // after we released the borrow.
+    drop(old);
+}
+
+fn list_index(index: c_int, count: usize, allow_end: bool) -> usize {
+    let count: c_int = count.try_into().unwrap();
+    let end = if allow_end { count + 1 } else { count };
+    let index = if index < 0 { end + index } else { index };
+    assert!(
+        (0..end).contains(&index),
+        "Index {} out of bounds - size of list is {}.",
+        index,
+        count
+    );
+    index as usize
+}
+
+fn get_list(vm: unsafe_wren::VM, slot: c_int) -> Rc<RefCell<Vec<Value>>> {
+    match get(vm, slot) {
+        Value::List(list) => list,
+        value => panic!("Slot {} must hold a list, got {:?}.", slot, value),
+    }
+}
+
+fn get_map(vm: unsafe_wren::VM, slot: c_int) -> Rc<RefCell<Vec<(Value, Value)>>> {
+    match get(vm, slot) {
+        Value::Map(map) => map,
+        value => panic!("Slot {} must hold a map, got {:?}.", slot, value),
+    }
+}
+
+fn get_key(vm: unsafe_wren::VM, slot: c_int) -> Value {
+    let key = get(vm, slot);
+    assert!(
+        matches!(
+            key,
+            Value::Null | Value::Bool(_) | Value::Num(_) | Value::String(_) | Value::Class(_)
+        ),
+        "Map keys must be hashable, got {:?}.",
+        key
+    );
+    key
+}
+
+extern "C" fn ensure_slots(vm: unsafe_wren::VM, slot_count: c_int) {
+    let mut slots = vm_state(vm).vm.slots.borrow_mut();
+    let slot_count = index(slot_count);
+    if slots.len() < slot_count {
+        slots.resize(slot_count, Value::Null);
+    }
+}
+
+unsafe extern "C" fn set_slot_null(vm: unsafe_wren::VM, slot: c_int) {
+    set(vm, slot, Value::Null)
+}
+unsafe extern "C" fn set_slot_bool(vm: unsafe_wren::VM, slot: c_int, value: bool) {
+    set(vm, slot, Value::Bool(value))
+}
+unsafe extern "C" fn set_slot_double(vm: unsafe_wren::VM, slot: c_int, value: c_double) {
+    set(vm, slot, Value::Num(value))
+}
+unsafe extern "C" fn set_slot_string(vm: unsafe_wren::VM, slot: c_int, text: *const c_char) {
+    set(
+        vm,
+        slot,
+        Value::String(CStr::from_ptr(text).to_bytes().to_owned()),
+    )
+}
+unsafe extern "C" fn set_slot_bytes(
+    vm: unsafe_wren::VM,
+    slot: c_int,
+    data: *const c_char,
+    length: size_t,
+) {
+    let bytes = if length == 0 {
+        Vec::new()
+    } else {
+        slice::from_raw_parts(data as *const u8, length).to_owned()
+    };
+    set(vm, slot, Value::String(bytes))
+}
+unsafe extern "C" fn set_slot_new_foreign(
+    vm: unsafe_wren::VM,
+    slot: c_int,
+    class_slot: c_int,
+    length: size_t,
+) -> *mut c_void {
+    let class = match get(vm, class_slot) {
+        Value::Class(class) => class,
+        value => panic!("Slot {} must hold a class, got {:?}.", class_slot, value),
+    };
+    let foreign = Rc::new(ForeignObject::new(class, length));
+    let data = foreign.data();
+    set(vm, slot, Value::Foreign(foreign));
+    data
+}
+unsafe extern "C" fn set_slot_new_list(vm: unsafe_wren::VM, slot: c_int) {
+    set(vm, slot, Value::list(Vec::new()))
+}
+unsafe extern "C" fn set_slot_new_map(vm: unsafe_wren::VM, slot: c_int) {
+    set(vm, slot, Value::map(Vec::new()))
+}
+
+extern "C" fn get_user_data(vm: unsafe_wren::VM) -> unsafe_dome::Context {
+    vm as unsafe_dome::Context
+}
+unsafe extern "C" fn get_slot_bool(vm: unsafe_wren::VM, slot: c_int) -> bool {
+    match get(vm, slot) {
+        Value::Bool(value) => value,
+        value => panic!("Slot {} must hold a bool, got {:?}.", slot, value),
+    }
+}
+unsafe extern "C" fn get_slot_double(vm: unsafe_wren::VM, slot: c_int) -> c_double {
+    match get(vm, slot) {
+        Value::Num(value) => value,
+        value => panic!("Slot {} must hold a num, got {:?}.", slot, value),
+    }
+}
+/// Returns a pointer into the slot's own buffer, which stays valid until the slot
+/// is modified - just like Wren.
+fn slot_bytes(vm: unsafe_wren::VM, slot: c_int) -> (*const c_char, usize) {
+    let mut slots = vm_state(vm).vm.slots.borrow_mut();
+    match slots.get_mut(index(slot)) {
+        Some(Value::String(bytes)) => {
+            bytes.reserve(1);
+            bytes.spare_capacity_mut()[0].write(0);
+            (bytes.as_ptr() as *const c_char, bytes.len())
+        }
+        value => panic!("Slot {} must hold a string, got {:?}.", slot, value),
+    }
+}
+unsafe extern "C" fn get_slot_string(vm: unsafe_wren::VM, slot: c_int) -> *const c_char {
+    slot_bytes(vm, slot).0
+}
+unsafe extern "C" fn get_slot_bytes(
+    vm: unsafe_wren::VM,
+    slot: c_int,
+    length: *mut c_int,
+) -> *const c_char {
+    let (data, data_length) = slot_bytes(vm, slot);
+    *length = data_length.try_into().unwrap();
+    data
+}
+unsafe extern "C" fn get_slot_foreign(vm: unsafe_wren::VM, slot: c_int) -> *mut c_void {
+    match get(vm, slot) {
+        Value::Foreign(foreign) => foreign.data(),
+        value => panic!("Slot {} must hold a foreign object, got {:?}.", slot, value),
+    }
+}
+
+unsafe extern "C" fn abort_fiber(vm: unsafe_wren::VM, slot: c_int) {
+    let error = get(vm, slot);
+    vm_state(vm).vm.error.replace(Some(error));
+}
+extern "C" fn get_slot_count(vm: unsafe_wren::VM) -> c_int {
+    vm_state(vm).vm.slots.borrow().len().try_into().unwrap()
+}
+unsafe extern "C" fn get_slot_type(vm: unsafe_wren::VM, slot: c_int) -> WrenType {
+    get(vm, slot).wren_type()
+}
+
+unsafe extern "C" fn get_list_count(vm: unsafe_wren::VM, slot: c_int) -> c_int {
+    get_list(vm, slot).borrow().len().try_into().unwrap()
+}
+unsafe extern "C" fn get_list_element(
+    vm: unsafe_wren::VM,
+    list_slot: c_int,
+    index: c_int,
+    element_slot: c_int,
+) {
+    let list = get_list(vm, list_slot);
+    let element = {
+        let list = list.borrow();
+        list[list_index(index, list.len(), false)].clone()
+    };
+    set(vm, element_slot, element)
+}
+unsafe extern "C" fn set_list_element(
+    vm: unsafe_wren::VM,
+    list_slot: c_int,
+    index: c_int,
+    element_slot: c_int,
+) {
+    let list = get_list(vm, list_slot);
+    let element = get(vm, element_slot);
+    let old = {
+        let mut list = list.borrow_mut();
+        let index = list_index(index, list.len(), false);
+        mem::replace(&mut list[index], element)
+    };
+    drop(old);
+}
+unsafe extern "C" fn insert_in_list(
+    vm: unsafe_wren::VM,
+    list_slot: c_int,
+    index: c_int,
+    element_slot: c_int,
+) {
+    let list = get_list(vm, list_slot);
+    let element = get(vm, element_slot);
+    let mut list = list.borrow_mut();
+    let index = list_index(index, list.len(), true);
+    list.insert(index, element);
+}
+
+unsafe extern "C" fn get_map_count(vm: unsafe_wren::VM, slot: c_int) -> c_int {
+    get_map(vm, slot).borrow().len().try_into().unwrap()
+}
+unsafe extern "C" fn get_map_contains_key(
+    vm: unsafe_wren::VM,
+    map_slot: c_int,
+    key_slot: c_int,
+) -> bool {
+    let map = get_map(vm, map_slot);
+    let key = get_key(vm, key_slot);
+    let contains = map.borrow().iter().any(|(k, _)| *k == key);
+    contains
+}
+unsafe extern "C" fn get_map_value(
+    vm: unsafe_wren::VM,
+    map_slot: c_int,
+    key_slot: c_int,
+    value_slot: c_int,
+) {
+    let map = get_map(vm, map_slot);
+    let key = get_key(vm, key_slot);
+    let value = map
+        .borrow()
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map_or(Value::Null, |(_, v)| v.clone());
+    set(vm, value_slot, value)
+}
+unsafe extern "C" fn set_map_value(
+    vm: unsafe_wren::VM,
+    map_slot: c_int,
+    key_slot: c_int,
+    value_slot: c_int,
+) {
+    let map = get_map(vm, map_slot);
+    let key = get_key(vm, key_slot);
+    let value = get(vm, value_slot);
+    let old = {
+        let mut map = map.borrow_mut();
+        match map.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => Some(mem::replace(v, value)),
+            None => {
+                map.push((key, value));
+                None
+            }
+        }
+    };
+    drop(old);
+}
+unsafe extern "C" fn remove_map_value(
+    vm: unsafe_wren::VM,
+    map_slot: c_int,
+    key_slot: c_int,
+    removed_value_slot: c_int,
+) {
+    let map = get_map(vm, map_slot);
+    let key = get_key(vm, key_slot);
+    let removed = {
+        let mut map = map.borrow_mut();
+        let position = map.iter().position(|(k, _)| *k == key);
+        position.map(|position| map.remove(position).1)
+    };
+    set(vm, removed_value_slot, removed.unwrap_or(Value::Null))
+}
+
+extern "C" fn get_variable(
+    vm: unsafe_wren::VM,
+    module: *const c_char,
+    name: *const c_char,
+    slot: c_int,
+) {
+    // SAFETY: The crate always passes valid C strings.
+    let (module, name) = unsafe {
+        (
+            CStr::from_ptr(module).to_string_lossy().into_owned(),
+            CStr::from_ptr(name).to_string_lossy().into_owned(),
+        )
+    };
+    let state = vm_state(vm);
+    let class = state.dome.class(&module, &name).map(Value::Class);
+    let value = class
+        .or_else(|| {
+            let variables = state.vm.variables.borrow();
+            variables.get(&(module.clone(), name.clone())).cloned()
+        })
+        .unwrap_or_else(|| panic!("Variable '{}' does not exist in module '{}'.", name, module));
+    set(vm, slot, value)
+}
+unsafe extern "C" fn get_slot_handle(vm: unsafe_wren::VM, slot: c_int) -> unsafe_wren::Handle {
+    let handle = Box::into_raw(Box::new(get(vm, slot)));
+    vm_state(vm).vm.handles.borrow_mut().insert(handle as usize);
+    handle as unsafe_wren::Handle
+}
+unsafe extern "C" fn set_slot_handle(
+    vm: unsafe_wren::VM,
+    slot: c_int,
+    handle: unsafe_wren::Handle,
+) {
+    assert!(
+        vm_state(vm)
+            .vm
+            .handles
+            .borrow()
+            .contains(&(handle as usize)),
+        "Use of a released handle."
+    );
+    let value = (*(handle as *const Value)).clone();
+    set(vm, slot, value)
+}
+extern "C" fn release_handle(vm: unsafe_wren::VM, handle: unsafe_wren::Handle) {
+    assert!(
+        vm_state(vm)
+            .vm
+            .handles
+            .borrow_mut()
+            .remove(&(handle as usize)),
+        "Handle released twice."
+    );
+    // SAFETY: We allocated the handle with `Box`, and it wasn't released yet.
+    drop(unsafe { Box::from_raw(handle as *mut Value) });
+}
+
+pub(crate) static API: unsafe_wren::ApiV0 = unsafe_wren::ApiV0 {
+    ensure_slots,
+
+    set_slot_null,
+    set_slot_bool,
+    set_slot_double,
+    set_slot_string,
+    set_slot_bytes,
+    set_slot_new_foreign,
+    set_slot_new_list,
+    set_slot_new_map,
+
+    get_user_data,
+    get_slot_bool,
+    get_slot_double,
+    get_slot_string,
+    get_slot_bytes,
+    get_slot_foreign,
+
+    abort_fiber,
+    get_slot_count,
+    get_slot_type,
+
+    get_list_count,
+    get_list_element,
+    set_list_element,
+    insert_in_list,
+
+    get_map_count,
+    get_map_contains_key,
+    get_map_value,
+    set_map_value,
+    remove_map_value,
+
+    get_variable,
+    get_slot_handle,
+    set_slot_handle,
+    release_handle,
+};
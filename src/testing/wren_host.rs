@@ -0,0 +1,405 @@
+use libc::{c_char, c_int};
+use std::cell::{Cell, RefCell};
+use std::ffi::{CStr, CString};
+use std::ops::Deref;
+use std::ptr;
+
+use super::{dome, ffi, state, Host, State};
+use crate::safe_wrappers::frame::foreign_call;
+use crate::unsafe_wrappers::dome as unsafe_dome;
+use crate::unsafe_wrappers::wren as unsafe_wren;
+use crate::{FromWren, WrenVM};
+
+/// The module [`WrenHost::run()`] runs code in.
+const MAIN_MODULE: &str = "main";
+/// The variable in the main module that holds the result of [`WrenHost::eval()`].
+const RESULT_VARIABLE: &str = "DomeCloomnikResult_";
+
+/// The state only a [`WrenHost`] has.
+pub(super) struct HostState {
+    /// The Wren VM, or null for a mock host.
+    vm: Cell<unsafe_wren::VM>,
+    /// What Wren code printed.
+    output: RefCell<String>,
+    /// The errors Wren reported during the current interpretation.
+    errors: RefCell<Vec<String>>,
+}
+
+impl HostState {
+    #[inline]
+    pub(super) fn vm(&self) -> Option<unsafe_wren::VM> {
+        Some(self.vm.get()).filter(|vm| !vm.is_null())
+    }
+}
+
+impl Default for HostState {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            vm: Cell::new(ptr::null_mut()),
+            output: RefCell::default(),
+            errors: RefCell::default(),
+        }
+    }
+}
+
+#[inline]
+fn vm_state<'a>(vm: unsafe_wren::VM) -> &'a State {
+    // SAFETY: The Wren host stores a pointer to its state as the user data of its VM.
+    unsafe { state(ffi::wrenGetUserData(vm)) }
+}
+
+extern "C" fn get_context(vm: unsafe_wren::VM) -> unsafe_dome::Context {
+    vm_state(vm).as_ptr() as unsafe_dome::Context
+}
+
+/// DOME's API, for a real Wren VM. Only the contexts differ from the mock host.
+static DOME_API: unsafe_dome::ApiV0 = unsafe_dome::ApiV0 {
+    get_context,
+    ..dome::API
+};
+
+#[inline]
+unsafe fn string(s: *const c_char) -> String {
+    CStr::from_ptr(s).to_string_lossy().into_owned()
+}
+
+extern "C" fn load_module(vm: unsafe_wren::VM, name: *const c_char) -> ffi::LoadModuleResult {
+    // SAFETY: Wren passes a valid C string.
+    let name = unsafe { string(name) };
+    // Wren reports modules that weren't registered.
+    let source = vm_state(vm)
+        .dome
+        .module_source(&name)
+        .and_then(|source| CString::new(source).ok());
+    ffi::LoadModuleResult {
+        source: source.map_or(ptr::null(), |source| source.into_raw() as *const c_char),
+        on_complete: Some(load_module_complete),
+        user_data: ptr::null_mut(),
+    }
+}
+
+extern "C" fn load_module_complete(
+    _vm: unsafe_wren::VM,
+    _name: *const c_char,
+    result: ffi::LoadModuleResult,
+) {
+    if !result.source.is_null() {
+        // SAFETY: `load_module()` allocated the source with `CString`.
+        drop(unsafe { CString::from_raw(result.source as *mut c_char) });
+    }
+}
+
+extern "C" fn bind_foreign_method(
+    vm: unsafe_wren::VM,
+    module: *const c_char,
+    class_name: *const c_char,
+    is_static: bool,
+    signature: *const c_char,
+) -> Option<unsafe_dome::ForeignFn> {
+    // SAFETY: Wren passes valid C strings.
+    let (module, class_name, signature) =
+        unsafe { (string(module), string(class_name), string(signature)) };
+    // DOME's signatures, as passed to `Context::register_fn()`.
+    let signature = format!(
+        "{}{}.{}",
+        if is_static { "static " } else { "" },
+        class_name,
+        signature
+    );
+    vm_state(vm).dome.method(&module, &signature)
+}
+
+extern "C" fn bind_foreign_class(
+    vm: unsafe_wren::VM,
+    module: *const c_char,
+    class_name: *const c_char,
+) -> ffi::ForeignClassMethods {
+    // SAFETY: Wren passes valid C strings.
+    let (module, class_name) = unsafe { (string(module), string(class_name)) };
+    let class = vm_state(vm).dome.class(&module, &class_name);
+    ffi::ForeignClassMethods {
+        allocate: class.as_ref().and_then(|class| class.allocate()),
+        finalize: class.as_ref().and_then(|class| class.finalize()),
+    }
+}
+
+extern "C" fn write(vm: unsafe_wren::VM, text: *const c_char) {
+    // SAFETY: Wren passes a valid C string.
+    let text = unsafe { CStr::from_ptr(text) }.to_string_lossy();
+    vm_state(vm).host.output.borrow_mut().push_str(&text);
+}
+
+extern "C" fn error(
+    vm: unsafe_wren::VM,
+    error_type: ffi::ErrorType,
+    module: *const c_char,
+    line: c_int,
+    message: *const c_char,
+) {
+    // SAFETY: Wren passes a valid C string as message, and as module for compile errors.
+    let message = unsafe { string(message) };
+    let error = match error_type {
+        ffi::ErrorType::Compile => {
+            format!("[{} line {}] {}", unsafe { string(module) }, line, message)
+        }
+        ffi::ErrorType::Runtime => message,
+        // Tests assert on the error itself, so the stack trace is only noise.
+        ffi::ErrorType::StackTrace => return,
+    };
+    vm_state(vm).host.errors.borrow_mut().push(error);
+}
+
+/// A DOME host that runs Wren code on a real Wren VM. Requires the `wren-host` feature,
+/// which compiles the Wren VM with the C compiler.
+///
+/// Like DOME, it loads the modules the plugin registers with
+/// [`Context::register_module()`][crate::Context::register_module()] when Wren code imports
+/// them, and binds the foreign methods and classes registered with
+/// [`Context::register_fn()`][crate::Context::register_fn()] and
+/// [`Context::register_class()`][crate::Context::register_class()]. This tests the Wren side
+/// of a plugin, e.g. the modules declared with [`register_modules!`][crate::register_modules!],
+/// together with the Rust side.
+///
+/// Code passed to [`run()`][Self::run()] and [`eval()`][Self::eval()] runs in the module
+/// `main`, so variables declared by one call are visible to the next.
+///
+/// The hooks, the registered modules and the audio engine are accessible through [`Host`],
+/// which the host dereferences to. Like [`MockHost`][super::MockHost], only one host can
+/// exist at a time, and handles must be dropped before the host.
+///
+/// # Example
+///
+/// ```
+/// use dome_cloomnik::testing::WrenHost;
+/// use dome_cloomnik::{register_modules, Context, HookResult, Hooks, WrenVM};
+///
+/// struct Counter(f64);
+/// impl Counter {
+///     fn new(vm: &WrenVM) -> Self {
+///         Counter(vm.get_slot_double(1))
+///     }
+///     fn increment(&mut self, step: f64) -> f64 {
+///         self.0 += step;
+///         self.0
+///     }
+/// }
+///
+/// fn on_init(mut ctx: Context) -> HookResult {
+///     (register_modules! {
+///         ctx,
+///         module "counter" {
+///             foreign class Counter = new of Counter {
+///                 "construct new(start) {}"
+///                 "increment() { increment(1) }"
+///                 foreign increment(step) = increment
+///             }
+///         }
+///     })?;
+///     Ok(())
+/// }
+///
+/// let host = WrenHost::new();
+/// assert!(host.init(Hooks {
+///     on_init: Some(on_init),
+///     ..Hooks::default()
+/// }));
+///
+/// host.run(r#"
+///     import "counter" for Counter
+///     var counter = Counter.new(40)
+///     counter.increment()
+///     System.print("Counted to %(counter.increment())")
+/// "#).unwrap();
+/// assert_eq!(host.output(), "Counted to 42\n");
+/// assert_eq!(host.eval::<f64>("counter.increment(0.5)"), Ok(42.5));
+///
+/// // Fiber errors are returned, with compile errors.
+/// assert_eq!(
+///     host.run("counter.increment(\"one\")"),
+///     Err("Invalid argument `step`. Argument 1 must be Num, got String.".to_owned()),
+/// );
+/// assert_eq!(
+///     host.run("counter.decrement()"),
+///     Err("Counter does not implement 'decrement()'.".to_owned()),
+/// );
+/// assert_eq!(
+///     host.run("var = 1"),
+///     Err("[main line 1] Error at '=': Expect variable name.\n\
+///          [main line 1] Error at '1': Expect end of file.".to_owned()),
+/// );
+/// ```
+pub struct WrenHost {
+    host: Host,
+}
+
+impl WrenHost {
+    /// Creates a new host with a new Wren VM. The plugin is not loaded until
+    /// [`Host::load()`] or [`Host::init()`] is called.
+    pub fn new() -> Self {
+        let host = Host::new(&DOME_API, &ffi::API);
+        let state = &host.state;
+        // SAFETY: `wrenInitConfiguration()` initializes the whole configuration. The state
+        // is boxed, so the pointer Wren keeps to it stays valid as long as the host.
+        unsafe {
+            let mut config = std::mem::MaybeUninit::uninit();
+            ffi::wrenInitConfiguration(config.as_mut_ptr());
+            let mut config: ffi::Configuration = config.assume_init();
+            config.load_module_fn = Some(load_module);
+            config.bind_foreign_method_fn = Some(bind_foreign_method);
+            config.bind_foreign_class_fn = Some(bind_foreign_class);
+            config.write_fn = Some(write);
+            config.error_fn = Some(error);
+            config.user_data = state.as_ptr();
+            state.host.vm.set(ffi::wrenNewVM(&mut config));
+        }
+        let host = Self { host };
+        host.run(&format!("var {} = null", RESULT_VARIABLE))
+            .expect("Failed to declare the result variable.");
+        host
+    }
+
+    #[inline]
+    fn vm(&self) -> unsafe_wren::VM {
+        self.host.state.host.vm.get()
+    }
+
+    /// Runs `source` in the module `main`.
+    ///
+    /// Returns the fiber error if the code aborted the fiber, or the compile errors (one
+    /// per line) if it does not compile.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `source` contains null bytes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use dome_cloomnik::testing::WrenHost;
+    /// use dome_cloomnik::WrenVM;
+    ///
+    /// extern "C" fn allocate(mut vm: WrenVM) {
+    ///     unsafe { vm.set_slot_new_raw_foreign_unchecked(0, 0, 0) };
+    /// }
+    /// extern "C" fn twice(mut vm: WrenVM) {
+    ///     match vm.try_get_slot_double(1) {
+    ///         Ok(value) => vm.set_slot_double(0, value * 2.0),
+    ///         Err(err) => vm.abort_fiber_with(err),
+    ///     }
+    /// }
+    ///
+    /// let host = WrenHost::new();
+    /// let mut ctx = host.context();
+    /// ctx.register_module("external", r"
+    ///     foreign class ExternalClass {
+    ///         construct new() {}
+    ///         foreign static twice(value)
+    ///     }
+    /// ")?;
+    /// unsafe {
+    ///     ctx.register_class("external", "ExternalClass", allocate, None)?;
+    ///     ctx.register_fn("external", "static ExternalClass.twice(_)", twice)?;
+    /// }
+    ///
+    /// host.run(r#"
+    ///     import "external" for ExternalClass
+    ///     var external = ExternalClass.new()
+    /// "#).unwrap();
+    /// assert_eq!(host.eval::<bool>("external is ExternalClass"), Ok(true));
+    /// assert_eq!(host.eval::<f64>("ExternalClass.twice(21)"), Ok(42.0));
+    /// assert_eq!(
+    ///     host.run("ExternalClass.twice(null)"),
+    ///     Err("Argument 1 must be Num, got Null.".to_owned()),
+    /// );
+    /// # Ok::<(), dome_cloomnik::Error>(())
+    /// ```
+    pub fn run(&self, source: &str) -> Result<(), String> {
+        let module = CString::new(MAIN_MODULE).unwrap();
+        let source = CString::new(source).expect("Source contains null byte(s).");
+        self.host.state.host.errors.borrow_mut().clear();
+        // SAFETY: The VM is valid, and Wren is not running.
+        let result = unsafe { ffi::wrenInterpret(self.vm(), module.as_ptr(), source.as_ptr()) };
+        match result {
+            ffi::InterpretResult::Success => Ok(()),
+            _ => Err(self.host.state.host.errors.take().join("\n")),
+        }
+    }
+
+    /// Evaluates the Wren `expression` in the module `main`, and converts the result
+    /// to `T`.
+    ///
+    /// Returns the fiber error if the evaluation aborted the fiber, or the compile errors
+    /// if it does not compile.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `expression` contains null bytes, or if the result cannot be converted
+    /// to `T`.
+    pub fn eval<T: FromWren>(&self, expression: &str) -> Result<T, String> {
+        self.run(&format!("{} = ({})", RESULT_VARIABLE, expression))?;
+        let result = self.with_vm(|vm| {
+            vm.ensure_slots(1);
+            // SAFETY: `new()` declared the variable.
+            unsafe { vm.get_variable(MAIN_MODULE, RESULT_VARIABLE, 0) };
+            vm.try_get::<T>(0)
+        });
+        // Do not keep the result alive.
+        self.run(&format!("{} = null", RESULT_VARIABLE))?;
+        Ok(result.unwrap_or_else(|err| panic!("{}", err)))
+    }
+
+    /// Runs `callback` with the [`WrenVM`], as if it was a foreign call without arguments.
+    ///
+    /// Use [`WrenVM::ensure_slots()`] to get slots, e.g. to read variables with
+    /// [`WrenVM::get_variable()`].
+    pub fn with_vm<R>(&self, callback: impl FnOnce(&mut WrenVM) -> R) -> R {
+        foreign_call(|| {
+            // SAFETY: The VM is valid as long as the host, and Wren is not running.
+            let mut vm = unsafe { WrenVM::from_raw(self.vm()) };
+            callback(&mut vm)
+        })
+    }
+
+    /// Runs a full garbage collection, so that the finalizers of unreachable foreign
+    /// objects run.
+    pub fn collect_garbage(&self) {
+        // Interpreting resets the slots of `with_vm()`, which would keep their values alive.
+        let _ = self.run("");
+        // SAFETY: The VM is valid, and Wren is not running.
+        unsafe { ffi::wrenCollectGarbage(self.vm()) }
+    }
+
+    /// Everything Wren code printed with `System.print()` and `System.write()`.
+    #[inline]
+    pub fn output(&self) -> String {
+        self.host.state.host.output.borrow().clone()
+    }
+}
+
+impl Deref for WrenHost {
+    type Target = Host;
+
+    #[inline]
+    fn deref(&self) -> &Host {
+        &self.host
+    }
+}
+
+impl Default for WrenHost {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for WrenHost {
+    fn drop(&mut self) {
+        self.host.release();
+        // SAFETY: All handles were released, and the VM is not used after this. Freeing it
+        // runs the finalizers of the remaining foreign objects, which the plugin registered.
+        unsafe { ffi::wrenFreeVM(self.vm()) };
+        self.host.state.host.vm.set(ptr::null_mut());
+        self.host.state.dome.clear();
+    }
+}
@@ -16,8 +16,8 @@ pub(crate) type Engine = *mut FakeEngine;
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub(crate) struct ChannelRef {
-    id: ChannelId,
-    engine: Engine,
+    pub(crate) id: ChannelId,
+    pub(crate) engine: Engine,
 }
 
 /// The state of a channel.
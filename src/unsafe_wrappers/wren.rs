@@ -1,4 +1,5 @@
 use libc::{c_char, c_double, c_int, c_void, size_t};
+use std::fmt;
 
 use super::dome;
 
@@ -20,7 +21,7 @@ pub(crate) type ForeignMethodFn = extern "C" fn(VM);
 pub(crate) type FinalizerFn = extern "C" fn(*mut c_void);
 
 /// A Wren type.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub enum Type {
     Bool,
@@ -35,6 +36,13 @@ pub enum Type {
     Unknown,
 }
 
+impl fmt::Display for Type {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub(crate) struct ApiV0 {
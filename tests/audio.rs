@@ -0,0 +1,59 @@
+//! Renders the synthesizer of `examples/audio` through the mock host.
+
+#[path = "../examples/audio/main.rs"]
+mod audio;
+
+use dome_cloomnik::testing::{MockHost, Value, SAMPLE_RATE};
+
+/// Renders `seconds` of audio in chunks of 10ms, like DOME's audio thread pulls them.
+fn render(host: &MockHost, seconds: f64) -> Vec<[f32; 2]> {
+    let chunk = SAMPLE_RATE as usize / 100;
+    let chunks = (seconds * 100.0).round() as usize;
+    (0..chunks)
+        .flat_map(|_| {
+            host.update_audio();
+            host.render_audio(chunk)
+        })
+        .collect()
+}
+
+#[test]
+fn play_tone_renders_a_saw_wave_for_its_duration() {
+    let host = MockHost::new();
+    assert!(host.load(audio::PLUGIN_onInit));
+    let synth = host.construct("synth", "SynthClass_", &[]).unwrap();
+    assert_eq!(host.channel_count(), 1);
+
+    // The channel plays from the start, but is silent until a tone is played.
+    assert!(render(&host, 0.1).iter().all(|&frame| frame == [0.0, 0.0]));
+
+    // Keep our own reference: the synth (and its channel) is freed once nothing refers to it.
+    let args = [synth.clone(), Value::Num(350.0), Value::Num(1000.0)];
+    host.call("synth", "SynthClass_.playTone(_,_)", args)
+        .unwrap();
+    let frames = render(&host, 1.2);
+    let (tone, rest) = frames.split_at(SAMPLE_RATE as usize);
+
+    assert!(tone.iter().all(|&[left, right]| left == right));
+    // A saw from -pi/2 to 2 - pi/2, at half volume.
+    let (min, max) = (
+        -std::f32::consts::FRAC_PI_4,
+        1.0 - std::f32::consts::FRAC_PI_4,
+    );
+    assert!(tone
+        .iter()
+        .all(|&[sample, _]| min - 1e-3 <= sample && sample <= max + 1e-3));
+
+    // Each period ends with a drop back to the bottom of the saw. Skip the attack, where
+    // the drops are smaller.
+    let drops = tone[SAMPLE_RATE as usize / 10..]
+        .windows(2)
+        .filter(|frames| frames[1][0] - frames[0][0] < -0.5)
+        .count();
+    assert!((313..=317).contains(&drops), "{} periods in 0.9s", drops);
+
+    // The tone stops once its second is over, at the end of the chunk.
+    assert!(rest[SAMPLE_RATE as usize / 100..]
+        .iter()
+        .all(|&frame| frame == [0.0, 0.0]));
+}
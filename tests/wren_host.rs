@@ -0,0 +1,324 @@
+//! Runs plugins on a real Wren VM, to test that the Wren side of the classes and modules
+//! the macros declare matches the Rust side.
+
+use dome_cloomnik::testing::WrenHost;
+use dome_cloomnik::{
+    dome_class, register_classes, register_modules, ClassHandle, Context, ForeignRef, HookResult,
+    Hooks, Result, Upcast, WrenVM,
+};
+
+fn init(host: &WrenHost, on_init: fn(Context) -> HookResult) {
+    assert!(host.init(Hooks {
+        on_init: Some(on_init),
+        ..Hooks::default()
+    }));
+}
+
+struct Vec2(f64, f64);
+impl Vec2 {
+    fn new(vm: &WrenVM) -> Self {
+        Vec2(vm.get_slot_double(1), vm.get_slot_double(2))
+    }
+    fn origin(vm: &mut WrenVM) -> Result {
+        vm.try_new_foreign(0, Vec2(0.0, 0.0))?;
+        Ok(())
+    }
+    fn add(&self, vm: &mut WrenVM, other: ForeignRef<Vec2>) {
+        vm.new_foreign(0, Vec2(self.0 + other.0, self.1 + other.1));
+    }
+    fn x(&self) -> f64 {
+        self.0
+    }
+    fn y(&self) -> f64 {
+        self.1
+    }
+}
+
+fn register_vector(mut ctx: Context) -> HookResult {
+    (register_modules! {
+        ctx,
+        module "vector" {
+            foreign class Vec2 = new of Vec2 {
+                "construct new(x, y) {}"
+                foreign static origin() = origin
+                foreign add(other) = add
+                foreign x = x
+                foreign y = y
+                "toString { \"(%(x), %(y))\" }"
+            }
+        }
+    })?;
+    Ok(())
+}
+
+#[test]
+fn register_modules_declares_the_module_to_wren() {
+    let host = WrenHost::new();
+    init(&host, register_vector);
+
+    host.run(
+        r#"
+        import "vector" for Vec2
+        var a = Vec2.new(1, 2)
+        System.print(a.add(Vec2.new(3, 4)))
+        "#,
+    )
+    .unwrap();
+    assert_eq!(host.output(), "(4, 6)\n");
+    assert_eq!(
+        host.run("a.add(1)"),
+        Err("Invalid argument `other`. Argument 1 must be Foreign, got Num.".to_owned()),
+    );
+}
+
+#[test]
+fn foreign_methods_create_instances_once_the_module_is_imported() {
+    let host = WrenHost::new();
+    init(&host, register_vector);
+
+    // DOME does not tell plugins about imports, so Rust cannot load the class yet.
+    let error = "The foreign class of Rust type `Vec2` is in module 'vector', \
+                 which is not imported yet.";
+    let result = host.with_vm(|vm| {
+        vm.ensure_slots(1);
+        vm.try_new_foreign(0, Vec2(0.0, 0.0)).map(|_| ())
+    });
+    assert_eq!(result.unwrap_err().to_string(), error);
+    let result = host.with_vm(|vm| ClassHandle::try_of::<Vec2>(vm).map(|_| ()));
+    assert_eq!(result.unwrap_err().to_string(), error);
+
+    // Calling a foreign method of the module shows that it is imported.
+    host.run(r#"import "vector" for Vec2"#).unwrap();
+    assert_eq!(host.eval::<f64>("Vec2.origin().x"), Ok(0.0));
+    assert_eq!(host.eval::<bool>("Vec2.origin() is Vec2"), Ok(true));
+
+    let name = host.with_vm(|vm| ClassHandle::of::<Vec2>(vm).name());
+    assert_eq!(name, "Vec2");
+    let y = host.with_vm(|vm| {
+        vm.ensure_slots(1);
+        vm.new_foreign(0, Vec2(5.0, 6.0));
+        unsafe { vm.get_slot_foreign::<Vec2>(0) }.1
+    });
+    assert_eq!(y, 6.0);
+}
+
+struct Counter {
+    value: f64,
+    step: f64,
+}
+
+#[dome_class(module = "counter")]
+impl Counter {
+    #[foreign(construct)]
+    fn new(start: f64) -> Self {
+        Counter {
+            value: start,
+            step: 1.0,
+        }
+    }
+
+    #[foreign]
+    fn increment(&mut self) -> f64 {
+        self.value += self.step;
+        self.value
+    }
+
+    #[foreign]
+    fn fork(&self) -> Counter {
+        Counter {
+            value: self.value,
+            step: self.step,
+        }
+    }
+
+    #[foreign]
+    fn add(&mut self, other: &Counter) -> f64 {
+        self.value += other.value;
+        self.value
+    }
+
+    #[foreign(setter)]
+    fn step(&mut self, step: f64) {
+        self.step = step;
+    }
+}
+
+struct Text;
+
+#[dome_class(module = "counter", source = "static answer { 42 }")]
+impl Text {
+    #[foreign]
+    fn repeat(text: &str, count: usize) -> String {
+        text.repeat(count)
+    }
+}
+
+#[test]
+fn dome_class_declares_the_class_to_wren() {
+    let host = WrenHost::new();
+    init(&host, |mut ctx| {
+        register_classes!(ctx, Counter, Text)?;
+        Ok(())
+    });
+
+    host.run(
+        r#"
+        import "counter" for Counter, Text
+        var counter = Counter.new(1)
+        counter.step = 10
+        var fork = counter.fork()
+        "#,
+    )
+    .unwrap();
+    assert_eq!(host.eval::<f64>("counter.increment()"), Ok(11.0));
+    assert_eq!(host.eval::<f64>("fork.increment()"), Ok(11.0));
+    assert_eq!(host.eval::<bool>("fork is Counter"), Ok(true));
+    assert_eq!(host.eval::<f64>("counter.add(fork)"), Ok(22.0));
+    assert_eq!(
+        host.run("counter.add(counter)"),
+        Err("Invalid argument `other`. Argument 1 is already borrowed.".to_owned()),
+    );
+    assert_eq!(
+        host.run("Counter.new(\"one\")"),
+        Err("Invalid argument `start`. Argument 1 must be Num, got String.".to_owned()),
+    );
+
+    assert_eq!(
+        host.eval::<String>("Text.repeat(\"ab\", 2)"),
+        Ok("abab".to_owned())
+    );
+    assert_eq!(host.eval::<f64>("Text.answer"), Ok(42.0));
+}
+
+struct Point(f64);
+
+#[dome_class(module = "points")]
+impl Point {
+    #[foreign(construct)]
+    fn new(x: f64) -> Self {
+        Point(x)
+    }
+
+    #[foreign]
+    fn origin() -> Self {
+        Point(0.0)
+    }
+
+    #[foreign]
+    fn x(&self) -> f64 {
+        self.0
+    }
+
+    #[foreign]
+    fn label(&self) -> Label {
+        Label(self.0.to_string())
+    }
+}
+
+struct Label(String);
+
+// Never registered.
+#[dome_class(module = "labels")]
+impl Label {
+    #[foreign(construct)]
+    fn new(text: String) -> Self {
+        Label(text)
+    }
+
+    #[foreign]
+    fn text(&self) -> String {
+        self.0.clone()
+    }
+}
+
+#[test]
+fn dome_class_returns_new_instances_from_foreign_methods() {
+    let host = WrenHost::new();
+    init(&host, |mut ctx| {
+        register_classes!(ctx, Point)?;
+        Ok(())
+    });
+
+    // Static factories work before Wren allocated any instance.
+    host.run(r#"import "points" for Point"#).unwrap();
+    assert_eq!(host.eval::<f64>("Point.origin().x()"), Ok(0.0));
+    assert_eq!(host.eval::<bool>("Point.origin() is Point"), Ok(true));
+
+    // Failing to create the instance aborts the fiber with the reason.
+    assert_eq!(
+        host.run("Point.new(1).label()"),
+        Err("Rust type `wren_host::Label` is not registered as a foreign class.".to_owned()),
+    );
+}
+
+struct Shape {
+    name: String,
+}
+impl Shape {
+    fn new(vm: &WrenVM) -> Self {
+        Shape {
+            name: vm.get_slot_string(1).unwrap(),
+        }
+    }
+    fn describe(shape: ForeignRef<Shape>) -> String {
+        format!("a shape named {}", shape.name)
+    }
+}
+
+struct Circle {
+    shape: Shape,
+}
+impl Circle {
+    fn new(_vm: &WrenVM) -> Self {
+        Circle {
+            shape: Shape {
+                name: "circle".to_owned(),
+            },
+        }
+    }
+}
+impl Upcast<Shape> for Circle {
+    fn upcast(&self) -> &Shape {
+        &self.shape
+    }
+    fn upcast_mut(&mut self) -> &mut Shape {
+        &mut self.shape
+    }
+}
+
+#[test]
+fn upcasts_let_wren_pass_derived_objects_as_bases() {
+    let host = WrenHost::new();
+    init(&host, |mut ctx| {
+        (register_modules! {
+            ctx,
+            module "shapes" {
+                foreign class Shape = new of Shape {
+                    "construct new(name) {}"
+                    foreign static describe(shape) = describe
+                }
+                foreign class Circle = new of Circle {
+                    "construct new() {}"
+                }
+            }
+        })?;
+        ctx.register_upcast::<Circle, Shape>();
+        Ok(())
+    });
+
+    host.run(
+        r#"
+        import "shapes" for Shape, Circle
+        var circle = Circle.new()
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        host.eval::<String>("Shape.describe(circle)"),
+        Ok("a shape named circle".to_owned()),
+    );
+    assert_eq!(
+        host.run("Shape.describe(1)"),
+        Err("Invalid argument `shape`. Argument 1 must be Foreign, got Num.".to_owned()),
+    );
+}